@@ -0,0 +1,12 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE subscriptions ADD COLUMN preferences_token TEXT NULL;
+    UPDATE subscriptions
+        SET preferences_token = md5(random()::text || id::text)
+        WHERE preferences_token IS NULL;
+    ALTER TABLE subscriptions ALTER COLUMN preferences_token SET NOT NULL;
+    ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_preferences_token_key
+        UNIQUE (preferences_token);
+    ALTER TABLE subscriptions
+        ADD COLUMN content_format TEXT NOT NULL DEFAULT 'html';
+COMMIT;
@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE email_change_requests(
+    confirmation_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    new_email TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (confirmation_token)
+);
@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE subscriber_audit_log(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    action TEXT NOT NULL,
+    old_value TEXT NULL,
+    new_value TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX subscriber_audit_log_subscriber_id_idx
+    ON subscriber_audit_log (subscriber_id, occurred_at);
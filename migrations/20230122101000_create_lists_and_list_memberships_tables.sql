@@ -0,0 +1,29 @@
+-- Add migration script here
+BEGIN;
+    CREATE TABLE lists(
+        id uuid NOT NULL,
+        PRIMARY KEY (id),
+        slug TEXT NOT NULL UNIQUE,
+        name TEXT NOT NULL,
+        created_at timestamptz NOT NULL
+    );
+    CREATE TABLE list_memberships(
+        subscriber_id uuid NOT NULL
+            REFERENCES subscriptions (id),
+        list_id uuid NOT NULL
+            REFERENCES lists (id),
+        status TEXT NOT NULL,
+        created_at timestamptz NOT NULL,
+        PRIMARY KEY (subscriber_id, list_id)
+    );
+    CREATE INDEX list_memberships_list_id_status_idx
+        ON list_memberships (list_id, status);
+
+    -- everybody who subscribed before lists existed ends up in the default one
+    INSERT INTO lists (id, slug, name, created_at)
+        VALUES (md5(random()::text)::uuid, 'newsletter', 'Newsletter', now());
+    INSERT INTO list_memberships (subscriber_id, list_id, status, created_at)
+        SELECT s.id, l.id, s.status, s.subscribed_at
+        FROM subscriptions s, lists l
+        WHERE l.slug = 'newsletter';
+COMMIT;
@@ -0,0 +1,13 @@
+-- Add migration script here
+BEGIN;
+    CREATE TABLE subscriber_tags(
+        subscriber_id uuid NOT NULL
+            REFERENCES subscriptions (id),
+        tag TEXT NOT NULL,
+        created_at timestamptz NOT NULL,
+        PRIMARY KEY (subscriber_id, tag)
+    );
+    CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+    ALTER TABLE subscriptions
+        ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}'::jsonb;
+COMMIT;
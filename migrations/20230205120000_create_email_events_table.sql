@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE email_events(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    -- NULL when the address doesn't belong to any subscriber
+    subscriber_id uuid NULL
+        REFERENCES subscriptions (id),
+    email TEXT NOT NULL,
+    record_type TEXT NOT NULL,
+    event_type TEXT NULL,
+    payload JSONB NOT NULL,
+    received_at timestamptz NOT NULL
+);
+CREATE INDEX email_events_subscriber_id_idx
+    ON email_events (subscriber_id, received_at);
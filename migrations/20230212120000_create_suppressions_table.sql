@@ -0,0 +1,9 @@
+-- Add migration script here
+-- addresses are stored lowercased, so a lookup is a plain equality check
+CREATE TABLE suppressions(
+    email TEXT NOT NULL,
+    PRIMARY KEY (email),
+    reason TEXT NOT NULL,
+    source TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
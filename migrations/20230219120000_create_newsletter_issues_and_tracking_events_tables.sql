@@ -0,0 +1,31 @@
+-- Add migration script here
+BEGIN;
+    CREATE TABLE newsletter_issues(
+        id uuid NOT NULL,
+        PRIMARY KEY (id),
+        list_id uuid NOT NULL
+            REFERENCES lists (id),
+        -- the sources, with their merge fields, as submitted by the editor
+        title TEXT NOT NULL,
+        text_content TEXT NOT NULL,
+        html_content TEXT NOT NULL,
+        tracking_enabled BOOLEAN NOT NULL,
+        published_at timestamptz NOT NULL
+    );
+    CREATE TABLE tracking_events(
+        id uuid NOT NULL,
+        PRIMARY KEY (id),
+        issue_id uuid NOT NULL
+            REFERENCES newsletter_issues (id),
+        subscriber_id uuid NOT NULL
+            REFERENCES subscriptions (id),
+        kind TEXT NOT NULL,
+        -- the destination of a click, NULL for opens
+        url TEXT NULL,
+        occurred_at timestamptz NOT NULL
+    );
+    CREATE INDEX tracking_events_issue_id_kind_idx
+        ON tracking_events (issue_id, kind);
+    ALTER TABLE subscriptions
+        ADD COLUMN tracking_enabled BOOLEAN NOT NULL DEFAULT true;
+COMMIT;
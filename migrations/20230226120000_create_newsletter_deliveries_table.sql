@@ -0,0 +1,27 @@
+-- Add migration script here
+BEGIN;
+    CREATE TABLE newsletter_deliveries(
+        issue_id uuid NOT NULL
+            REFERENCES newsletter_issues (id),
+        subscriber_id uuid NOT NULL
+            REFERENCES subscriptions (id),
+        status TEXT NOT NULL,
+        attempted_at timestamptz NOT NULL,
+        PRIMARY KEY (issue_id, subscriber_id)
+    );
+    -- the stats of an issue are counted from indexes only
+    CREATE INDEX newsletter_deliveries_issue_id_status_idx
+        ON newsletter_deliveries (issue_id, status);
+    -- finds the latest issue a subscriber received, to attribute unsubscribes
+    CREATE INDEX newsletter_deliveries_subscriber_id_attempted_at_idx
+        ON newsletter_deliveries (subscriber_id, attempted_at);
+    DROP INDEX tracking_events_issue_id_kind_idx;
+    CREATE INDEX tracking_events_issue_id_kind_subscriber_id_idx
+        ON tracking_events (issue_id, kind, subscriber_id);
+    -- bounces and complaints carry the issue they answer to in their metadata
+    ALTER TABLE email_events ADD COLUMN issue_id uuid NULL;
+    CREATE INDEX email_events_issue_id_idx
+        ON email_events (issue_id, record_type);
+    CREATE INDEX subscriptions_subscribed_at_idx
+        ON subscriptions (subscribed_at);
+COMMIT;
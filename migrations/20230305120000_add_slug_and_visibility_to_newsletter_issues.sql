@@ -0,0 +1,11 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL;
+    -- issues published before the archive existed are reachable through their id
+    UPDATE newsletter_issues SET slug = id::text;
+    ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
+    ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_slug_key UNIQUE (slug);
+    ALTER TABLE newsletter_issues ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public';
+    CREATE INDEX newsletter_issues_visibility_published_at_idx
+        ON newsletter_issues (visibility, published_at);
+COMMIT;
@@ -0,0 +1,8 @@
+-- Add migration script here
+BEGIN;
+    -- bumped by every change to an issue, it validates the cached copies of the feeds
+    ALTER TABLE newsletter_issues ADD COLUMN updated_at timestamptz NULL;
+    UPDATE newsletter_issues SET updated_at = published_at;
+    ALTER TABLE newsletter_issues ALTER COLUMN updated_at SET NOT NULL;
+    CREATE INDEX newsletter_issues_updated_at_idx ON newsletter_issues (updated_at);
+COMMIT;
@@ -0,0 +1,26 @@
+-- Add migration script here
+BEGIN;
+    -- an issue sent with candidate subjects to a sample of its list,
+    -- the remainder gets the winning subject once the test is decided
+    CREATE TABLE subject_tests(
+        issue_id uuid NOT NULL REFERENCES newsletter_issues (id),
+        PRIMARY KEY (issue_id),
+        metric TEXT NOT NULL,
+        decide_at timestamptz NOT NULL,
+        winner INT NULL,
+        decided_at timestamptz NULL
+    );
+    CREATE INDEX subject_tests_undecided_idx ON subject_tests (decide_at) WHERE decided_at IS NULL;
+    CREATE TABLE subject_variants(
+        issue_id uuid NOT NULL REFERENCES subject_tests (issue_id),
+        variant INT NOT NULL,
+        subject TEXT NOT NULL,
+        PRIMARY KEY (issue_id, variant)
+    );
+    -- the subject variant a subscriber received, NULL outside of subject tests
+    ALTER TABLE newsletter_deliveries ADD COLUMN variant INT NULL;
+    CREATE INDEX newsletter_deliveries_pending_idx
+        ON newsletter_deliveries (issue_id) WHERE status = 'pending';
+    -- deliveries sent after publication need to know how the issue was rendered
+    ALTER TABLE newsletter_issues ADD COLUMN use_layout BOOLEAN NOT NULL DEFAULT false;
+COMMIT;
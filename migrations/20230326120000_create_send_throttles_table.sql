@@ -0,0 +1,20 @@
+-- Add migration script here
+BEGIN;
+    -- spreads the deliveries of an issue over time, they are queued and sent by the worker
+    CREATE TABLE send_throttles(
+        issue_id uuid NOT NULL REFERENCES newsletter_issues (id),
+        PRIMARY KEY (issue_id),
+        max_per_hour INT NULL,
+        -- local times of day in `timezone`, an IANA name such as `Europe/Berlin`
+        window_start TIME NULL,
+        window_end TIME NULL,
+        timezone TEXT NULL,
+        CHECK (
+            (window_start IS NULL AND window_end IS NULL AND timezone IS NULL)
+            OR (window_start IS NOT NULL AND window_end IS NOT NULL AND timezone IS NOT NULL)
+        )
+    );
+    -- counts the deliveries of the last hour against the cap
+    CREATE INDEX newsletter_deliveries_issue_id_attempted_at_idx
+        ON newsletter_deliveries (issue_id, attempted_at);
+COMMIT;
@@ -0,0 +1,18 @@
+-- Add migration script here
+BEGIN;
+    -- editors may pause, resume or cancel the deliveries of an issue still being sent
+    ALTER TABLE newsletter_issues ADD COLUMN delivery_state TEXT NOT NULL DEFAULT 'active';
+    -- who changed the delivery state of an issue, and why
+    CREATE TABLE issue_audit_log(
+        id uuid NOT NULL,
+        PRIMARY KEY (id),
+        issue_id uuid NOT NULL
+            REFERENCES newsletter_issues (id),
+        action TEXT NOT NULL,
+        actor TEXT NOT NULL,
+        reason TEXT NULL,
+        occurred_at timestamptz NOT NULL
+    );
+    CREATE INDEX issue_audit_log_issue_id_idx
+        ON issue_audit_log (issue_id, occurred_at);
+COMMIT;
@@ -0,0 +1,15 @@
+-- Add migration script here
+BEGIN;
+    -- rate limiting state shared by every instance of the application
+    CREATE TABLE rate_limit_counters(
+        key TEXT NOT NULL,
+        PRIMARY KEY (key),
+        -- the start of the window for fixed windows, the theoretical arrival time for GCRA
+        at timestamptz NOT NULL,
+        count BIGINT NOT NULL,
+        -- the counter no longer limits anything past this point
+        expires_at timestamptz NOT NULL
+    );
+    CREATE INDEX rate_limit_counters_expires_at_idx
+        ON rate_limit_counters (expires_at);
+COMMIT;
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- domains admins allow despite the disposable blocklist, or deny outright
+CREATE TABLE email_domain_rules(
+    domain TEXT NOT NULL,
+    PRIMARY KEY (domain),
+    rule TEXT NOT NULL,
+    updated_at timestamptz NOT NULL
+);
@@ -0,0 +1,37 @@
+-- Add migration script here
+BEGIN;
+    -- `email` is the normalized address we send to, `email_display` the address as it was typed
+    ALTER TABLE subscriptions ADD COLUMN email_display TEXT NULL;
+    UPDATE subscriptions SET email_display = btrim(email);
+    ALTER TABLE subscriptions ALTER COLUMN email_display SET NOT NULL;
+    -- addresses differing only by case are the same subscriber,
+    -- existing duplicates must be merged by hand before the migration can run
+    DO $$
+    DECLARE
+        duplicates TEXT;
+    BEGIN
+        SELECT string_agg(addresses, '; ') INTO duplicates
+        FROM (
+            SELECT string_agg(email, ', ' ORDER BY subscribed_at) AS addresses
+            FROM subscriptions
+            GROUP BY lower(btrim(email))
+            HAVING COUNT(*) > 1
+        ) d;
+        IF duplicates IS NOT NULL THEN
+            RAISE EXCEPTION 'Subscribers share an email address once case is ignored, merge them first: %',
+                duplicates;
+        END IF;
+    END $$;
+    ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+    -- the same normalization as the application, but for IDNA which only applies to new addresses
+    UPDATE subscriptions
+    SET email = substring(btrim(email) from '^(.*)@') || '@' || lower(substring(btrim(email) from '@([^@]*)$'))
+    WHERE btrim(email) LIKE '%@%';
+    -- always `lower(email)`, set by the application along with `email`
+    ALTER TABLE subscriptions ADD COLUMN normalized_email TEXT NULL;
+    UPDATE subscriptions SET normalized_email = lower(email);
+    ALTER TABLE subscriptions ALTER COLUMN normalized_email SET NOT NULL;
+    ALTER TABLE subscriptions
+        ADD CONSTRAINT subscriptions_normalized_email_key UNIQUE (normalized_email);
+    ALTER TABLE email_change_requests ADD COLUMN new_email_display TEXT NULL;
+COMMIT;
@@ -0,0 +1,8 @@
+-- Add migration script here
+BEGIN;
+    -- the address with the Unicode form of its domain, `email` has the ASCII (IDNA) one.
+    -- Addresses stored before keep their ASCII domain until they change.
+    ALTER TABLE subscriptions ADD COLUMN email_unicode TEXT NULL;
+    UPDATE subscriptions SET email_unicode = email;
+    ALTER TABLE subscriptions ALTER COLUMN email_unicode SET NOT NULL;
+COMMIT;
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- the language of the emails and pages a subscriber gets,
+-- subscribers who joined before it was captured get the default locale
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NULL;
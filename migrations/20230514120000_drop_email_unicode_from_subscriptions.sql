@@ -0,0 +1,3 @@
+-- Add migration script here
+-- never read, `email_display` keeps the internationalized form of the address
+ALTER TABLE subscriptions DROP COLUMN email_unicode;
@@ -0,0 +1,8 @@
+-- Add migration script here
+BEGIN;
+    -- the address with the Unicode form of its domain is stored next to the ASCII one again,
+    -- addresses stored while the column was gone keep their ASCII domain until they change.
+    ALTER TABLE subscriptions ADD COLUMN email_unicode TEXT NULL;
+    UPDATE subscriptions SET email_unicode = email;
+    ALTER TABLE subscriptions ALTER COLUMN email_unicode SET NOT NULL;
+COMMIT;
@@ -0,0 +1,32 @@
+use std::future::Future;
+use std::pin::Pin;
+
+pub type Verification<'a> = Pin<Box<dyn Future<Output = Result<bool, anyhow::Error>> + Send + 'a>>;
+
+/// Checks the answer to a challenge embedded in the subscription form, e.g. a captcha.
+///
+/// Implementations usually call the verification endpoint of their provider.
+/// An error means the answer couldn't be checked, not that it is wrong.
+pub trait ChallengeVerifier: Send + Sync {
+    fn verify<'a>(&'a self, response: &'a str) -> Verification<'a>;
+}
+
+/// Accepts a single answer, known in advance.
+/// Stands in for a real provider in tests and during local development.
+pub struct FakeChallengeVerifier {
+    expected_response: String,
+}
+
+impl FakeChallengeVerifier {
+    pub fn new(expected_response: impl Into<String>) -> Self {
+        Self {
+            expected_response: expected_response.into(),
+        }
+    }
+}
+
+impl ChallengeVerifier for FakeChallengeVerifier {
+    fn verify<'a>(&'a self, response: &'a str) -> Verification<'a> {
+        Box::pin(async move { Ok(response == self.expected_response) })
+    }
+}
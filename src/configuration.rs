@@ -1,7 +1,8 @@
 use secrecy::{Secret, ExposeSecret};
 use sqlx::{postgres::PgConnectOptions, ConnectOptions};
 use serde_aux::field_attributes::deserialize_number_from_string;
-use crate::domain::SubscriberEmail;
+use crate::domain::{EmailPolicy, Locale, SubscriberEmail};
+use crate::email_client::EmailClient;
 
 #[derive(Clone)]
 #[derive(serde::Deserialize)]
@@ -9,6 +10,13 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub webhooks: WebhookSettings,
+    pub admin: AdminSettings,
+    pub api: ApiSettings,
+    pub bot_protection: BotProtectionSettings,
+    pub rate_limits: RateLimitSettings,
+    pub email_policy: EmailPolicySettings,
+    pub localization: LocalizationSettings,
 }
 
 #[derive(Clone)]
@@ -28,7 +36,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
     }
 
@@ -45,7 +53,9 @@ pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
-    pub base_url: String
+    pub base_url: String,
+    // signs the tracking links embedded in newsletter issues and the subscription form
+    pub hmac_secret: Secret<String>,
 }
 
 #[derive(Clone)]
@@ -54,10 +64,124 @@ pub struct EmailClientSettings {
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
-    pub timeout_milliseconds: u64
+    pub timeout_milliseconds: u64,
+    // Postmark can't deliver to UTF-8 local parts, transports supporting SMTPUTF8 can
+    pub smtputf8: bool,
+}
+
+// credentials Postmark must present, through basic auth, when calling our webhooks
+#[derive(Clone)]
+#[derive(serde::Deserialize)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+// editors allowed to pause, resume and cancel deliveries, through basic auth,
+// their username goes to the audit log of the issue
+#[derive(Clone)]
+#[derive(serde::Deserialize)]
+pub struct AdminSettings {
+    pub users: Vec<AdminCredentials>,
+}
+
+#[derive(Clone)]
+#[derive(serde::Deserialize)]
+pub struct AdminCredentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+// keys of the applications allowed to call the JSON API, sent as bearer tokens
+#[derive(Clone)]
+#[derive(serde::Deserialize)]
+pub struct ApiSettings {
+    pub keys: Vec<Secret<String>>,
+}
+
+// submissions of the subscription form faster or older than this are dropped
+#[derive(Clone)]
+#[derive(serde::Deserialize)]
+pub struct BotProtectionSettings {
+    pub min_fill_seconds: i64,
+    pub max_form_age_hours: i64,
+}
+
+#[derive(Clone)]
+#[derive(serde::Deserialize)]
+pub struct EmailPolicySettings {
+    // a blocklist of disposable email domains, none are rejected when missing
+    pub disposable_domains_path: Option<String>,
+    pub reject_role_accounts: bool,
+}
+
+// readers get this locale when neither they nor their browser asked for a supported one
+#[derive(Clone)]
+#[derive(serde::Deserialize)]
+pub struct LocalizationSettings {
+    pub default_locale: String,
+}
+
+impl LocalizationSettings {
+    pub fn default_locale(&self) -> Result<Locale, String> {
+        Locale::parse(&self.default_locale)
+    }
+}
+
+#[derive(Clone)]
+#[derive(serde::Deserialize)]
+pub struct RateLimitSettings {
+    pub backend: RateLimitBackend,
+    // the X-Forwarded-For header is only trusted when set by one of these proxies
+    pub trusted_proxies: Vec<std::net::IpAddr>,
+    pub rules: Vec<RateLimitRuleSettings>,
+}
+
+// `memory` counts on each instance on its own, `postgres` shares the counts between instances
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitBackend {
+    Memory,
+    Postgres,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitAlgorithm {
+    FixedWindow,
+    Gcra,
+}
+
+// at most `limit` requests to `path` every `period_seconds`, for each client ip,
+// or for each value of `form_field` when it is set
+#[derive(Clone)]
+#[derive(serde::Deserialize)]
+pub struct RateLimitRuleSettings {
+    pub path: String,
+    // any method when missing
+    pub method: Option<String>,
+    pub algorithm: RateLimitAlgorithm,
+    pub limit: u32,
+    pub period_seconds: u32,
+    // a field of url encoded forms or json bodies
+    pub form_field: Option<String>,
 }
 
 impl EmailClientSettings {
+    pub fn client(self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        let timeout = self.timeout();
+        EmailClient::new(
+            self.base_url,
+            sender_email,
+            self.authorization_token,
+            timeout,
+            self.smtputf8,
+        )
+    }
+
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
@@ -67,7 +191,16 @@ impl EmailClientSettings {
     }
 }
 
-
+impl EmailPolicySettings {
+    // the blocklist is read once, replacing the file takes effect on the next start
+    pub fn policy(&self) -> Result<EmailPolicy, std::io::Error> {
+        let disposable_domains = match &self.disposable_domains_path {
+            Some(path) => EmailPolicy::parse_blocklist(&std::fs::read_to_string(path)?),
+            None => Default::default(),
+        };
+        Ok(EmailPolicy::new(disposable_domains, self.reject_role_accounts))
+    }
+}
 
 // get a settings struct populated using config files
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
@@ -0,0 +1,51 @@
+/// The kind of email a subscriber wants to receive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentFormat {
+    Html,
+    Text,
+}
+
+impl ContentFormat {
+    pub fn parse(s: &str) -> Result<ContentFormat, String> {
+        match s {
+            "html" => Ok(Self::Html),
+            "text" => Ok(Self::Text),
+            other => Err(format!(
+                "{} is not a supported content format. Use either `html` or `text`.",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentFormat::Html => "html",
+            ContentFormat::Text => "text",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContentFormat;
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn supported_formats_are_parsed_successfully() {
+        assert_ok_eq!(ContentFormat::parse("html"), ContentFormat::Html);
+        assert_ok_eq!(ContentFormat::parse("text"), ContentFormat::Text);
+    }
+
+    #[test]
+    fn unknown_formats_are_rejected() {
+        assert_err!(ContentFormat::parse("pdf"));
+        assert_err!(ContentFormat::parse(""));
+    }
+
+    #[test]
+    fn parsing_round_trips_through_as_str() {
+        for format in [ContentFormat::Html, ContentFormat::Text] {
+            assert_ok_eq!(ContentFormat::parse(format.as_str()), format);
+        }
+    }
+}
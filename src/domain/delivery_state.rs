@@ -0,0 +1,50 @@
+/// Whether the deliveries of an issue still go out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryState {
+    Active,
+    // queued deliveries are held until the issue is resumed
+    Paused,
+    // queued deliveries are discarded, for good
+    Cancelled,
+}
+
+impl DeliveryState {
+    pub fn parse(s: &str) -> Result<DeliveryState, String> {
+        match s {
+            "active" => Ok(Self::Active),
+            "paused" => Ok(Self::Paused),
+            "cancelled" => Ok(Self::Cancelled),
+            other => Err(format!("{} is not a known delivery state.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryState::Active => "active",
+            DeliveryState::Paused => "paused",
+            DeliveryState::Cancelled => "cancelled",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryState;
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn parsing_round_trips_through_as_str() {
+        for state in [
+            DeliveryState::Active,
+            DeliveryState::Paused,
+            DeliveryState::Cancelled,
+        ] {
+            assert_ok_eq!(DeliveryState::parse(state.as_str()), state);
+        }
+    }
+
+    #[test]
+    fn unknown_states_are_rejected() {
+        assert_err!(DeliveryState::parse("stopped"));
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use crate::domain::SubscriberEmail;
+
+// local parts reaching a role rather than a person, they shouldn't receive newsletters
+const ROLE_ACCOUNTS: &[&str] = &[
+    "abuse",
+    "admin",
+    "hostmaster",
+    "mailer-daemon",
+    "no-reply",
+    "noc",
+    "noreply",
+    "postmaster",
+    "root",
+    "security",
+    "webmaster",
+];
+
+/// What admins decided about the addresses of a domain and its subdomains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomainRule {
+    // accepted even when the domain is known to be disposable
+    Allow,
+    Deny,
+}
+
+impl DomainRule {
+    pub fn parse(s: &str) -> Result<DomainRule, String> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "deny" => Ok(Self::Deny),
+            other => Err(format!(
+                "{} is not a supported domain rule. Use either `allow` or `deny`.",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DomainRule::Allow => "allow",
+            DomainRule::Deny => "deny",
+        }
+    }
+}
+
+/// The addresses we accept subscriptions from, on top of their syntax.
+#[derive(Debug, Default)]
+pub struct EmailPolicy {
+    disposable_domains: HashSet<String>,
+    reject_role_accounts: bool,
+}
+
+impl EmailPolicy {
+    pub fn new(disposable_domains: HashSet<String>, reject_role_accounts: bool) -> Self {
+        Self {
+            disposable_domains,
+            reject_role_accounts,
+        }
+    }
+
+    /// The domains of a blocklist file: one per line, `#` starts a comment.
+    pub fn parse_blocklist(contents: &str) -> HashSet<String> {
+        contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim().to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect()
+    }
+
+    /// Why `email` is refused, `None` when it is accepted.
+    /// `rule` is the rule admins set for its domain, if any.
+    pub fn check(&self, email: &SubscriberEmail, rule: Option<DomainRule>) -> Option<String> {
+        let (local_part, domain) = split_email(email.as_ref());
+        if rule == Some(DomainRule::Deny) {
+            return Some(format!("Email addresses at {} are not accepted.", domain));
+        }
+        if self.reject_role_accounts {
+            // tags don't change who reads the mailbox, e.g. `postmaster+news`
+            let mailbox = local_part.split('+').next().unwrap_or(local_part);
+            if ROLE_ACCOUNTS.contains(&mailbox.to_lowercase().as_str()) {
+                return Some(format!(
+                    "{}@ addresses belong to a role rather than a person, please use a personal address.",
+                    mailbox
+                ));
+            }
+        }
+        if rule != Some(DomainRule::Allow)
+            && parent_domains(&domain).any(|d| self.disposable_domains.contains(d))
+        {
+            return Some(
+                "Disposable email addresses are not accepted, please use a permanent address."
+                    .into(),
+            );
+        }
+        None
+    }
+}
+
+/// The domain of an address and every domain it belongs to, from the most specific one,
+/// e.g. `mail.example.com` then `example.com` then `com`.
+pub fn parent_domains(domain: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(domain), |d| d.split_once('.').map(|(_, parent)| parent))
+}
+
+/// The local part and the lowercased domain of a valid address.
+pub fn split_email(email: &str) -> (&str, String) {
+    let (local_part, domain) = email.rsplit_once('@').unwrap_or((email, ""));
+    (local_part, domain.trim().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DomainRule, EmailPolicy};
+    use crate::domain::SubscriberEmail;
+    use claim::{assert_none, assert_some};
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.into()).unwrap()
+    }
+
+    fn policy() -> EmailPolicy {
+        EmailPolicy::new(
+            EmailPolicy::parse_blocklist("# disposable\nmailinator.com\n\nYopmail.com # reused\n"),
+            true,
+        )
+    }
+
+    #[test]
+    fn domain_rules_round_trip() {
+        for rule in [DomainRule::Allow, DomainRule::Deny] {
+            assert_eq!(DomainRule::parse(rule.as_str()), Ok(rule));
+        }
+        assert!(DomainRule::parse("block").is_err());
+    }
+
+    #[test]
+    fn disposable_domains_and_their_subdomains_are_rejected() {
+        let policy = policy();
+
+        assert_some!(policy.check(&email("ursula@mailinator.com"), None));
+        assert_some!(policy.check(&email("ursula@eu.YOPMAIL.com"), None));
+        assert_none!(policy.check(&email("ursula@gmail.com"), None));
+    }
+
+    #[test]
+    fn allowed_domains_are_not_disposable() {
+        let rejection = policy().check(&email("ursula@mailinator.com"), Some(DomainRule::Allow));
+
+        assert_none!(rejection);
+    }
+
+    #[test]
+    fn denied_domains_are_rejected() {
+        let rejection = policy().check(&email("ursula@gmail.com"), Some(DomainRule::Deny));
+
+        assert_eq!(
+            rejection.as_deref(),
+            Some("Email addresses at gmail.com are not accepted.")
+        );
+    }
+
+    #[test]
+    fn role_accounts_are_rejected_when_the_policy_says_so() {
+        for address in ["postmaster@example.com", "Abuse@example.com", "noreply+news@example.com"] {
+            assert_some!(policy().check(&email(address), None), "{} was accepted", address);
+            assert_none!(EmailPolicy::default().check(&email(address), None));
+        }
+    }
+}
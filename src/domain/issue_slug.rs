@@ -0,0 +1,103 @@
+const MAX_LENGTH: usize = 64;
+
+/// The identifier of an issue in its archive URL, e.g. `/archive/our-first-issue`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssueSlug(String);
+
+impl IssueSlug {
+    pub fn parse(s: String) -> Result<IssueSlug, String> {
+        let is_empty = s.is_empty();
+        let is_too_long = s.len() > MAX_LENGTH;
+        let has_invalid_characters = !s
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        let has_dangling_dash = s.starts_with('-') || s.ends_with('-');
+
+        if is_empty || is_too_long || has_invalid_characters || has_dangling_dash {
+            Err(format!("{} is not a valid issue slug.", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+
+    /// Derive a slug from the title of an issue,
+    /// keeping its ASCII letters and digits and joining the words with dashes.
+    pub fn from_title(title: &str) -> IssueSlug {
+        let mut slug = String::new();
+        for word in title
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|w| !w.is_empty())
+        {
+            if slug.len() + word.len() + 1 > MAX_LENGTH {
+                break;
+            }
+            if !slug.is_empty() {
+                slug.push('-');
+            }
+            slug.push_str(&word.to_ascii_lowercase());
+        }
+        if slug.is_empty() {
+            slug.push_str("issue");
+        }
+        Self(slug)
+    }
+
+    /// The same slug with a numeric suffix, to tell apart issues sharing a title.
+    pub fn with_suffix(&self, n: u32) -> IssueSlug {
+        let suffix = format!("-{}", n);
+        let mut base = self.0[..self.0.len().min(MAX_LENGTH - suffix.len())].to_string();
+        while base.ends_with('-') {
+            base.pop();
+        }
+        Self(base + &suffix)
+    }
+}
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for IssueSlug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IssueSlug;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn a_valid_slug_is_parsed_successfully() {
+        assert_ok!(IssueSlug::parse("our-first-issue-2".to_string()));
+    }
+
+    #[test]
+    fn invalid_slugs_are_rejected() {
+        for slug in &["", "Issue", "our first issue", "-issue", "issue-", "ïssue"] {
+            assert_err!(IssueSlug::parse(slug.to_string()));
+        }
+        assert_err!(IssueSlug::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn a_slug_is_derived_from_the_title() {
+        let slug = IssueSlug::from_title("Hello, {{ name }}! What's new in 2023?");
+        assert_eq!(slug.as_ref(), "hello-name-what-s-new-in-2023");
+    }
+
+    #[test]
+    fn a_title_without_ascii_words_gets_a_placeholder_slug() {
+        assert_eq!(IssueSlug::from_title("¡¿ …").as_ref(), "issue");
+    }
+
+    #[test]
+    fn derived_slugs_are_valid_and_short_enough() {
+        let slug = IssueSlug::from_title(&"word ".repeat(50));
+        assert_ok!(IssueSlug::parse(slug.to_string()));
+        assert_ok!(IssueSlug::parse(slug.with_suffix(12).to_string()));
+    }
+}
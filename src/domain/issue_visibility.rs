@@ -0,0 +1,53 @@
+/// Who can read a published issue in the web archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueVisibility {
+    Public,
+    // only confirmed subscribers of the issue's list, identified by their preferences token
+    SubscribersOnly,
+    Hidden,
+}
+
+impl IssueVisibility {
+    pub fn parse(s: &str) -> Result<IssueVisibility, String> {
+        match s {
+            "public" => Ok(Self::Public),
+            "subscribers-only" => Ok(Self::SubscribersOnly),
+            "hidden" => Ok(Self::Hidden),
+            other => Err(format!(
+                "{} is not a supported visibility. Use `public`, `subscribers-only` or `hidden`.",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueVisibility::Public => "public",
+            IssueVisibility::SubscribersOnly => "subscribers-only",
+            IssueVisibility::Hidden => "hidden",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IssueVisibility;
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn parsing_round_trips_through_as_str() {
+        for visibility in [
+            IssueVisibility::Public,
+            IssueVisibility::SubscribersOnly,
+            IssueVisibility::Hidden,
+        ] {
+            assert_ok_eq!(IssueVisibility::parse(visibility.as_str()), visibility);
+        }
+    }
+
+    #[test]
+    fn unknown_visibilities_are_rejected() {
+        assert_err!(IssueVisibility::parse("private"));
+        assert_err!(IssueVisibility::parse(""));
+    }
+}
@@ -0,0 +1,93 @@
+/// The short identifier of a mailing list, e.g. `weekly-digest`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(s: String) -> Result<ListSlug, String> {
+        let is_empty = s.is_empty();
+        let is_too_long = s.len() > 64;
+        let has_invalid_characters = !s
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        let has_dangling_dash = s.starts_with('-') || s.ends_with('-');
+
+        if is_empty || is_too_long || has_invalid_characters || has_dangling_dash {
+            Err(format!("{} is not a valid list slug.", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+
+    /// Parse a comma separated list of slugs, as submitted by the subscribe form.
+    pub fn parse_many(s: &str) -> Result<Vec<ListSlug>, String> {
+        let mut slugs: Vec<ListSlug> = Vec::new();
+        for slug in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let slug = ListSlug::parse(slug.to_string())?;
+            if !slugs.contains(&slug) {
+                slugs.push(slug);
+            }
+        }
+        if slugs.is_empty() {
+            return Err("At least one list must be selected.".into());
+        }
+        Ok(slugs)
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for ListSlug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ListSlug;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn a_valid_slug_is_parsed_successfully() {
+        assert_ok!(ListSlug::parse("weekly-digest-2".to_string()));
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(ListSlug::parse("".to_string()));
+    }
+
+    #[test]
+    fn a_slug_longer_than_64_characters_is_rejected() {
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn slugs_with_invalid_characters_are_rejected() {
+        for slug in &["Weekly", "weekly digest", "weekly_digest", "weekly;--", "ünï"] {
+            assert_err!(ListSlug::parse(slug.to_string()));
+        }
+    }
+
+    #[test]
+    fn slugs_starting_or_ending_with_a_dash_are_rejected() {
+        assert_err!(ListSlug::parse("-weekly".to_string()));
+        assert_err!(ListSlug::parse("weekly-".to_string()));
+    }
+
+    #[test]
+    fn many_slugs_are_split_trimmed_and_deduplicated() {
+        let slugs = ListSlug::parse_many(" weekly, product ,weekly,").unwrap();
+        let slugs: Vec<&str> = slugs.iter().map(|s| s.as_ref()).collect();
+        assert_eq!(slugs, vec!["weekly", "product"]);
+    }
+
+    #[test]
+    fn an_empty_selection_is_rejected() {
+        assert_err!(ListSlug::parse_many(" , "));
+    }
+}
@@ -0,0 +1,95 @@
+use std::cmp::Ordering;
+
+/// A language our emails and pages are translated to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    En,
+    Fr,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Self::En, Self::Fr];
+
+    /// Accepts any BCP 47 tag of a supported language, e.g. `fr-CA` is `fr`.
+    pub fn parse(s: &str) -> Result<Locale, String> {
+        let language = s.trim().split(['-', '_']).next().unwrap_or("");
+        Self::ALL
+            .into_iter()
+            .find(|locale| language.eq_ignore_ascii_case(locale.as_str()))
+            .ok_or_else(|| {
+                format!(
+                    "{} is not a supported locale. Use either `en` or `fr`.",
+                    s
+                )
+            })
+    }
+
+    /// The supported locale the reader prefers according to an `Accept-Language` header,
+    /// `None` when they accept none of them.
+    pub fn from_accept_language(header: &str) -> Option<Locale> {
+        let mut ranges: Vec<(&str, f32)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let tag = parts.next().filter(|tag| !tag.is_empty())?;
+                let quality = parts
+                    .find_map(|p| p.strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse().ok())?;
+                Some((tag, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        // a stable sort keeps the order of the header between equal qualities,
+        // NaN qualities are filtered out above
+        ranges.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        ranges.into_iter().find_map(|(tag, _)| Self::parse(tag).ok())
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Fr => "fr",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Locale;
+    use claim::{assert_err, assert_none, assert_ok_eq, assert_some_eq};
+
+    #[test]
+    fn the_language_of_a_tag_is_used() {
+        assert_ok_eq!(Locale::parse("fr"), Locale::Fr);
+        assert_ok_eq!(Locale::parse("fr-CA"), Locale::Fr);
+        assert_ok_eq!(Locale::parse(" EN_gb "), Locale::En);
+    }
+
+    #[test]
+    fn unsupported_languages_are_rejected() {
+        assert_err!(Locale::parse("de"));
+        assert_err!(Locale::parse("french"));
+        assert_err!(Locale::parse(""));
+    }
+
+    #[test]
+    fn parsing_round_trips_through_as_str() {
+        for locale in Locale::ALL {
+            assert_ok_eq!(Locale::parse(locale.as_str()), locale);
+        }
+    }
+
+    #[test]
+    fn the_preferred_supported_language_is_picked() {
+        assert_some_eq!(Locale::from_accept_language("de-CH, fr;q=0.8, en;q=0.9"), Locale::En);
+        assert_some_eq!(Locale::from_accept_language("fr-BE,fr;q=0.9,en;q=0.8"), Locale::Fr);
+        assert_some_eq!(Locale::from_accept_language("*;q=0.5, fr"), Locale::Fr);
+    }
+
+    #[test]
+    fn refused_and_unsupported_languages_are_ignored() {
+        assert_none!(Locale::from_accept_language("fr;q=0, de"));
+        assert_none!(Locale::from_accept_language("en;q=abc"));
+        assert_none!(Locale::from_accept_language(""));
+    }
+}
@@ -1,7 +1,27 @@
+mod content_format;
+mod delivery_state;
+mod email_policy;
+mod issue_slug;
+mod issue_visibility;
+mod list_slug;
+mod locale;
 mod new_subscriber;
+mod newsletter_template;
+mod segment;
 mod subscriber_name;
 mod subscriber_email;
+mod subscriber_tag;
 
+pub use content_format::ContentFormat;
+pub use delivery_state::DeliveryState;
+pub use email_policy::{parent_domains, split_email, DomainRule, EmailPolicy};
+pub use issue_slug::IssueSlug;
+pub use issue_visibility::IssueVisibility;
+pub use list_slug::ListSlug;
+pub use locale::Locale;
 pub use subscriber_name::SubscriberName;
 pub use new_subscriber::NewSubscriber;
-pub use subscriber_email::SubscriberEmail;
\ No newline at end of file
+pub use newsletter_template::{MergeField, MergeValues, NewsletterTemplate};
+pub use segment::{is_valid_attribute_key, Segment};
+pub use subscriber_email::SubscriberEmail;
+pub use subscriber_tag::SubscriberTag;
@@ -1,8 +1,11 @@
 use crate::domain::SubscriberName;
 use crate::domain::SubscriberEmail;
+use crate::domain::Locale;
 
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    // the language of the emails and pages they get
+    pub locale: Locale,
 }
\ No newline at end of file
@@ -0,0 +1,164 @@
+use crate::templating::escape_html;
+
+/// The merge fields editors can use in a newsletter issue,
+/// e.g. `Hello {{ name }}!`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeField {
+    Name,
+    UnsubscribeUrl,
+    PreferencesUrl,
+}
+
+impl MergeField {
+    pub const ALL: [MergeField; 3] = [Self::Name, Self::UnsubscribeUrl, Self::PreferencesUrl];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::UnsubscribeUrl => "unsubscribe_url",
+            Self::PreferencesUrl => "preferences_url",
+        }
+    }
+
+    fn parse(s: &str) -> Result<MergeField, String> {
+        match s {
+            "name" => Ok(Self::Name),
+            "unsubscribe_url" => Ok(Self::UnsubscribeUrl),
+            "preferences_url" => Ok(Self::PreferencesUrl),
+            other => Err(format!(
+                "`{{{{ {} }}}}` is not a supported merge field. \
+                Use `name`, `unsubscribe_url` or `preferences_url`.",
+                other
+            )),
+        }
+    }
+}
+
+/// The values substituted for a single recipient.
+pub struct MergeValues<'a> {
+    pub name: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub preferences_url: &'a str,
+}
+
+impl MergeValues<'_> {
+    fn get(&self, field: MergeField) -> &str {
+        match field {
+            MergeField::Name => self.name,
+            MergeField::UnsubscribeUrl => self.unsubscribe_url,
+            MergeField::PreferencesUrl => self.preferences_url,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(MergeField),
+}
+
+/// A piece of newsletter content with its merge fields already located,
+/// so that an unknown field is caught once at publish time
+/// rather than sent raw to every recipient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewsletterTemplate(Vec<Part>);
+
+impl NewsletterTemplate {
+    pub fn parse(s: &str) -> Result<NewsletterTemplate, String> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let after_open = &rest[start + 2..];
+            let end = after_open
+                .find("}}")
+                .ok_or_else(|| "A merge field is missing its closing `}}`.".to_string())?;
+            parts.push(Part::Field(MergeField::parse(after_open[..end].trim())?));
+            rest = &after_open[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(Self(parts))
+    }
+
+    /// Substitute merge fields as they are, for plain text and subject lines.
+    pub fn render_text(&self, values: &MergeValues) -> String {
+        self.render(values, |v| v.to_string())
+    }
+
+    /// Substitute merge fields escaping them, so that values can't inject markup.
+    pub fn render_html(&self, values: &MergeValues) -> String {
+        self.render(values, escape_html)
+    }
+
+    fn render(&self, values: &MergeValues, escape: impl Fn(&str) -> String) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                Part::Literal(literal) => literal.clone(),
+                Part::Field(field) => escape(values.get(*field)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MergeValues, NewsletterTemplate};
+    use claim::{assert_err, assert_ok};
+
+    fn values() -> MergeValues<'static> {
+        MergeValues {
+            name: "Ursula <Le Guin> & co",
+            unsubscribe_url: "https://example.com/preferences/unsubscribe?token=a&b",
+            preferences_url: "https://example.com/preferences?token=abc",
+        }
+    }
+
+    #[test]
+    fn content_without_merge_fields_is_left_untouched() {
+        let template = NewsletterTemplate::parse("<p>Hello world</p>").unwrap();
+        assert_eq!(template.render_html(&values()), "<p>Hello world</p>");
+    }
+
+    #[test]
+    fn merge_fields_are_substituted_with_or_without_spaces() {
+        let template =
+            NewsletterTemplate::parse("Hi {{ name }}, manage: {{preferences_url}}").unwrap();
+        assert_eq!(
+            template.render_text(&values()),
+            "Hi Ursula <Le Guin> & co, manage: https://example.com/preferences?token=abc"
+        );
+    }
+
+    #[test]
+    fn html_substitutions_are_escaped() {
+        let template =
+            NewsletterTemplate::parse(r#"<p>Hi {{ name }}</p><a href="{{ unsubscribe_url }}">"#)
+                .unwrap();
+        assert_eq!(
+            template.render_html(&values()),
+            "<p>Hi Ursula &lt;Le Guin&gt; &amp; co</p>\
+            <a href=\"https://example.com/preferences/unsubscribe?token=a&amp;b\">"
+        );
+    }
+
+    #[test]
+    fn unknown_merge_fields_are_rejected() {
+        assert_err!(NewsletterTemplate::parse("Hi {{ first_name }}"));
+        assert_err!(NewsletterTemplate::parse("Hi {{}}"));
+    }
+
+    #[test]
+    fn unterminated_merge_fields_are_rejected() {
+        assert_err!(NewsletterTemplate::parse("Hi {{ name"));
+    }
+
+    #[test]
+    fn single_braces_are_not_merge_fields() {
+        assert_ok!(NewsletterTemplate::parse("body { color: red; }"));
+    }
+}
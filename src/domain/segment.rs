@@ -0,0 +1,414 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::{Postgres, QueryBuilder};
+use crate::domain::SubscriberTag;
+
+const MAX_EXPRESSION_LENGTH: usize = 1024;
+const MAX_NESTING_DEPTH: usize = 16;
+
+/// A filter selecting a subset of the subscribers of a list.
+///
+/// Segments are written as predicates combined with `AND`, `OR`, `NOT` and parentheses:
+///
+/// * `tag:beta` - subscribers tagged `beta`
+/// * `joined_after:2023-01-31` - subscribers who joined after that day (UTC)
+/// * `joined_before:2023-01-31` - subscribers who joined before that day (UTC)
+/// * `attr.plan:pro` or `attr.plan:"pro plus"` - subscribers whose `plan` attribute is `pro`
+///
+/// e.g. `tag:beta OR (joined_after:2023-01-01 AND NOT attr.country:fr)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Tag(SubscriberTag),
+    JoinedAfter(NaiveDate),
+    JoinedBefore(NaiveDate),
+    Attribute { key: String, value: String },
+    Not(Box<Segment>),
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+}
+
+impl Segment {
+    pub fn parse(s: &str) -> Result<Segment, String> {
+        if s.len() > MAX_EXPRESSION_LENGTH {
+            return Err(format!(
+                "The segment expression can't be longer than {} characters.",
+                MAX_EXPRESSION_LENGTH
+            ));
+        }
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let segment = parser.parse_or(0)?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(segment),
+            Some(token) => Err(format!("Unexpected {} in the segment expression.", token)),
+        }
+    }
+
+    /// Append the condition matching this segment to `builder`.
+    ///
+    /// The condition refers to the subscriber as `s` (i.e. `subscriptions s`).
+    /// Every value coming from the expression is sent as a bind parameter,
+    /// never spliced into the SQL text.
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Segment::Tag(tag) => {
+                builder
+                    .push("EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = ")
+                    .push_bind(tag.as_ref().to_string())
+                    .push(")");
+            }
+            Segment::JoinedAfter(date) => match date.checked_add_signed(Duration::days(1)) {
+                Some(next_day) => {
+                    builder
+                        .push("s.subscribed_at >= ")
+                        .push_bind(start_of_day(next_day));
+                }
+                // nobody joins after the last date, `parse` rejects it anyway
+                None => {
+                    builder.push("FALSE");
+                }
+            },
+            Segment::JoinedBefore(date) => {
+                builder
+                    .push("s.subscribed_at < ")
+                    .push_bind(start_of_day(*date));
+            }
+            Segment::Attribute { key, value } => {
+                builder
+                    .push("(s.attributes ->> ")
+                    .push_bind(key.clone())
+                    .push(") = ")
+                    .push_bind(value.clone());
+            }
+            Segment::Not(inner) => {
+                // a missing attribute yields NULL, which must count as "not matching"
+                builder.push("NOT COALESCE((");
+                inner.push_sql(builder);
+                builder.push("), FALSE)");
+            }
+            Segment::And(left, right) => {
+                builder.push("(");
+                left.push_sql(builder);
+                builder.push(" AND ");
+                right.push_sql(builder);
+                builder.push(")");
+            }
+            Segment::Or(left, right) => {
+                builder.push("(");
+                left.push_sql(builder);
+                builder.push(" OR ");
+                right.push_sql(builder);
+                builder.push(")");
+            }
+        }
+    }
+}
+
+// `from_utc` is deprecated in recent chrono releases,
+// its replacement isn't available in the 0.4.23 we depend on
+#[allow(deprecated)]
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    DateTime::from_utc(date.and_hms_opt(0, 0, 0).unwrap(), Utc)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    LeftParen,
+    RightParen,
+    And,
+    Or,
+    Not,
+    Predicate { field: String, value: String },
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::LeftParen => write!(f, "`(`"),
+            Token::RightParen => write!(f, "`)`"),
+            Token::And => write!(f, "`AND`"),
+            Token::Or => write!(f, "`OR`"),
+            Token::Not => write!(f, "`NOT`"),
+            Token::Predicate { field, value } => write!(f, "`{}:{}`", field, value),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '(' || c == ')' {
+            chars.next();
+            tokens.push(if c == '(' { Token::LeftParen } else { Token::RightParen });
+            continue;
+        }
+
+        let mut word = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == '(' || c == ')' || c == ':' {
+                break;
+            }
+            word.push(c);
+            chars.next();
+        }
+        if chars.peek() != Some(&':') {
+            tokens.push(match word.to_uppercase().as_str() {
+                "AND" => Token::And,
+                "OR" => Token::Or,
+                "NOT" => Token::Not,
+                _ => return Err(format!("`{}` is not a valid segment predicate.", word)),
+            });
+            continue;
+        }
+        // skip the colon, the value is either quoted or runs until the next delimiter
+        chars.next();
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(c) => value.push(c),
+                        None => return Err("Unterminated quoted value.".into()),
+                    },
+                    Some(c) => value.push(c),
+                    None => return Err("Unterminated quoted value.".into()),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+        }
+        tokens.push(Token::Predicate { field: word, value });
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn parse_or(&mut self, depth: usize) -> Result<Segment, String> {
+        let mut left = self.parse_and(depth)?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            let right = self.parse_and(depth)?;
+            left = Segment::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self, depth: usize) -> Result<Segment, String> {
+        let mut left = self.parse_unary(depth)?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            let right = self.parse_unary(depth)?;
+            left = Segment::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self, depth: usize) -> Result<Segment, String> {
+        if depth > MAX_NESTING_DEPTH {
+            return Err("The segment expression is nested too deeply.".into());
+        }
+        match self.next() {
+            Some(Token::Not) => Ok(Segment::Not(Box::new(self.parse_unary(depth + 1)?))),
+            Some(Token::LeftParen) => {
+                let inner = self.parse_or(depth + 1)?;
+                match self.next() {
+                    Some(Token::RightParen) => Ok(inner),
+                    _ => Err("Missing `)` in the segment expression.".into()),
+                }
+            }
+            Some(Token::Predicate { field, value }) => parse_predicate(field, value),
+            Some(token) => Err(format!("Unexpected {} in the segment expression.", token)),
+            None => Err("The segment expression ended unexpectedly.".into()),
+        }
+    }
+}
+
+fn parse_predicate(field: String, value: String) -> Result<Segment, String> {
+    let parse_date = |value: &str| {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_| format!("{} is not a valid date, use YYYY-MM-DD.", value))
+    };
+    match field.as_str() {
+        "tag" => Ok(Segment::Tag(SubscriberTag::parse(value)?)),
+        "joined_after" => {
+            let date = parse_date(&value)?;
+            // the segment starts the next day, which must exist
+            if date.checked_add_signed(Duration::days(1)).is_none() {
+                return Err(format!("{} is too far in the future.", value));
+            }
+            Ok(Segment::JoinedAfter(date))
+        }
+        "joined_before" => Ok(Segment::JoinedBefore(parse_date(&value)?)),
+        _ => match field.strip_prefix("attr.") {
+            Some(key) if is_valid_attribute_key(key) => Ok(Segment::Attribute {
+                key: key.to_string(),
+                value,
+            }),
+            _ => Err(format!("`{}` is not a valid segment predicate.", field)),
+        },
+    }
+}
+
+pub fn is_valid_attribute_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= 64
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Segment;
+    use crate::domain::SubscriberTag;
+    use chrono::NaiveDate;
+    use claim::{assert_err, assert_ok};
+    use sqlx::{Postgres, QueryBuilder};
+
+    fn tag(s: &str) -> Segment {
+        Segment::Tag(SubscriberTag::parse(s.to_string()).unwrap())
+    }
+
+    fn sql(segment: &Segment) -> String {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("");
+        segment.push_sql(&mut builder);
+        builder.into_sql()
+    }
+
+    #[test]
+    fn a_single_predicate_is_parsed() {
+        assert_eq!(Segment::parse("tag:beta").unwrap(), tag("beta"));
+        assert_eq!(
+            Segment::parse("joined_after:2023-01-31").unwrap(),
+            Segment::JoinedAfter(NaiveDate::from_ymd_opt(2023, 1, 31).unwrap())
+        );
+        assert_eq!(
+            Segment::parse(r#"attr.plan:"pro \"plus\"""#).unwrap(),
+            Segment::Attribute { key: "plan".into(), value: r#"pro "plus""#.into() }
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let segment = Segment::parse("tag:a OR tag:b and tag:c").unwrap();
+        assert_eq!(
+            segment,
+            Segment::Or(
+                Box::new(tag("a")),
+                Box::new(Segment::And(Box::new(tag("b")), Box::new(tag("c"))))
+            )
+        );
+    }
+
+    #[test]
+    fn parentheses_and_not_are_supported() {
+        let segment = Segment::parse("NOT (tag:a OR tag:b)").unwrap();
+        assert_eq!(
+            segment,
+            Segment::Not(Box::new(Segment::Or(Box::new(tag("a")), Box::new(tag("b")))))
+        );
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        for expression in &[
+            "",
+            "beta",
+            "tag:",
+            "tag:Beta",
+            "tag:a AND",
+            "(tag:a",
+            "tag:a)",
+            "tag:a tag:b",
+            "joined_after:yesterday",
+            "attr.:x",
+            "attr.Plan:x",
+            "email:x@example.com",
+            r#"attr.plan:"pro"#,
+        ] {
+            assert_err!(Segment::parse(expression), "{} should be rejected", expression);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_expressions_are_rejected() {
+        let expression = format!("{}tag:a{}", "(".repeat(100), ")".repeat(100));
+        assert_err!(Segment::parse(&expression));
+        let expression = format!("{}tag:a", "NOT ".repeat(100));
+        assert_err!(Segment::parse(&expression));
+    }
+
+    #[test]
+    fn overly_long_expressions_are_rejected() {
+        let expression = ["tag:a"; 300].join(" OR ");
+        assert_err!(Segment::parse(&expression));
+        let expression = ["tag:a"; 10].join(" OR ");
+        assert_ok!(Segment::parse(&expression));
+    }
+
+    #[test]
+    fn the_generated_sql_only_contains_placeholders() {
+        let segment = Segment::parse(
+            r#"tag:beta AND (joined_after:2023-01-01 OR NOT attr.plan:"x' OR 1=1; --")"#,
+        )
+        .unwrap();
+        assert_eq!(
+            sql(&segment),
+            "(EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = $1) \
+            AND (s.subscribed_at >= $2 OR NOT COALESCE(((s.attributes ->> $3) = $4), FALSE)))"
+        );
+    }
+
+    #[test]
+    fn injection_attempts_never_reach_the_sql_text() {
+        for expression in &[
+            r#"attr.plan:"'; DROP TABLE subscriptions; --""#,
+            r#"attr.plan:pro'--"#,
+        ] {
+            let segment = Segment::parse(expression).unwrap();
+            let sql = sql(&segment);
+            assert!(!sql.contains('\''), "{}", sql);
+            assert!(!sql.contains("DROP"), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn joined_after_the_last_supported_date_is_rejected() {
+        let last_date = NaiveDate::MAX.format("%Y-%m-%d").to_string();
+        assert_err!(Segment::parse(&format!("joined_after:{}", last_date)));
+        assert_ok!(Segment::parse(&format!("joined_before:{}", last_date)));
+    }
+
+    #[test]
+    fn joined_before_excludes_the_given_day() {
+        let segment = Segment::parse("joined_before:2023-01-31").unwrap();
+        assert_eq!(sql(&segment), "s.subscribed_at < $1");
+    }
+}
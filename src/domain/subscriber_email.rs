@@ -1,34 +1,82 @@
 use validator::validate_email;
 
+/// A valid email address, normalized so that the same mailbox is always written the same way:
+/// without surrounding whitespace, with a lowercase, ASCII (IDNA) domain.
+/// Local parts may be UTF-8 (RFC 6531), the Unicode form of the domain
+/// and the address as the subscriber typed it are kept for display.
 #[derive(Debug)]
-pub struct SubscriberEmail(String);
+pub struct SubscriberEmail {
+    address: String,
+    unicode: String,
+    display: String,
+}
 
 impl SubscriberEmail {
     pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
-        } else {
-            Err(format!("{} is not a valid subscriber email.", s))
+        let display = s.trim();
+        if !validate_email(ascii_stand_in(display)) {
+            return Err(format!("{} is not a valid subscriber email.", s));
         }
+        let invalid = || format!("{} is not a valid subscriber email.", s);
+        let (local_part, domain) = display.rsplit_once('@').ok_or_else(invalid)?;
+        let domain = idna::domain_to_ascii(domain).map_err(|_| invalid())?;
+        let (unicode_domain, result) = idna::domain_to_unicode(&domain);
+        result.map_err(|_| invalid())?;
+        Ok(Self {
+            address: format!("{}@{}", local_part, domain),
+            unicode: format!("{}@{}", local_part, unicode_domain),
+            display: display.to_string(),
+        })
+    }
+
+    /// The address as typed, e.g. with an internationalized domain.
+    pub fn display(&self) -> &str {
+        &self.display
+    }
+
+    /// The normalized address with the Unicode form of its domain.
+    pub fn unicode(&self) -> &str {
+        &self.unicode
+    }
+
+    /// Only transports supporting SMTPUTF8 can deliver to UTF-8 local parts,
+    /// internationalized domains go through their ASCII form.
+    pub fn requires_smtputf8(&self) -> bool {
+        !self.address.is_ascii()
     }
 }
 
+// RFC 6531 allows non-ASCII characters wherever RFC 5322 allows letters,
+// so they are checked as letters; control and space characters stay invalid
+fn ascii_stand_in(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii() || c.is_control() || c.is_whitespace() {
+                c
+            } else {
+                'a'
+            }
+        })
+        .collect()
+}
+
 impl std::fmt::Display for SubscriberEmail {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
+        self.display.fmt(f)
     }
 }
 
+// the normalized address, the one we store, look up and send to
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
-        &self.0
+        &self.address
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::SubscriberEmail;
-    use claim::assert_err;
+    use claim::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
     use quickcheck::Gen;
@@ -48,6 +96,55 @@ mod tests {
         SubscriberEmail::parse(valid_email.0).is_ok()
     }
 
+    // an address with a UTF-8 local part, an internationalized domain, or both
+    #[derive(Debug, Clone)]
+    struct InternationalEmailFixture {
+        local_part: String,
+        domain: String,
+    }
+
+    impl quickcheck::Arbitrary for InternationalEmailFixture {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let characters = ['a', 'z', '7', '_', 'é', 'ü', 'ñ', 'δ', 'ж', '用', '户', 'ก', 'ह'];
+            let labels = ["bücher", "例え", "пример", "mañana", "δοκιμή", "example", "mail"];
+            let tlds = ["com", "org", "jp", "рф", "中国"];
+            let length = 1 + usize::arbitrary(g) % 20;
+            let local_part = (0..length).map(|_| *g.choose(&characters).unwrap()).collect();
+            let domain = format!(
+                "{}.{}",
+                g.choose(&labels).unwrap(),
+                g.choose(&tlds).unwrap()
+            );
+            Self { local_part, domain }
+        }
+    }
+
+    impl InternationalEmailFixture {
+        fn address(&self) -> String {
+            format!("{}@{}", self.local_part, self.domain)
+        }
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn international_emails_are_parsed_successfully(email: InternationalEmailFixture) -> bool {
+        SubscriberEmail::parse(email.address()).is_ok()
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn international_emails_keep_both_forms(email: InternationalEmailFixture) -> bool {
+        let parsed = SubscriberEmail::parse(email.address()).unwrap();
+        let (local_part, domain) = parsed.as_ref().rsplit_once('@').unwrap();
+        local_part == email.local_part
+            && domain.is_ascii()
+            && parsed.unicode() == email.address()
+            && parsed.requires_smtputf8() != email.local_part.is_ascii()
+    }
+
+    #[test]
+    fn control_characters_are_rejected_from_utf8_local_parts() {
+        assert_err!(SubscriberEmail::parse("us\u{85}ula@domain.com".to_string()));
+    }
+
     #[test]
     fn empty_string_is_rejected() {
         let email = "".to_string();
@@ -65,4 +162,26 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn surrounding_whitespace_is_trimmed() {
+        let email = assert_ok!(SubscriberEmail::parse("  ursula@domain.com \n".to_string()));
+        assert_eq!(email.as_ref(), "ursula@domain.com");
+        assert_eq!(email.display(), "ursula@domain.com");
+    }
+
+    #[test]
+    fn the_domain_is_lowercased_but_not_the_local_part() {
+        let email = assert_ok!(SubscriberEmail::parse("Ursula@Domain.COM".to_string()));
+        assert_eq!(email.as_ref(), "Ursula@domain.com");
+        assert_eq!(email.display(), "Ursula@Domain.COM");
+    }
+
+    #[test]
+    fn internationalized_domains_are_idna_encoded() {
+        let email = assert_ok!(SubscriberEmail::parse("ursula@Bücher.example".to_string()));
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.example");
+        assert_eq!(email.unicode(), "ursula@bücher.example");
+        assert_eq!(email.to_string(), "ursula@Bücher.example");
+    }
+}
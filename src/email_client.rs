@@ -1,12 +1,26 @@
 use reqwest::Client;
 use secrecy::{Secret, ExposeSecret};
+use uuid::Uuid;
 use crate::domain::SubscriberEmail;
 
 pub struct EmailClient {
     sender: SubscriberEmail,
     http_client: Client,
     base_url: String,
-    authorization_token: Secret<String>
+    authorization_token: Secret<String>,
+    // whether the transport delivers to UTF-8 local parts
+    smtputf8: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SendEmailError {
+    #[error(
+        "{0} has a non-ASCII local part, \
+        the email transport can't deliver to it without SMTPUTF8 support."
+    )]
+    Smtputf8Unsupported(String),
+    #[error(transparent)]
+    RequestError(#[from] reqwest::Error),
 }
 
 impl EmailClient {
@@ -14,7 +28,8 @@ impl EmailClient {
         base_url: String, 
         sender: SubscriberEmail,
         authorization_token: Secret<String>,
-        timeout: std::time::Duration
+        timeout: std::time::Duration,
+        smtputf8: bool,
     ) -> Self {
         let http_client = Client::builder()
             .timeout(timeout)
@@ -24,17 +39,51 @@ impl EmailClient {
             http_client,
             base_url,
             sender,
-            authorization_token
+            authorization_token,
+            smtputf8,
         }
     }
 
+    /// Addresses with a UTF-8 local part need a transport supporting SMTPUTF8.
+    pub fn can_deliver_to(&self, recipient: &SubscriberEmail) -> bool {
+        self.smtputf8 || !recipient.requires_smtputf8()
+    }
+
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<(), SendEmailError> {
+        self.send(recipient, subject, Some(html_content), text_content, None).await
+    }
+
+    /// Send a newsletter issue, with its id in the message metadata
+    /// so that the bounces and complaints Postmark reports can be attributed to it.
+    pub async fn send_issue(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: Option<&str>,
+        text_content: &str,
+        issue_id: Uuid,
+    ) -> Result<(), SendEmailError> {
+        let metadata = Metadata { issue_id: issue_id.to_string() };
+        self.send(recipient, subject, html_content, text_content, Some(metadata)).await
+    }
+
+    async fn send(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: Option<&str>,
+        text_content: &str,
+        metadata: Option<Metadata>,
+    ) -> Result<(), SendEmailError> {
+        if !self.can_deliver_to(recipient) {
+            return Err(SendEmailError::Smtputf8Unsupported(recipient.to_string()));
+        }
         let url = format!("{}/email", self.base_url);
         let request_body = SendEmailRequest {
             from: self.sender.as_ref(),
@@ -42,6 +91,7 @@ impl EmailClient {
             subject,
             html_body: html_content,
             text_body: text_content,
+            metadata,
         };
         self
             .http_client
@@ -64,14 +114,22 @@ struct SendEmailRequest<'a> {
     from: &'a str,
     to: &'a str,
     subject: &'a str,
-    html_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html_body: Option<&'a str>,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<Metadata>,
+}
+
+#[derive(serde::Serialize)]
+struct Metadata {
+    issue_id: String,
 }
 
 #[cfg(test)]
 mod tests {
     use crate::domain::SubscriberEmail;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{EmailClient, SendEmailError};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
@@ -80,7 +138,7 @@ mod tests {
     use wiremock::{Mock, MockServer, ResponseTemplate};
     use wiremock::Request;
     use wiremock::matchers::any;
-    use claim::{assert_ok, assert_err};
+    use claim::{assert_ok, assert_err, assert_matches};
 
     struct SendEmailBodyMatcher;
 
@@ -120,6 +178,28 @@ mod tests {
             .await;
     }
 
+    #[tokio::test]
+    async fn send_issue_carries_the_issue_id_in_the_metadata() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let issue_id = uuid::Uuid::new_v4();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        email_client
+            .send_issue(&email(), &subject(), Some(&content()), &content(), issue_id)
+            .await
+            .unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["Metadata"]["issue_id"], issue_id.to_string());
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_the_server_returns_200() {
         let mock_server = MockServer::start().await;
@@ -176,6 +256,52 @@ mod tests {
         assert_err!(outcome);
     }
 
+    #[tokio::test]
+    async fn utf8_local_parts_are_refused_without_smtputf8_support() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&utf8_email(), &subject(), &content(), &content())
+            .await;
+
+        assert_matches!(outcome, Err(SendEmailError::Smtputf8Unsupported(_)));
+    }
+
+    #[tokio::test]
+    async fn utf8_local_parts_are_sent_with_smtputf8_support() {
+        let mock_server = MockServer::start().await;
+        let email_client = EmailClient::new(
+            mock_server.uri(),
+            email(),
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+            true,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&utf8_email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    fn utf8_email() -> SubscriberEmail {
+        SubscriberEmail::parse("用户@例え.jp".to_string()).unwrap()
+    }
+
     fn subject() -> String {
         Sentence(1..2).fake()
     }
@@ -194,6 +320,7 @@ mod tests {
             email(), 
             Secret::new(Faker.fake()),
             std::time::Duration::from_millis(200),
+            false,
         )
     }
 }
\ No newline at end of file
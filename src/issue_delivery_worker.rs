@@ -0,0 +1,368 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+use tracing::field::display;
+use tracing::Span;
+use uuid::Uuid;
+use crate::configuration::Settings;
+use crate::domain::{Locale, NewsletterTemplate};
+use crate::email_client::EmailClient;
+use crate::routes::{
+    deliver_issue, pick_winner, ConfirmedSubscriber, ConfirmedSubscriberRow, IssueContent,
+    IssueDelivery, VariantResult,
+};
+use crate::startup::get_connection_pool;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+struct StoredIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    use_layout: bool,
+    tracking_enabled: bool,
+    // the subject variant sent, if the issue has a subject test
+    variant: Option<i32>,
+    subject: Option<String>,
+}
+
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = configuration.email_client.client();
+    let default_locale = configuration
+        .localization
+        .default_locale()
+        .map_err(|e| anyhow::anyhow!(e))?;
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.application.hmac_secret,
+        default_locale,
+    )
+    .await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_secret: Secret<String>,
+    default_locale: Locale,
+) -> Result<(), anyhow::Error> {
+    loop {
+        let outcome = try_execute_task(
+            &pool,
+            &email_client,
+            &base_url,
+            &hmac_secret,
+            default_locale,
+            Utc::now(),
+        )
+        .await;
+        match outcome {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// Decide the subject tests whose wait is over at `now`,
+/// then send one of the deliveries that can go out at `now`:
+/// those of throttled issues inside their window and under their hourly cap,
+/// and those waiting for the winner of a decided subject test.
+///
+/// The clock is a parameter so that tests don't have to wait for real.
+#[tracing::instrument(
+    skip_all,
+    fields(issue_id = tracing::field::Empty, subscriber_id = tracing::field::Empty),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    default_locale: Locale,
+    now: DateTime<Utc>,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    decide_subject_tests(pool, now)
+        .await
+        .context("Failed to decide the subject tests.")?;
+    let mut transaction = pool.begin().await?;
+    let task = dequeue_pending_delivery(&mut transaction, now).await?;
+    let (issue_id, subscriber_id, variant) = match task {
+        Some(task) => task,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current()
+        .record("issue_id", display(issue_id))
+        .record("subscriber_id", display(subscriber_id));
+    let issue = get_stored_issue(&mut transaction, issue_id, variant).await?;
+    // subscribers may have left the list since the issue was published
+    let subscriber = match get_recipient(&mut transaction, issue_id, subscriber_id).await? {
+        Some(row) => ConfirmedSubscriber::try_from(row)
+            .map_err(|error| {
+                tracing::warn!(
+                    error.cause_chain = ?error,
+                    "Skipping a confirmed subscriber. \
+                    Their stored contact details are invalid",
+                );
+            })
+            .ok(),
+        None => None,
+    };
+    let status = match subscriber {
+        Some(subscriber) => {
+            let content = IssueContent::from_stored(
+                issue.html_content,
+                issue.text_content,
+                issue.use_layout,
+            )
+            .map_err(|e| anyhow::anyhow!(e))?;
+            let subject = NewsletterTemplate::parse(issue.subject.as_ref().unwrap_or(&issue.title))
+                .map_err(|e| anyhow::anyhow!(e))?;
+            let delivery = IssueDelivery {
+                issue_id,
+                subject: &subject,
+                content: &content,
+                tracking: issue.tracking_enabled,
+                base_url,
+                hmac_secret,
+                default_locale,
+            };
+            match deliver_issue(email_client, &delivery, &subscriber).await {
+                Ok(()) => "sent",
+                Err(error) => {
+                    tracing::error!(
+                        error.cause_chain = ?error,
+                        "Failed to send a queued newsletter issue",
+                    );
+                    "failed"
+                }
+            }
+        }
+        None => "skipped",
+    };
+    complete_delivery(&mut transaction, issue_id, subscriber_id, status, issue.variant, now).await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Pick the winner of every subject test due at `now`.
+/// A throttled issue may still be sending its sample, the test then waits for it.
+#[tracing::instrument(name = "Decide the subject tests", skip(pool))]
+async fn decide_subject_tests(pool: &PgPool, now: DateTime<Utc>) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let due_tests = sqlx::query!(
+        r#"
+        SELECT issue_id, metric
+        FROM subject_tests
+        WHERE decided_at IS NULL AND decide_at <= $1
+            AND NOT EXISTS (
+                SELECT 1 FROM newsletter_deliveries d
+                WHERE d.issue_id = subject_tests.issue_id
+                    AND d.status = 'pending' AND d.variant IS NOT NULL
+            )
+        FOR UPDATE SKIP LOCKED
+        "#,
+        now
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    for test in due_tests {
+        // subscribers who opted out of tracking can't tell anything about a subject
+        let results: Vec<VariantResult> = sqlx::query!(
+            r#"
+            SELECT
+                v.variant,
+                COUNT(DISTINCT d.subscriber_id) AS "sent!",
+                COUNT(DISTINCT e.subscriber_id) AS "engaged!"
+            FROM subject_variants v
+            LEFT JOIN (
+                SELECT d.subscriber_id, d.variant
+                FROM newsletter_deliveries d
+                JOIN subscriptions s ON s.id = d.subscriber_id
+                WHERE d.issue_id = $1 AND d.status = 'sent' AND s.tracking_enabled
+            ) d ON d.variant = v.variant
+            LEFT JOIN tracking_events e
+                ON e.issue_id = $1 AND e.subscriber_id = d.subscriber_id AND e.kind = $2
+            WHERE v.issue_id = $1
+            GROUP BY v.variant
+            "#,
+            test.issue_id,
+            test.metric
+        )
+        .fetch_all(&mut transaction)
+        .await?
+        .into_iter()
+        .map(|r| VariantResult {
+            variant: r.variant as usize,
+            sent: r.sent,
+            engaged: r.engaged,
+        })
+        .collect();
+        let winner = pick_winner(&results);
+        sqlx::query!(
+            r#"UPDATE subject_tests SET winner = $2, decided_at = $3 WHERE issue_id = $1"#,
+            test.issue_id,
+            winner as i32,
+            now
+        )
+        .execute(&mut transaction)
+        .await?;
+        tracing::info!(issue_id = %test.issue_id, winner, "Decided a subject test");
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+// An issue is ready when it is neither paused nor cancelled, and either it isn't throttled
+// or `now` is inside its window and fewer deliveries than its cap were attempted in the last hour.
+// Concurrent workers may each send one more delivery than the cap allows.
+#[tracing::instrument(skip_all)]
+async fn dequeue_pending_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    now: DateTime<Utc>,
+) -> Result<Option<(Uuid, Uuid, Option<i32>)>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        WITH ready AS (
+            SELECT q.issue_id
+            FROM (
+                SELECT DISTINCT issue_id FROM newsletter_deliveries WHERE status = 'pending'
+            ) q
+            JOIN newsletter_issues i ON i.id = q.issue_id AND i.delivery_state = 'active'
+            LEFT JOIN send_throttles th ON th.issue_id = q.issue_id
+            WHERE th.issue_id IS NULL OR (
+                (
+                    th.window_start IS NULL
+                    OR (
+                        th.window_start < th.window_end
+                        AND ($1 AT TIME ZONE th.timezone)::time >= th.window_start
+                        AND ($1 AT TIME ZONE th.timezone)::time < th.window_end
+                    )
+                    OR (
+                        th.window_start > th.window_end
+                        AND (
+                            ($1 AT TIME ZONE th.timezone)::time >= th.window_start
+                            OR ($1 AT TIME ZONE th.timezone)::time < th.window_end
+                        )
+                    )
+                )
+                AND (
+                    th.max_per_hour IS NULL
+                    OR (
+                        SELECT COUNT(*) FROM newsletter_deliveries r
+                        WHERE r.issue_id = q.issue_id
+                            AND r.status IN ('sent', 'failed')
+                            AND r.attempted_at > $1 - interval '1 hour'
+                            AND r.attempted_at <= $1
+                    ) < th.max_per_hour
+                )
+            )
+        )
+        SELECT d.issue_id, d.subscriber_id, d.variant
+        FROM newsletter_deliveries d
+        JOIN ready ON ready.issue_id = d.issue_id
+        LEFT JOIN subject_tests t ON t.issue_id = d.issue_id
+        WHERE d.status = 'pending'
+            -- the sample of a subject test goes out before the test is decided
+            AND (d.variant IS NOT NULL OR t.issue_id IS NULL OR t.decided_at IS NOT NULL)
+        FOR UPDATE OF d
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+        now
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(record.map(|r| (r.issue_id, r.subscriber_id, r.variant)))
+}
+
+// the variant assigned to the delivery if any, or the winner of the subject test
+#[tracing::instrument(skip_all)]
+async fn get_stored_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    variant: Option<i32>,
+) -> Result<StoredIssue, sqlx::Error> {
+    sqlx::query_as!(
+        StoredIssue,
+        r#"
+        SELECT
+            i.title, i.text_content, i.html_content, i.use_layout, i.tracking_enabled,
+            v.variant AS "variant?", v.subject AS "subject?"
+        FROM newsletter_issues i
+        LEFT JOIN subject_tests t ON t.issue_id = i.id
+        LEFT JOIN subject_variants v
+            ON v.issue_id = i.id AND v.variant = COALESCE($2, t.winner)
+        WHERE i.id = $1
+        "#,
+        issue_id,
+        variant
+    )
+    .fetch_one(transaction)
+    .await
+}
+
+// the subscriber, if they are still a confirmed member of the issue's list
+#[tracing::instrument(skip_all)]
+async fn get_recipient(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<Option<ConfirmedSubscriberRow>, sqlx::Error> {
+    sqlx::query_as!(
+        ConfirmedSubscriberRow,
+        r#"
+        SELECT s.id, s.email, s.name, s.content_format, s.preferences_token, s.tracking_enabled,
+            s.locale
+        FROM subscriptions s
+        JOIN newsletter_issues i ON i.id = $1
+        JOIN list_memberships m ON m.subscriber_id = s.id AND m.list_id = i.list_id
+        WHERE s.id = $2 AND s.status = 'confirmed' AND m.status = 'confirmed'
+            AND NOT EXISTS (SELECT 1 FROM suppressions sp WHERE sp.email = lower(s.email))
+        "#,
+        issue_id,
+        subscriber_id
+    )
+    .fetch_optional(transaction)
+    .await
+}
+
+#[tracing::instrument(skip_all)]
+async fn complete_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    status: &str,
+    variant: Option<i32>,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_deliveries
+        SET status = $3, variant = $4, attempted_at = $5
+        WHERE issue_id = $1 AND subscriber_id = $2
+        "#,
+        issue_id,
+        subscriber_id,
+        status,
+        variant,
+        now
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
@@ -6,6 +6,11 @@ pub mod startup;
 pub mod telemetry;
 pub mod domain;
 pub mod email_client;
+pub mod challenge_verifier;
+pub mod issue_delivery_worker;
+pub mod rate_limiting;
+pub mod localization;
+pub mod templating;
 
 
 
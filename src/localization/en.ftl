@@ -0,0 +1,48 @@
+## Confirmation email, sent at signup and when a new link is requested
+
+confirmation-email-subject = Welcome
+confirmation-email-welcome = Welcome to our newsletter!
+confirmation-email-confirm-text = Visit { $link } to confirm your subscription.
+confirmation-email-confirm-html = Click <a href="{ $link }">here</a> to confirm your subscription.
+confirmation-email-manage-text = Manage your subscription at { $link }
+manage-subscription = Manage your subscription
+
+## Footer of newsletter issues
+
+newsletter-footer-text = Manage your subscription: { $link }
+
+## Pages
+
+back-to-homepage = Back to the homepage
+something-went-wrong = Something went wrong
+invalid-link-heading = This link is not valid
+
+## Confirmation landing pages
+
+confirmed-title = Subscription confirmed
+confirmed-message = Thank you for confirming your subscription, you'll receive our next issue in your inbox.
+already-confirmed-title = Already confirmed
+already-confirmed-message = Your subscription has already been confirmed, there is nothing else to do.
+missing-token-title = Incomplete confirmation link
+missing-token-message = The confirmation link you followed is missing its token.
+missing-token-hint = Please copy the full link from the email we sent you and try again.
+invalid-token-title = Invalid confirmation link
+invalid-token-message = The confirmation link you followed is invalid or has expired.
+invalid-token-hint = Enter your email address below and we'll send you a new one.
+email-label = Email
+email-placeholder = Enter your email
+resend-confirmation-button = Send a new link
+confirm-error-message = We couldn't confirm your subscription right now, please try again in a few minutes.
+
+## Unsubscribe pages
+
+unsubscribe-title = Unsubscribe
+unsubscribe-question = Do you really want to stop receiving our newsletter?
+unsubscribe-button = Unsubscribe
+unsubscribe-manage-preferences = Manage your preferences instead
+unsubscribed-title = Unsubscribed
+unsubscribed-heading = You have been unsubscribed
+unsubscribed-message = You won't receive any more issues of our newsletter.
+invalid-preferences-link-message = Please use the link from the latest email we sent you.
+invalid-preferences-title = Invalid preferences
+preferences-error-message = We couldn't update your preferences right now, please try again later.
@@ -0,0 +1,48 @@
+## Confirmation email, sent at signup and when a new link is requested
+
+confirmation-email-subject = Bienvenue
+confirmation-email-welcome = Bienvenue dans notre newsletter !
+confirmation-email-confirm-text = Rendez-vous sur { $link } pour confirmer votre abonnement.
+confirmation-email-confirm-html = Cliquez <a href="{ $link }">ici</a> pour confirmer votre abonnement.
+confirmation-email-manage-text = Gérez votre abonnement sur { $link }
+manage-subscription = Gérer votre abonnement
+
+## Footer of newsletter issues
+
+newsletter-footer-text = Gérer votre abonnement : { $link }
+
+## Pages
+
+back-to-homepage = Retour à l'accueil
+something-went-wrong = Une erreur est survenue
+invalid-link-heading = Ce lien n'est pas valide
+
+## Confirmation landing pages
+
+confirmed-title = Abonnement confirmé
+confirmed-message = Merci d'avoir confirmé votre abonnement, vous recevrez notre prochain numéro dans votre boîte de réception.
+already-confirmed-title = Déjà confirmé
+already-confirmed-message = Votre abonnement a déjà été confirmé, vous n'avez rien d'autre à faire.
+missing-token-title = Lien de confirmation incomplet
+missing-token-message = Il manque son jeton au lien de confirmation que vous avez suivi.
+missing-token-hint = Copiez le lien complet depuis l'email que nous vous avons envoyé et réessayez.
+invalid-token-title = Lien de confirmation invalide
+invalid-token-message = Le lien de confirmation que vous avez suivi est invalide ou a expiré.
+invalid-token-hint = Saisissez votre adresse email ci-dessous et nous vous en enverrons un nouveau.
+email-label = Email
+email-placeholder = Saisissez votre email
+resend-confirmation-button = Envoyer un nouveau lien
+confirm-error-message = Nous n'avons pas pu confirmer votre abonnement, réessayez dans quelques minutes.
+
+## Unsubscribe pages
+
+unsubscribe-title = Se désabonner
+unsubscribe-question = Voulez-vous vraiment ne plus recevoir notre newsletter ?
+unsubscribe-button = Se désabonner
+unsubscribe-manage-preferences = Gérer plutôt vos préférences
+unsubscribed-title = Désabonné
+unsubscribed-heading = Vous êtes désabonné
+unsubscribed-message = Vous ne recevrez plus aucun numéro de notre newsletter.
+invalid-preferences-link-message = Utilisez le lien du dernier email que nous vous avons envoyé.
+invalid-preferences-title = Préférences invalides
+preferences-error-message = Nous n'avons pas pu mettre à jour vos préférences, réessayez plus tard.
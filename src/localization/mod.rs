@@ -0,0 +1,145 @@
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::HttpRequest;
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use once_cell::sync::Lazy;
+use crate::domain::Locale;
+
+type Bundle = FluentBundle<FluentResource>;
+
+// every message has an English translation, it stands in for missing ones
+const REFERENCE_LOCALE: Locale = Locale::En;
+
+fn catalog_source(locale: Locale) -> &'static str {
+    match locale {
+        Locale::En => include_str!("en.ftl"),
+        Locale::Fr => include_str!("fr.ftl"),
+    }
+}
+
+fn build_bundle(locale: Locale) -> Bundle {
+    let resource = FluentResource::try_new(catalog_source(locale).to_string())
+        .unwrap_or_else(|(_, errors)| {
+            panic!("The {} catalog is invalid: {:?}", locale.as_str(), errors)
+        });
+    let language = locale
+        .as_str()
+        .parse()
+        .expect("Every supported locale is a valid language identifier");
+    let mut bundle = Bundle::new_concurrent(vec![language]);
+    // isolation marks around variables would end up inside the links of our emails
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .expect("A catalog defines each of its messages once");
+    bundle
+}
+
+fn bundle(locale: Locale) -> &'static Bundle {
+    static BUNDLES: Lazy<Vec<(Locale, Bundle)>> = Lazy::new(|| {
+        Locale::ALL
+            .into_iter()
+            .map(|locale| (locale, build_bundle(locale)))
+            .collect()
+    });
+    BUNDLES
+        .iter()
+        .find(|(l, _)| *l == locale)
+        .map(|(_, bundle)| bundle)
+        .expect("Every supported locale has a catalog")
+}
+
+/// The message `id` in `locale`, `args` fill in its `{ $variables }`.
+///
+/// Messages are inserted as is in pages and emails, escape the arguments
+/// beforehand if they come from user input.
+pub fn message(locale: Locale, id: &str, args: &[(&str, &str)]) -> String {
+    format_message(locale, id, args)
+        .or_else(|| format_message(REFERENCE_LOCALE, id, args))
+        .unwrap_or_else(|| {
+            tracing::error!(message_id = id, "The message is missing from the catalogs.");
+            id.to_string()
+        })
+}
+
+fn format_message(locale: Locale, id: &str, args: &[(&str, &str)]) -> Option<String> {
+    let bundle = bundle(locale);
+    let pattern = bundle.get_message(id)?.value()?;
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, *value);
+    }
+    let mut errors = vec![];
+    let formatted = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+    if !errors.is_empty() {
+        tracing::warn!(
+            message_id = id,
+            locale = locale.as_str(),
+            ?errors,
+            "Failed to format a message of the catalog."
+        );
+    }
+    Some(formatted.into_owned())
+}
+
+/// The supported locale the browser of the reader asks for, if any.
+pub fn request_locale(request: &HttpRequest) -> Option<Locale> {
+    request
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|header| header.to_str().ok())
+        .and_then(Locale::from_accept_language)
+}
+
+/// The locale stored for a subscriber, `None` when they should get the default one:
+/// they joined before locales were captured, or their locale has no catalog anymore.
+pub fn stored_locale(locale: Option<&str>) -> Option<Locale> {
+    locale.and_then(|l| Locale::parse(l).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bundle, catalog_source, message, REFERENCE_LOCALE};
+    use crate::domain::Locale;
+
+    fn message_ids(source: &str) -> Vec<&str> {
+        source
+            .lines()
+            .filter(|line| line.starts_with(|c: char| c.is_ascii_lowercase()))
+            .filter_map(|line| line.split_once(" =").map(|(id, _)| id))
+            .collect()
+    }
+
+    #[test]
+    fn every_message_is_translated_to_every_locale() {
+        let ids = message_ids(catalog_source(REFERENCE_LOCALE));
+        assert!(!ids.is_empty());
+        for locale in Locale::ALL {
+            for id in &ids {
+                assert!(
+                    bundle(locale).has_message(id),
+                    "{} is missing from the {} catalog",
+                    id,
+                    locale.as_str()
+                );
+            }
+            assert_eq!(message_ids(catalog_source(locale)).len(), ids.len());
+        }
+    }
+
+    #[test]
+    fn variables_are_filled_in_without_isolation_marks() {
+        let text = message(
+            Locale::Fr,
+            "newsletter-footer-text",
+            &[("link", "https://example.com/preferences")],
+        );
+
+        assert_eq!(text, "Gérer votre abonnement : https://example.com/preferences");
+    }
+
+    #[test]
+    fn unknown_messages_fall_back_to_their_id() {
+        assert_eq!(message(Locale::Fr, "no-such-message", &[]), "no-such-message");
+    }
+}
@@ -0,0 +1,99 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use crate::rate_limiting::{Check, RateLimitRule, RateLimitState, RateLimitStore};
+
+// at most this many counters are kept, see `evict`
+const MAX_COUNTERS: usize = 10_000;
+
+/// Keeps the counters in the memory of this instance,
+/// every instance of the application limits its clients on its own.
+#[derive(Default)]
+pub struct MemoryStore {
+    counters: Mutex<HashMap<String, (RateLimitState, DateTime<Utc>)>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn check<'a>(&'a self, rule: &'a RateLimitRule, key: &'a str, now: DateTime<Utc>) -> Check<'a> {
+        Box::pin(async move {
+            let mut counters = self
+                .counters
+                .lock()
+                .map_err(|_| anyhow::anyhow!("The rate limiting counters are poisoned."))?;
+            if counters.len() >= MAX_COUNTERS {
+                evict(&mut counters, now);
+            }
+            let state = counters.get(key).map(|(state, _)| *state).unwrap_or_default();
+            let (state, decision) = rule.apply(state, now);
+            counters.insert(key.to_string(), (state, rule.expires_at(&state)));
+            Ok(decision)
+        })
+    }
+}
+
+// drops the expired counters, then the ones expiring first until half of the room is free,
+// so that the scan runs at most once every `MAX_COUNTERS / 2` new counters
+fn evict(counters: &mut HashMap<String, (RateLimitState, DateTime<Utc>)>, now: DateTime<Utc>) {
+    counters.retain(|_, (_, expires_at)| *expires_at > now);
+    let keep = MAX_COUNTERS / 2;
+    if counters.len() <= keep {
+        return;
+    }
+    let mut expirations: Vec<(DateTime<Utc>, String)> = counters
+        .iter()
+        .map(|(key, (_, expires_at))| (*expires_at, key.clone()))
+        .collect();
+    let evicted = expirations.len() - keep;
+    expirations.select_nth_unstable(evicted - 1);
+    for (_, key) in &expirations[..evicted] {
+        counters.remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryStore, MAX_COUNTERS};
+    use crate::configuration::{RateLimitAlgorithm, RateLimitRuleSettings};
+    use crate::rate_limiting::{RateLimitRule, RateLimitStore};
+    use chrono::{Duration, Utc};
+
+    fn rule() -> RateLimitRule {
+        RateLimitRule::new(
+            0,
+            &RateLimitRuleSettings {
+                path: "/subscriptions".into(),
+                method: None,
+                algorithm: RateLimitAlgorithm::FixedWindow,
+                limit: 5,
+                period_seconds: 60,
+                form_field: None,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn the_counters_expiring_first_are_evicted_when_the_store_is_full() {
+        let store = MemoryStore::new();
+        let rule = rule();
+        let start = Utc::now();
+        for i in 0..MAX_COUNTERS {
+            let now = start + Duration::milliseconds(i as i64);
+            store.check(&rule, &i.to_string(), now).await.unwrap();
+        }
+
+        let now = start + Duration::milliseconds(MAX_COUNTERS as i64);
+        store.check(&rule, "latest", now).await.unwrap();
+
+        let counters = store.counters.lock().unwrap();
+        assert_eq!(counters.len(), MAX_COUNTERS / 2 + 1);
+        assert!(counters.contains_key("latest"));
+        assert!(counters.contains_key(&(MAX_COUNTERS - 1).to_string()));
+        assert!(!counters.contains_key("0"));
+    }
+}
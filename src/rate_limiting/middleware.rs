@@ -0,0 +1,240 @@
+use actix_web::body::{BoxBody, EitherBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::{web, Error, HttpMessage, HttpResponse, ResponseError};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::future::{ready, Future, Ready};
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use crate::configuration::{RateLimitBackend, RateLimitSettings};
+use crate::rate_limiting::{
+    client_ip, MemoryStore, PostgresStore, RateLimitDecision, RateLimitKey, RateLimitRule,
+    RateLimitStore,
+};
+use crate::routes::ProblemDetails;
+
+const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+const JSON_CONTENT_TYPE: &str = "application/json";
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+// longer field values are cut before hashing, no email address is that long
+const MAX_FIELD_LENGTH: usize = 320;
+
+/// Middleware limiting the rate of requests to the routes with a rule,
+/// over the limit clients get a 429 telling them when to retry.
+#[derive(Clone)]
+pub struct RateLimiter {
+    limits: Arc<Limits>,
+}
+
+struct Limits {
+    rules: Vec<RateLimitRule>,
+    trusted_proxies: Vec<IpAddr>,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    /// Counts in the backend picked by the settings, `pool` is only used by the Postgres one.
+    pub fn from_settings(settings: &RateLimitSettings, pool: PgPool) -> Self {
+        let store: Arc<dyn RateLimitStore> = match settings.backend {
+            RateLimitBackend::Memory => Arc::new(MemoryStore::new()),
+            RateLimitBackend::Postgres => Arc::new(PostgresStore::new(pool)),
+        };
+        Self::new(settings, store)
+    }
+
+    pub fn new(settings: &RateLimitSettings, store: Arc<dyn RateLimitStore>) -> Self {
+        let rules = settings
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(id, rule)| RateLimitRule::new(id, rule))
+            .collect();
+        Self {
+            limits: Arc::new(Limits {
+                rules,
+                trusted_proxies: settings.trusted_proxies.clone(),
+                store,
+            }),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            limits: self.limits.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limits: Arc<Limits>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limits = self.limits.clone();
+        Box::pin(async move {
+            let mut rules: Vec<&RateLimitRule> = limits
+                .rules
+                .iter()
+                .filter(|rule| rule.matches(request.method().as_str(), request.path()))
+                .collect();
+            // the rules on the client ip go first, a limited client then can't
+            // create counters for as many field values as it likes
+            rules.sort_by_key(|rule| matches!(rule.key, RateLimitKey::FormField(_)));
+            if rules.is_empty() {
+                return Ok(service.call(request).await?.map_into_left_body());
+            }
+            let fields = if rules.iter().any(|rule| matches!(rule.key, RateLimitKey::FormField(_))) {
+                match read_fields(&mut request).await {
+                    Ok(fields) => fields,
+                    Err(e) => return Ok(request.error_response(e).map_into_right_body()),
+                }
+            } else {
+                Vec::new()
+            };
+            let forwarded_for: Vec<&str> = request
+                .headers()
+                .get_all(X_FORWARDED_FOR)
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            let client = match request.peer_addr() {
+                Some(peer) => client_ip(peer.ip(), &forwarded_for, &limits.trusted_proxies),
+                // only happens in unit tests of handlers
+                None => return Ok(service.call(request).await?.map_into_left_body()),
+            };
+            let now = Utc::now();
+            let mut retry_after: Option<Duration> = None;
+            for rule in rules {
+                let value = match &rule.key {
+                    RateLimitKey::ClientIp => client.to_string(),
+                    RateLimitKey::FormField(name) => {
+                        match fields.iter().find(|(field, _)| field == name) {
+                            Some((_, value)) if !value.trim().is_empty() => field_digest(value),
+                            // the route rejects the request anyway
+                            _ => continue,
+                        }
+                    }
+                };
+                match limits.store.check(rule, &rule.counter_key(&value), now).await {
+                    Ok(RateLimitDecision::Allowed) => {}
+                    // the other rules aren't counted once one limits the request
+                    Ok(RateLimitDecision::Limited { retry_after: wait }) => {
+                        retry_after = Some(wait);
+                        break;
+                    }
+                    // better let a few requests too many through than refuse every request
+                    Err(e) => {
+                        tracing::error!(
+                            error.cause_chain = ?e,
+                            "Failed to check a rate limit."
+                        );
+                    }
+                }
+            }
+            if let Some(retry_after) = retry_after {
+                tracing::info!(%client, "Rate limiting a client");
+                let error = RateLimitError::new(retry_after);
+                return Ok(request.error_response(error).map_into_right_body());
+            }
+            Ok(service.call(request).await?.map_into_left_body())
+        })
+    }
+}
+
+// the key of a field value, hashed so that the keys stay short whatever the clients send
+fn field_digest(value: &str) -> String {
+    let value: String = value.trim().to_lowercase().chars().take(MAX_FIELD_LENGTH).collect();
+    format!("{:x}", Sha256::digest(value.as_bytes()))
+}
+
+// the fields of a url encoded or json body, which is put back for the route to read
+async fn read_fields(request: &mut ServiceRequest) -> Result<Vec<(String, String)>, Error> {
+    let content_type = request.content_type().to_owned();
+    if content_type != FORM_CONTENT_TYPE && content_type != JSON_CONTENT_TYPE {
+        return Ok(Vec::new());
+    }
+    let body = request.extract::<web::Bytes>().await?;
+    let fields = if content_type == JSON_CONTENT_TYPE {
+        json_fields(&body)
+    } else {
+        serde_urlencoded::from_bytes(&body).unwrap_or_default()
+    };
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body);
+    request.set_payload(payload.into());
+    Ok(fields)
+}
+
+// the string fields at the top of a json object, the route rejects other bodies anyway
+fn json_fields(body: &[u8]) -> Vec<(String, String)> {
+    match serde_json::from_slice(body) {
+        Ok(serde_json::Value::Object(object)) => object
+            .into_iter()
+            .filter_map(|(field, value)| match value {
+                serde_json::Value::String(value) => Some((field, value)),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Too many requests, try again in {retry_after} seconds.")]
+pub struct RateLimitError {
+    retry_after: i64,
+}
+
+impl RateLimitError {
+    fn new(retry_after: Duration) -> Self {
+        // whole seconds, rounded up so clients don't come back too early
+        let seconds = (retry_after.num_milliseconds() + 999) / 1000;
+        Self {
+            retry_after: seconds.max(1),
+        }
+    }
+}
+
+impl ResponseError for RateLimitError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        let mut response = ProblemDetails::new(self.status_code(), self.to_string()).response();
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(self.retry_after));
+        response
+    }
+}
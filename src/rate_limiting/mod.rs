@@ -0,0 +1,294 @@
+mod memory;
+mod middleware;
+mod postgres;
+
+pub use memory::*;
+pub use middleware::*;
+pub use postgres::*;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use crate::configuration::{RateLimitAlgorithm, RateLimitRuleSettings};
+
+pub type Check<'a> =
+    Pin<Box<dyn Future<Output = Result<RateLimitDecision, anyhow::Error>> + Send + 'a>>;
+
+/// Where the rate limiting counters live.
+///
+/// `check` counts a request against a rule, `key` identifies the client within the rule.
+/// Errors mean the counter couldn't be read or updated, the request is let through.
+pub trait RateLimitStore: Send + Sync {
+    fn check<'a>(&'a self, rule: &'a RateLimitRule, key: &'a str, now: DateTime<Utc>) -> Check<'a>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitDecision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+/// What a store keeps for each client of a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitState {
+    // the start of the current window for fixed windows,
+    // the theoretical arrival time of the next request for GCRA
+    pub at: DateTime<Utc>,
+    // requests seen in the current window, unused by GCRA
+    pub count: i64,
+}
+
+impl Default for RateLimitState {
+    // a client never seen before, or whose state has expired
+    fn default() -> Self {
+        Self {
+            at: Utc.timestamp_opt(0, 0).unwrap(),
+            count: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateLimitKey {
+    ClientIp,
+    // the value of a field of url encoded forms or json bodies, e.g. the email address of a subscription
+    FormField(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitRule {
+    // tells the rules apart in the keys of the counters
+    pub id: usize,
+    pub path: String,
+    pub method: Option<String>,
+    pub algorithm: RateLimitAlgorithm,
+    pub limit: u32,
+    pub period: Duration,
+    pub key: RateLimitKey,
+}
+
+impl RateLimitRule {
+    pub fn new(id: usize, settings: &RateLimitRuleSettings) -> Self {
+        Self {
+            id,
+            path: settings.path.clone(),
+            method: settings.method.as_ref().map(|m| m.to_uppercase()),
+            algorithm: settings.algorithm,
+            limit: settings.limit.max(1),
+            period: Duration::seconds(settings.period_seconds.max(1).into()),
+            key: match &settings.form_field {
+                Some(field) => RateLimitKey::FormField(field.clone()),
+                None => RateLimitKey::ClientIp,
+            },
+        }
+    }
+
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        self.path == path && self.method.as_deref().map_or(true, |m| m == method)
+    }
+
+    /// The key of the counter of a client, `value` is its ip address or the value of the body field.
+    pub fn counter_key(&self, value: &str) -> String {
+        format!("{}:{}:{}", self.id, self.path, value)
+    }
+
+    /// Count a request made at `now`, returning the new state and whether the request is allowed.
+    /// A limited request leaves the state untouched.
+    pub fn apply(
+        &self,
+        state: RateLimitState,
+        now: DateTime<Utc>,
+    ) -> (RateLimitState, RateLimitDecision) {
+        match self.algorithm {
+            RateLimitAlgorithm::FixedWindow => self.apply_fixed_window(state, now),
+            RateLimitAlgorithm::Gcra => self.apply_gcra(state, now),
+        }
+    }
+
+    // windows are aligned on the epoch, so every instance agrees on their bounds
+    fn apply_fixed_window(
+        &self,
+        state: RateLimitState,
+        now: DateTime<Utc>,
+    ) -> (RateLimitState, RateLimitDecision) {
+        let period = self.period.num_seconds();
+        let window_start = Utc
+            .timestamp_opt(now.timestamp() - now.timestamp().rem_euclid(period), 0)
+            .unwrap();
+        let count = if state.at == window_start { state.count } else { 0 };
+        if count >= i64::from(self.limit) {
+            let retry_after = window_start + self.period - now;
+            return (state, RateLimitDecision::Limited { retry_after });
+        }
+        let state = RateLimitState {
+            at: window_start,
+            count: count + 1,
+        };
+        (state, RateLimitDecision::Allowed)
+    }
+
+    // requests are spaced by `period / limit`, with bursts of up to `limit` requests
+    fn apply_gcra(
+        &self,
+        state: RateLimitState,
+        now: DateTime<Utc>,
+    ) -> (RateLimitState, RateLimitDecision) {
+        let emission_interval = self.period / self.limit as i32;
+        let arrival = std::cmp::max(state.at, now) + emission_interval;
+        if arrival - now > self.period {
+            let retry_after = arrival - self.period - now;
+            return (state, RateLimitDecision::Limited { retry_after });
+        }
+        let state = RateLimitState {
+            at: arrival,
+            count: 0,
+        };
+        (state, RateLimitDecision::Allowed)
+    }
+
+    /// When a state stops limiting anything, stores may forget it past this point.
+    pub fn expires_at(&self, state: &RateLimitState) -> DateTime<Utc> {
+        match self.algorithm {
+            RateLimitAlgorithm::FixedWindow => state.at + self.period,
+            RateLimitAlgorithm::Gcra => state.at,
+        }
+    }
+}
+
+/// The ip address of the client, as seen by the first proxy we don't trust.
+///
+/// `forwarded_for` are the values of the X-Forwarded-For headers,
+/// each proxy appends the address it received the request from.
+/// They are read from the right, as long as the address was appended by a trusted proxy:
+/// anything on their left may have been forged by the client.
+pub fn client_ip(peer: IpAddr, forwarded_for: &[&str], trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+    let forwarded = forwarded_for
+        .iter()
+        .flat_map(|header| header.split(','))
+        .map(|address| address.trim())
+        .collect::<Vec<_>>();
+    for address in forwarded.into_iter().rev() {
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+        match address.parse() {
+            Ok(address) => client = address,
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{client_ip, RateLimitDecision, RateLimitRule, RateLimitState};
+    use crate::configuration::{RateLimitAlgorithm, RateLimitRuleSettings};
+    use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+    use claim::assert_matches;
+    use std::net::IpAddr;
+
+    fn rule(algorithm: RateLimitAlgorithm, limit: u32, period_seconds: u32) -> RateLimitRule {
+        RateLimitRule::new(
+            0,
+            &RateLimitRuleSettings {
+                path: "/subscriptions".into(),
+                method: Some("post".into()),
+                algorithm,
+                limit,
+                period_seconds,
+                form_field: None,
+            },
+        )
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap())
+    }
+
+    // the decision of each request, made one after the other
+    fn decisions(rule: &RateLimitRule, times: &[DateTime<Utc>]) -> Vec<RateLimitDecision> {
+        let mut state = RateLimitState::default();
+        times
+            .iter()
+            .map(|now| {
+                let (new_state, decision) = rule.apply(state, *now);
+                state = new_state;
+                decision
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_fixed_window_allows_the_limit_then_waits_for_the_next_window() {
+        let rule = rule(RateLimitAlgorithm::FixedWindow, 2, 60);
+        let now = utc("2030-01-07 10:00:15");
+
+        let decisions = decisions(&rule, &[now, now, now, now + Duration::seconds(45)]);
+
+        assert_eq!(decisions[0], RateLimitDecision::Allowed);
+        assert_eq!(decisions[1], RateLimitDecision::Allowed);
+        assert_eq!(
+            decisions[2],
+            RateLimitDecision::Limited {
+                retry_after: Duration::seconds(45)
+            }
+        );
+        assert_eq!(decisions[3], RateLimitDecision::Allowed);
+    }
+
+    #[test]
+    fn gcra_allows_a_burst_then_spaces_the_requests() {
+        let rule = rule(RateLimitAlgorithm::Gcra, 2, 60);
+        let now = utc("2030-01-07 10:00:15");
+
+        let decisions = decisions(
+            &rule,
+            &[now, now, now, now + Duration::seconds(30), now + Duration::seconds(31)],
+        );
+
+        assert_eq!(decisions[0], RateLimitDecision::Allowed);
+        assert_eq!(decisions[1], RateLimitDecision::Allowed);
+        assert_eq!(
+            decisions[2],
+            RateLimitDecision::Limited {
+                retry_after: Duration::seconds(30)
+            }
+        );
+        assert_eq!(decisions[3], RateLimitDecision::Allowed);
+        assert_matches!(decisions[4], RateLimitDecision::Limited { .. });
+    }
+
+    #[test]
+    fn rules_match_their_path_and_method() {
+        let rule = rule(RateLimitAlgorithm::Gcra, 2, 60);
+
+        assert!(rule.matches("POST", "/subscriptions"));
+        assert!(!rule.matches("GET", "/subscriptions"));
+        assert!(!rule.matches("POST", "/subscriptions/confirm"));
+    }
+
+    #[test]
+    fn forwarded_addresses_are_ignored_without_a_trusted_proxy() {
+        let peer: IpAddr = "203.0.113.7".parse().unwrap();
+
+        let client = client_ip(peer, &["198.51.100.1"], &[]);
+
+        assert_eq!(client, peer);
+    }
+
+    #[test]
+    fn the_client_is_the_first_address_not_appended_by_a_trusted_proxy() {
+        let proxies: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+
+        // the client forged the first address, then went through both proxies
+        let client = client_ip(
+            "10.0.0.2".parse().unwrap(),
+            &["192.0.2.99, 198.51.100.1", "10.0.0.1"],
+            &proxies,
+        );
+
+        assert_eq!(client, "198.51.100.1".parse::<IpAddr>().unwrap());
+    }
+}
@@ -0,0 +1,90 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::rate_limiting::{Check, RateLimitDecision, RateLimitRule, RateLimitState, RateLimitStore};
+
+// expired counters are deleted once every this many checks
+const PRUNE_INTERVAL: u64 = 1_000;
+
+/// Keeps the counters in Postgres, shared by every instance of the application.
+pub struct PostgresStore {
+    pool: PgPool,
+    checks: AtomicU64,
+}
+
+impl PostgresStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            checks: AtomicU64::new(0),
+        }
+    }
+
+    async fn count(
+        &self,
+        rule: &RateLimitRule,
+        key: &str,
+        now: DateTime<Utc>,
+    ) -> Result<RateLimitDecision, anyhow::Error> {
+        if self.checks.fetch_add(1, Ordering::Relaxed) % PRUNE_INTERVAL == 0 {
+            sqlx::query!("DELETE FROM rate_limit_counters WHERE expires_at < $1", now)
+                .execute(&self.pool)
+                .await
+                .context("Failed to delete the expired rate limiting counters.")?;
+        }
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool.")?;
+        // the row exists before it is locked, so concurrent requests of a new client wait for each other
+        let initial = RateLimitState::default();
+        sqlx::query!(
+            r#"
+            INSERT INTO rate_limit_counters (key, at, count, expires_at)
+            VALUES ($1, $2, $3, $2)
+            ON CONFLICT (key) DO NOTHING
+            "#,
+            key,
+            initial.at,
+            initial.count,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to insert the rate limiting counter.")?;
+        let row = sqlx::query!(
+            "SELECT at, count FROM rate_limit_counters WHERE key = $1 FOR UPDATE",
+            key,
+        )
+        .fetch_one(&mut transaction)
+        .await
+        .context("Failed to lock the rate limiting counter.")?;
+        let state = RateLimitState {
+            at: row.at,
+            count: row.count,
+        };
+        let (state, decision) = rule.apply(state, now);
+        sqlx::query!(
+            "UPDATE rate_limit_counters SET at = $2, count = $3, expires_at = $4 WHERE key = $1",
+            key,
+            state.at,
+            state.count,
+            rule.expires_at(&state),
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to update the rate limiting counter.")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the rate limiting counter.")?;
+        Ok(decision)
+    }
+}
+
+impl RateLimitStore for PostgresStore {
+    fn check<'a>(&'a self, rule: &'a RateLimitRule, key: &'a str, now: DateTime<Utc>) -> Check<'a> {
+        Box::pin(self.count(rule, key, now))
+    }
+}
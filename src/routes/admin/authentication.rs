@@ -0,0 +1,77 @@
+use actix_web::dev::Payload;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use std::future::{ready, Ready};
+use crate::configuration::AdminSettings;
+use crate::routes::{basic_authentication, error_chain_fmt, ProblemDetails};
+
+/// An editor who authenticated through basic auth.
+pub struct Admin {
+    pub username: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum AdminAuthError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AdminAuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for AdminAuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AdminAuthError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            AdminAuthError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = ProblemDetails::from_response_error(self).response();
+        if let AdminAuthError::AuthError(_) = self {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(r#"Basic realm="admin""#),
+            );
+        }
+        response
+    }
+}
+
+impl FromRequest for Admin {
+    type Error = AdminAuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let settings = match request.app_data::<web::Data<AdminSettings>>() {
+            Some(settings) => settings,
+            None => {
+                return ready(Err(AdminAuthError::UnexpectedError(anyhow::anyhow!(
+                    "The admin settings are not registered."
+                ))))
+            }
+        };
+        ready(authenticate(request, settings).map_err(AdminAuthError::AuthError))
+    }
+}
+
+fn authenticate(request: &HttpRequest, settings: &AdminSettings) -> Result<Admin, anyhow::Error> {
+    let credentials = basic_authentication(request.headers())?;
+    // every account is checked, so the time taken doesn't reveal which usernames exist
+    let matches = settings.users.iter().fold(false, |found, user| {
+        credentials.matches(&user.username, &user.password) | found
+    });
+    if !matches {
+        anyhow::bail!("Invalid username or password.");
+    }
+    Ok(Admin {
+        username: credentials.username,
+    })
+}
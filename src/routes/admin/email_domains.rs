@@ -0,0 +1,177 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::http::StatusCode;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use crate::domain::{parent_domains, split_email, DomainRule, EmailPolicy, SubscriberEmail};
+use crate::routes::{error_chain_fmt, Admin, ProblemDetails};
+
+const MAX_DOMAIN_LENGTH: usize = 253;
+
+/// A rule set by admins for the addresses of a domain and its subdomains.
+#[derive(serde::Serialize)]
+pub struct EmailDomainRule {
+    pub domain: String,
+    pub rule: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailDomainRuleData {
+    rule: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum EmailDomainError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0} has no rule.")]
+    NotFound(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for EmailDomainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for EmailDomainError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            EmailDomainError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            EmailDomainError::NotFound(_) => StatusCode::NOT_FOUND,
+            EmailDomainError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ProblemDetails::from_response_error(self).response()
+    }
+}
+
+#[tracing::instrument(name = "List email domain rules", skip(_admin, pool))]
+pub async fn get_email_domain_rules(
+    _admin: Admin,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, EmailDomainError> {
+    let rules = sqlx::query_as!(
+        EmailDomainRule,
+        "SELECT domain, rule, updated_at FROM email_domain_rules ORDER BY domain"
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("Failed to retrieve the email domain rules.")?;
+    Ok(HttpResponse::Ok().json(rules))
+}
+
+/// Allow or deny the addresses of a domain, replacing its previous rule.
+#[tracing::instrument(name = "Set an email domain rule", skip(_admin, body, pool))]
+pub async fn set_email_domain_rule(
+    _admin: Admin,
+    domain: web::Path<String>,
+    body: web::Json<EmailDomainRuleData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, EmailDomainError> {
+    let domain = parse_domain(&domain).map_err(EmailDomainError::ValidationError)?;
+    let rule = DomainRule::parse(&body.rule).map_err(EmailDomainError::ValidationError)?;
+    let rule = sqlx::query_as!(
+        EmailDomainRule,
+        r#"
+        INSERT INTO email_domain_rules (domain, rule, updated_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (domain) DO UPDATE SET rule = EXCLUDED.rule, updated_at = EXCLUDED.updated_at
+        RETURNING domain, rule, updated_at
+        "#,
+        domain,
+        rule.as_str(),
+        Utc::now()
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .context("Failed to store the email domain rule.")?;
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+#[tracing::instrument(name = "Delete an email domain rule", skip(_admin, pool))]
+pub async fn delete_email_domain_rule(
+    _admin: Admin,
+    domain: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, EmailDomainError> {
+    let domain = domain.into_inner().trim().to_lowercase();
+    let deleted = sqlx::query!("DELETE FROM email_domain_rules WHERE domain = $1", domain)
+        .execute(pool.as_ref())
+        .await
+        .context("Failed to delete the email domain rule.")?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(EmailDomainError::NotFound(domain));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Why the policy refuses `email`, `None` when it is accepted.
+/// The rule of the most specific domain of the address applies.
+#[tracing::instrument(name = "Check the email policy", skip(pool, policy))]
+pub async fn find_email_rejection(
+    pool: &PgPool,
+    policy: &EmailPolicy,
+    email: &SubscriberEmail,
+) -> Result<Option<String>, anyhow::Error> {
+    let (_, domain) = split_email(email.as_ref());
+    let domains: Vec<String> = parent_domains(&domain).map(String::from).collect();
+    let rule = sqlx::query!(
+        r#"
+        SELECT rule FROM email_domain_rules
+        WHERE domain = ANY($1)
+        ORDER BY LENGTH(domain) DESC
+        LIMIT 1
+        "#,
+        &domains
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the rule of the email domain.")?;
+    let rule = rule
+        .map(|r| DomainRule::parse(&r.rule))
+        .transpose()
+        .map_err(|e| anyhow::anyhow!(e))?;
+    Ok(policy.check(email, rule))
+}
+
+fn parse_domain(domain: &str) -> Result<String, String> {
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    let is_valid = !domain.is_empty()
+        && domain.len() <= MAX_DOMAIN_LENGTH
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if is_valid {
+        Ok(domain)
+    } else {
+        Err(format!("{} is not a valid domain.", domain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_domain;
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn domains_are_lowercased() {
+        assert_ok_eq!(parse_domain(" Mailinator.COM. "), "mailinator.com".to_string());
+    }
+
+    #[test]
+    fn malformed_domains_are_rejected() {
+        for domain in ["", "mail..com", "-mail.com", "mail_box.com", "mail.com/x"] {
+            assert_err!(parse_domain(domain), "{} was accepted", domain);
+        }
+    }
+}
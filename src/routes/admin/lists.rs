@@ -0,0 +1,141 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::http::StatusCode;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+use crate::domain::ListSlug;
+use crate::routes::{error_chain_fmt, Admin, ProblemDetails};
+
+/// The list everybody joins when no list is picked explicitly.
+pub const DEFAULT_LIST_SLUG: &str = "newsletter";
+
+#[derive(serde::Serialize)]
+pub struct MailingList {
+    pub id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewListData {
+    slug: String,
+    name: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum ListError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("A list with slug {0} already exists.")]
+    Conflict(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ListError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ListError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ListError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ListError::Conflict(_) => StatusCode::CONFLICT,
+            ListError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ProblemDetails::from_response_error(self).response()
+    }
+}
+
+#[tracing::instrument(name = "List mailing lists", skip(_admin, pool))]
+pub async fn get_lists(_admin: Admin, pool: web::Data<PgPool>) -> Result<HttpResponse, ListError> {
+    let lists = get_all_lists(pool.as_ref())
+        .await
+        .context("Failed to retrieve the mailing lists.")?;
+    Ok(HttpResponse::Ok().json(lists))
+}
+
+#[tracing::instrument(
+    name = "Create a mailing list",
+    skip(_admin, body, pool),
+    fields(list_slug = %body.slug)
+)]
+pub async fn create_list(
+    _admin: Admin,
+    body: web::Json<NewListData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ListError> {
+    let slug = ListSlug::parse(body.0.slug).map_err(ListError::ValidationError)?;
+    let name = body.0.name.trim().to_string();
+    if name.is_empty() {
+        return Err(ListError::ValidationError("The list name can't be empty.".into()));
+    }
+    let list = MailingList {
+        id: Uuid::new_v4(),
+        slug: slug.as_ref().to_string(),
+        name,
+        created_at: Utc::now(),
+    };
+    let outcome = sqlx::query!(
+        r#"
+        INSERT INTO lists (id, slug, name, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        list.id,
+        list.slug,
+        list.name,
+        list.created_at
+    )
+    .execute(pool.as_ref())
+    .await
+    .context("Failed to insert a new mailing list.")?;
+    if outcome.rows_affected() == 0 {
+        return Err(ListError::Conflict(list.slug));
+    }
+    Ok(HttpResponse::Created().json(list))
+}
+
+#[tracing::instrument(name = "Get all mailing lists", skip(executor))]
+pub async fn get_all_lists(
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"SELECT id, slug, name, created_at FROM lists ORDER BY created_at, slug"#
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[tracing::instrument(name = "Get mailing lists by slug", skip(executor))]
+pub async fn get_lists_by_slug(
+    executor: impl PgExecutor<'_>,
+    slugs: &[ListSlug],
+) -> Result<Vec<MailingList>, sqlx::Error> {
+    let slugs: Vec<String> = slugs.iter().map(|s| s.as_ref().to_string()).collect();
+    sqlx::query_as!(
+        MailingList,
+        r#"SELECT id, slug, name, created_at FROM lists WHERE slug = ANY($1)"#,
+        &slugs[..]
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Return the first requested slug that didn't match any list.
+pub fn find_unknown_slug<'a>(
+    requested: &'a [ListSlug],
+    found: &[MailingList],
+) -> Option<&'a ListSlug> {
+    requested
+        .iter()
+        .find(|slug| !found.iter().any(|l| l.slug == slug.as_ref()))
+}
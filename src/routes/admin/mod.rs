@@ -0,0 +1,12 @@
+mod authentication;
+mod email_domains;
+mod lists;
+mod newsletters;
+mod subscribers;
+mod suppressions;
+pub use authentication::*;
+pub use email_domains::*;
+pub use lists::*;
+pub use newsletters::*;
+pub use subscribers::*;
+pub use suppressions::*;
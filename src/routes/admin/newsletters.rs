@@ -0,0 +1,551 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::http::StatusCode;
+use anyhow::Context;
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::domain::{DeliveryState, IssueVisibility};
+use crate::routes::{
+    error_chain_fmt, Admin, DeliveryWindow, ProblemDetails, Throttle, HARD_BOUNCE_TYPES,
+};
+
+// how far the list growth series reaches on each side of the publication
+const GROWTH_WINDOW_DAYS: i64 = 30;
+const MAX_REASON_LENGTH: usize = 1024;
+
+#[derive(thiserror::Error)]
+pub enum NewsletterAdminError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no newsletter issue with id {0}.")]
+    UnknownIssue(Uuid),
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for NewsletterAdminError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for NewsletterAdminError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            NewsletterAdminError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            NewsletterAdminError::UnknownIssue(_) => StatusCode::NOT_FOUND,
+            NewsletterAdminError::Conflict(_) => StatusCode::CONFLICT,
+            NewsletterAdminError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ProblemDetails::from_response_error(self).response()
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct VisibilityData {
+    visibility: String,
+}
+
+#[derive(serde::Serialize)]
+struct IssueVisibilityRecord {
+    issue_id: Uuid,
+    slug: String,
+    visibility: String,
+}
+
+#[derive(serde::Serialize)]
+struct IssueStats {
+    issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+    tracking_enabled: bool,
+    sent: i64,
+    failed: i64,
+    bounced: i64,
+    unsubscribes: i64,
+    opens: i64,
+    unique_opens: i64,
+    clicks: i64,
+    unique_clicks: i64,
+    links: Vec<LinkStats>,
+    growth: Vec<DailyGrowth>,
+}
+
+#[derive(serde::Serialize)]
+struct LinkStats {
+    url: String,
+    clicks: i64,
+    unique_clicks: i64,
+}
+
+#[derive(serde::Serialize)]
+struct DailyGrowth {
+    day: NaiveDate,
+    new_subscribers: i64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeliveryChangeData {
+    reason: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct DeliveryChange {
+    issue_id: Uuid,
+    delivery_state: String,
+    // deliveries still queued, held while the issue is paused
+    pending: i64,
+    // deliveries discarded by this change
+    cancelled: u64,
+}
+
+#[derive(serde::Serialize)]
+struct IssueProgress {
+    issue_id: Uuid,
+    delivery_state: String,
+    total: i64,
+    sent: i64,
+    failed: i64,
+    // subscribers who left the list before their delivery went out
+    skipped: i64,
+    pending: i64,
+    cancelled: i64,
+    throttle: Option<ThrottleSettings>,
+    // when the pending deliveries should be sent, if the worker keeps up
+    estimated_completion: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+struct ThrottleSettings {
+    max_per_hour: Option<i32>,
+    window_start: Option<NaiveTime>,
+    window_end: Option<NaiveTime>,
+    timezone: Option<String>,
+}
+
+/// Delivery, engagement and list growth figures of a published issue.
+/// Every count is served by an index on the issue id,
+/// so the cost doesn't grow with the number of issues.
+#[tracing::instrument(name = "Get the engagement stats of an issue", skip(_admin, pool))]
+pub async fn get_issue_stats(
+    _admin: Admin,
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, NewsletterAdminError> {
+    let issue_id = issue_id.into_inner();
+    let issue = sqlx::query!(
+        r#"
+        SELECT list_id, title, published_at, tracking_enabled
+        FROM newsletter_issues
+        WHERE id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve the issue.")?
+    .ok_or(NewsletterAdminError::UnknownIssue(issue_id))?;
+    let deliveries = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE status = 'sent') AS "sent!",
+            COUNT(*) FILTER (WHERE status = 'failed') AS "failed!"
+        FROM newsletter_deliveries
+        WHERE issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .context("Failed to count the deliveries of the issue.")?;
+    let bounced = sqlx::query!(
+        r#"
+        SELECT COUNT(DISTINCT email) AS "bounced!"
+        FROM email_events
+        WHERE issue_id = $1 AND record_type = 'Bounce' AND event_type = ANY($2)
+        "#,
+        issue_id,
+        &HARD_BOUNCE_TYPES.map(String::from)[..]
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .context("Failed to count the bounces of the issue.")?
+    .bounced;
+    // an unsubscribe is attributed to the last issue the subscriber received before it
+    let unsubscribes = sqlx::query!(
+        r#"
+        SELECT COUNT(DISTINCT d.subscriber_id) AS "unsubscribes!"
+        FROM newsletter_deliveries d
+        JOIN subscriber_audit_log a
+            ON a.subscriber_id = d.subscriber_id
+            AND a.action = 'unsubscribed'
+            AND a.occurred_at >= d.attempted_at
+        WHERE d.issue_id = $1 AND d.status = 'sent'
+            AND NOT EXISTS (
+                SELECT 1 FROM newsletter_deliveries later
+                WHERE later.subscriber_id = d.subscriber_id
+                    AND later.status = 'sent'
+                    AND later.attempted_at > d.attempted_at
+                    AND later.attempted_at <= a.occurred_at
+            )
+        "#,
+        issue_id
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .context("Failed to count the unsubscribes attributable to the issue.")?
+    .unsubscribes;
+    let totals = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE kind = 'open') AS "opens!",
+            COUNT(DISTINCT subscriber_id) FILTER (WHERE kind = 'open') AS "unique_opens!",
+            COUNT(*) FILTER (WHERE kind = 'click') AS "clicks!",
+            COUNT(DISTINCT subscriber_id) FILTER (WHERE kind = 'click') AS "unique_clicks!"
+        FROM tracking_events
+        WHERE issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .context("Failed to count the tracking events of the issue.")?;
+    let links = sqlx::query_as!(
+        LinkStats,
+        r#"
+        SELECT
+            url AS "url!",
+            COUNT(*) AS "clicks!",
+            COUNT(DISTINCT subscriber_id) AS "unique_clicks!"
+        FROM tracking_events
+        WHERE issue_id = $1 AND kind = 'click'
+        GROUP BY url
+        ORDER BY 2 DESC, 1
+        "#,
+        issue_id
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("Failed to count the clicks per link of the issue.")?;
+    let growth = get_list_growth(
+        pool.as_ref(),
+        issue.list_id,
+        issue.published_at - Duration::days(GROWTH_WINDOW_DAYS),
+        std::cmp::min(issue.published_at + Duration::days(GROWTH_WINDOW_DAYS), Utc::now()),
+    )
+    .await
+    .context("Failed to compute the growth of the list.")?;
+    Ok(HttpResponse::Ok().json(IssueStats {
+        issue_id,
+        title: issue.title,
+        published_at: issue.published_at,
+        tracking_enabled: issue.tracking_enabled,
+        sent: deliveries.sent,
+        failed: deliveries.failed,
+        bounced,
+        unsubscribes,
+        opens: totals.opens,
+        unique_opens: totals.unique_opens,
+        clicks: totals.clicks,
+        unique_clicks: totals.unique_clicks,
+        links,
+        growth,
+    }))
+}
+
+/// New confirmed members of a list for each day between `from` and `to`, in UTC.
+/// Days without new members are included with a count of zero.
+#[tracing::instrument(name = "Get the daily growth of a list", skip(pool))]
+async fn get_list_growth(
+    pool: &PgPool,
+    list_id: Uuid,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<DailyGrowth>, sqlx::Error> {
+    sqlx::query_as!(
+        DailyGrowth,
+        r#"
+        SELECT days.day AS "day!", COUNT(s.id) AS "new_subscribers!"
+        FROM (
+            SELECT generate_series(
+                ($2::timestamptz AT TIME ZONE 'UTC')::date,
+                ($3::timestamptz AT TIME ZONE 'UTC')::date,
+                interval '1 day'
+            )::date AS day
+        ) days
+        LEFT JOIN subscriptions s
+            ON s.subscribed_at >= days.day::timestamp AT TIME ZONE 'UTC'
+            AND s.subscribed_at < (days.day + 1)::timestamp AT TIME ZONE 'UTC'
+            AND EXISTS (
+                SELECT 1 FROM list_memberships m
+                WHERE m.subscriber_id = s.id AND m.list_id = $1 AND m.status = 'confirmed'
+            )
+        GROUP BY days.day
+        ORDER BY days.day
+        "#,
+        list_id,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Publish an issue to the web archive, restrict it to subscribers or take it down.
+#[tracing::instrument(name = "Update the visibility of an issue", skip(_admin, body, pool))]
+pub async fn update_issue_visibility(
+    _admin: Admin,
+    issue_id: web::Path<Uuid>,
+    body: web::Json<VisibilityData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, NewsletterAdminError> {
+    let issue_id = issue_id.into_inner();
+    let visibility = IssueVisibility::parse(&body.0.visibility)
+        .map_err(NewsletterAdminError::ValidationError)?;
+    let record = sqlx::query_as!(
+        IssueVisibilityRecord,
+        r#"
+        UPDATE newsletter_issues SET visibility = $2, updated_at = $3
+        WHERE id = $1
+        RETURNING id AS issue_id, slug, visibility
+        "#,
+        issue_id,
+        visibility.as_str(),
+        Utc::now()
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to update the visibility of the issue.")?
+    .ok_or(NewsletterAdminError::UnknownIssue(issue_id))?;
+    Ok(HttpResponse::Ok().json(record))
+}
+
+/// How far the deliveries of an issue have gone and when the remainder should be sent.
+#[tracing::instrument(name = "Get the delivery progress of an issue", skip(_admin, pool))]
+pub async fn get_issue_progress(
+    _admin: Admin,
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, NewsletterAdminError> {
+    let issue_id = issue_id.into_inner();
+    let progress = sqlx::query!(
+        r#"
+        SELECT
+            i.delivery_state,
+            COUNT(d.subscriber_id) AS "total!",
+            COUNT(*) FILTER (WHERE d.status = 'sent') AS "sent!",
+            COUNT(*) FILTER (WHERE d.status = 'failed') AS "failed!",
+            COUNT(*) FILTER (WHERE d.status = 'skipped') AS "skipped!",
+            COUNT(*) FILTER (WHERE d.status = 'pending') AS "pending!",
+            COUNT(*) FILTER (WHERE d.status = 'cancelled') AS "cancelled!"
+        FROM newsletter_issues i
+        LEFT JOIN newsletter_deliveries d ON d.issue_id = i.id
+        WHERE i.id = $1
+        GROUP BY i.id
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to count the deliveries of the issue.")?
+    .ok_or(NewsletterAdminError::UnknownIssue(issue_id))?;
+    let throttle = sqlx::query_as!(
+        ThrottleSettings,
+        r#"
+        SELECT max_per_hour, window_start, window_end, timezone
+        FROM send_throttles
+        WHERE issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve the throttle of the issue.")?;
+    // a paused issue won't complete until it is resumed
+    let estimated_completion = match &throttle {
+        Some(_) if progress.delivery_state != DeliveryState::Active.as_str() => None,
+        Some(settings) => settings
+            .as_throttle()
+            .context("The stored throttle of the issue is invalid.")?
+            .estimate_completion(progress.pending, Utc::now()),
+        None => None,
+    };
+    Ok(HttpResponse::Ok().json(IssueProgress {
+        issue_id,
+        delivery_state: progress.delivery_state,
+        total: progress.total,
+        sent: progress.sent,
+        failed: progress.failed,
+        skipped: progress.skipped,
+        pending: progress.pending,
+        cancelled: progress.cancelled,
+        throttle,
+        estimated_completion,
+    }))
+}
+
+impl ThrottleSettings {
+    fn as_throttle(&self) -> Result<Throttle, anyhow::Error> {
+        let window = match (self.window_start, self.window_end, &self.timezone) {
+            (Some(start), Some(end), Some(timezone)) => Some(DeliveryWindow {
+                start,
+                end,
+                timezone: timezone.parse().map_err(|e| anyhow::anyhow!("{}", e))?,
+            }),
+            _ => None,
+        };
+        Ok(Throttle {
+            max_per_hour: self.max_per_hour.map(|n| n as u32),
+            window,
+        })
+    }
+}
+
+/// Hold the queued deliveries of an issue until it is resumed.
+/// An issue being sent by `publish_newsletter` stops before its next delivery.
+#[tracing::instrument(name = "Pause the deliveries of an issue", skip(admin, body, pool))]
+pub async fn pause_issue(
+    admin: Admin,
+    issue_id: web::Path<Uuid>,
+    body: web::Json<DeliveryChangeData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, NewsletterAdminError> {
+    change_delivery_state(&pool, issue_id.into_inner(), &admin, body.0, DeliveryState::Paused).await
+}
+
+/// Let the worker send the deliveries held by a pause.
+#[tracing::instrument(name = "Resume the deliveries of an issue", skip(admin, body, pool))]
+pub async fn resume_issue(
+    admin: Admin,
+    issue_id: web::Path<Uuid>,
+    body: web::Json<DeliveryChangeData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, NewsletterAdminError> {
+    change_delivery_state(&pool, issue_id.into_inner(), &admin, body.0, DeliveryState::Active).await
+}
+
+/// Discard the queued deliveries of an issue. There is no coming back from it.
+#[tracing::instrument(name = "Cancel the deliveries of an issue", skip(admin, body, pool))]
+pub async fn cancel_issue(
+    admin: Admin,
+    issue_id: web::Path<Uuid>,
+    body: web::Json<DeliveryChangeData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, NewsletterAdminError> {
+    change_delivery_state(&pool, issue_id.into_inner(), &admin, body.0, DeliveryState::Cancelled).await
+}
+
+// The issue row is locked so that concurrent changes are applied one after the other.
+// Deliveries being sent by the worker are locked too, a cancellation waits for them.
+async fn change_delivery_state(
+    pool: &PgPool,
+    issue_id: Uuid,
+    admin: &Admin,
+    data: DeliveryChangeData,
+    target: DeliveryState,
+) -> Result<HttpResponse, NewsletterAdminError> {
+    // the audit log records who asked for the change
+    let actor = admin.username.as_str();
+    let reason = data.reason.as_deref().map(str::trim).filter(|r| !r.is_empty());
+    if reason.map_or(false, |r| r.chars().count() > MAX_REASON_LENGTH) {
+        return Err(NewsletterAdminError::ValidationError(format!(
+            "The reason is longer than {} characters.",
+            MAX_REASON_LENGTH
+        )));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let current = sqlx::query!(
+        r#"SELECT delivery_state FROM newsletter_issues WHERE id = $1 FOR UPDATE"#,
+        issue_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the delivery state of the issue.")?
+    .ok_or(NewsletterAdminError::UnknownIssue(issue_id))?
+    .delivery_state;
+    let current = DeliveryState::parse(&current).map_err(|e| anyhow::anyhow!(e))?;
+    let (allowed, action) = match target {
+        DeliveryState::Paused => (current == DeliveryState::Active, "paused"),
+        DeliveryState::Active => (current == DeliveryState::Paused, "resumed"),
+        DeliveryState::Cancelled => (current != DeliveryState::Cancelled, "cancelled"),
+    };
+    if !allowed {
+        return Err(NewsletterAdminError::Conflict(format!(
+            "The deliveries of issue {} are {}, they can't be {}.",
+            issue_id,
+            current.as_str(),
+            action
+        )));
+    }
+    let now = Utc::now();
+    sqlx::query!(
+        r#"UPDATE newsletter_issues SET delivery_state = $2 WHERE id = $1"#,
+        issue_id,
+        target.as_str()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the delivery state of the issue.")?;
+    let cancelled = if target == DeliveryState::Cancelled {
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_deliveries SET status = 'cancelled', attempted_at = $2
+            WHERE issue_id = $1 AND status = 'pending'
+            "#,
+            issue_id,
+            now
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to cancel the queued deliveries of the issue.")?
+        .rows_affected()
+    } else {
+        0
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_audit_log (id, issue_id, action, actor, reason, occurred_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        issue_id,
+        action,
+        actor,
+        reason,
+        now
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to record the change in the audit log of the issue.")?;
+    let pending = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "pending!"
+        FROM newsletter_deliveries
+        WHERE issue_id = $1 AND status = 'pending'
+        "#,
+        issue_id
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to count the queued deliveries of the issue.")?
+    .pending;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the change of the delivery state.")?;
+    tracing::info!(%issue_id, actor, action, "Changed the delivery state of an issue");
+    Ok(HttpResponse::Ok().json(DeliveryChange {
+        issue_id,
+        delivery_state: target.as_str().into(),
+        pending,
+        cancelled,
+    }))
+}
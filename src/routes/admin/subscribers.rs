@@ -0,0 +1,187 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::http::StatusCode;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::domain::{is_valid_attribute_key, SubscriberTag};
+use crate::routes::{error_chain_fmt, record_subscriber_change, Admin, ProblemDetails};
+
+#[derive(serde::Deserialize)]
+pub struct TagData {
+    tag: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscriberAdminError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no subscriber with id {0}.")]
+    UnknownSubscriber(Uuid),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscriberAdminError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriberAdminError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscriberAdminError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscriberAdminError::UnknownSubscriber(_) => StatusCode::NOT_FOUND,
+            SubscriberAdminError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ProblemDetails::from_response_error(self).response()
+    }
+}
+
+#[tracing::instrument(name = "Tag a subscriber", skip(_admin, body, pool))]
+pub async fn tag_subscriber(
+    _admin: Admin,
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<TagData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberAdminError> {
+    let subscriber_id = subscriber_id.into_inner();
+    let tag = SubscriberTag::parse(body.0.tag).map_err(SubscriberAdminError::ValidationError)?;
+    let mut transaction = begin_for_subscriber(&pool, subscriber_id).await?;
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag, created_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        tag.as_ref(),
+        Utc::now()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to tag the subscriber.")?;
+    if inserted.rows_affected() > 0 {
+        record_subscriber_change(&mut transaction, subscriber_id, "tag_added", None, Some(tag.as_ref()))
+            .await
+            .context("Failed to record the new tag.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to tag a subscriber.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Untag a subscriber", skip(_admin, pool))]
+pub async fn untag_subscriber(
+    _admin: Admin,
+    path: web::Path<(Uuid, String)>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberAdminError> {
+    let (subscriber_id, tag) = path.into_inner();
+    let mut transaction = begin_for_subscriber(&pool, subscriber_id).await?;
+    let deleted = sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag = $2"#,
+        subscriber_id,
+        tag
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to untag the subscriber.")?;
+    if deleted.rows_affected() > 0 {
+        record_subscriber_change(&mut transaction, subscriber_id, "tag_removed", Some(&tag), None)
+            .await
+            .context("Failed to record the tag removal.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to untag a subscriber.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Merge the submitted object into the subscriber attributes,
+/// a `null` value removes the attribute.
+#[tracing::instrument(name = "Update subscriber attributes", skip(_admin, body, pool))]
+pub async fn update_subscriber_attributes(
+    _admin: Admin,
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<serde_json::Value>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberAdminError> {
+    let subscriber_id = subscriber_id.into_inner();
+    let attributes = body.0;
+    let object = attributes.as_object().ok_or_else(|| {
+        SubscriberAdminError::ValidationError("Attributes must be a JSON object.".into())
+    })?;
+    for (key, value) in object {
+        if !is_valid_attribute_key(key) {
+            return Err(SubscriberAdminError::ValidationError(format!(
+                "{} is not a valid attribute name.",
+                key
+            )));
+        }
+        if value.is_array() || value.is_object() {
+            return Err(SubscriberAdminError::ValidationError(format!(
+                "The value of {} must be a string, a number, a boolean or null.",
+                key
+            )));
+        }
+    }
+
+    let mut transaction = begin_for_subscriber(&pool, subscriber_id).await?;
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET attributes = jsonb_strip_nulls(attributes || $2)
+        WHERE id = $1
+        RETURNING attributes
+        "#,
+        subscriber_id,
+        attributes
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to update the subscriber attributes.")?;
+    record_subscriber_change(
+        &mut transaction,
+        subscriber_id,
+        "attributes_updated",
+        None,
+        Some(&attributes.to_string()),
+    )
+    .await
+    .context("Failed to record the attributes update.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update subscriber attributes.")?;
+    Ok(HttpResponse::Ok().json(updated.attributes))
+}
+
+// start a transaction, making sure the subscriber exists first
+async fn begin_for_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Transaction<'static, Postgres>, SubscriberAdminError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let exists = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to look up the subscriber.")?;
+    if exists.is_none() {
+        return Err(SubscriberAdminError::UnknownSubscriber(subscriber_id));
+    }
+    Ok(transaction)
+}
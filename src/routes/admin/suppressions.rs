@@ -0,0 +1,301 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::http::StatusCode;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use crate::domain::SubscriberEmail;
+use crate::routes::{error_chain_fmt, Admin, ProblemDetails};
+
+/// An address we must never email again, whatever its subscription status.
+#[derive(serde::Serialize)]
+pub struct Suppression {
+    pub email: String,
+    pub reason: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewSuppressionData {
+    email: String,
+    reason: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SuppressionUpdateData {
+    reason: String,
+}
+
+#[derive(serde::Deserialize)]
+struct ImportRow {
+    email: String,
+    reason: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct ImportOutcome {
+    imported: u64,
+    skipped: u64,
+}
+
+#[derive(thiserror::Error)]
+pub enum SuppressionError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0} is already suppressed.")]
+    Conflict(String),
+    #[error("{0} is not suppressed.")]
+    NotFound(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SuppressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SuppressionError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SuppressionError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SuppressionError::Conflict(_) => StatusCode::CONFLICT,
+            SuppressionError::NotFound(_) => StatusCode::NOT_FOUND,
+            SuppressionError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ProblemDetails::from_response_error(self).response()
+    }
+}
+
+#[tracing::instrument(name = "List suppressed addresses", skip(_admin, pool))]
+pub async fn get_suppressions(
+    _admin: Admin,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SuppressionError> {
+    let suppressions = sqlx::query_as!(
+        Suppression,
+        r#"SELECT email, reason, source, created_at FROM suppressions ORDER BY created_at, email"#
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("Failed to retrieve the suppressed addresses.")?;
+    Ok(HttpResponse::Ok().json(suppressions))
+}
+
+#[tracing::instrument(name = "Get a suppressed address", skip(_admin, pool))]
+pub async fn get_suppression(
+    _admin: Admin,
+    email: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SuppressionError> {
+    let email = email.into_inner().to_lowercase();
+    let suppression = sqlx::query_as!(
+        Suppression,
+        r#"SELECT email, reason, source, created_at FROM suppressions WHERE email = $1"#,
+        email
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve the suppressed address.")?
+    .ok_or(SuppressionError::NotFound(email))?;
+    Ok(HttpResponse::Ok().json(suppression))
+}
+
+#[tracing::instrument(
+    name = "Suppress an address",
+    skip(_admin, body, pool),
+    fields(email = %body.email)
+)]
+pub async fn create_suppression(
+    _admin: Admin,
+    body: web::Json<NewSuppressionData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SuppressionError> {
+    let email = parse_email(body.0.email).map_err(SuppressionError::ValidationError)?;
+    let reason = parse_reason(&body.0.reason).map_err(SuppressionError::ValidationError)?;
+    let suppression = Suppression {
+        email,
+        reason,
+        source: "admin".into(),
+        created_at: Utc::now(),
+    };
+    let inserted = insert_suppression(pool.as_ref(), &suppression)
+        .await
+        .context("Failed to insert a new suppression.")?;
+    if !inserted {
+        return Err(SuppressionError::Conflict(suppression.email));
+    }
+    Ok(HttpResponse::Created().json(suppression))
+}
+
+#[tracing::instrument(name = "Update a suppressed address", skip(_admin, body, pool))]
+pub async fn update_suppression(
+    _admin: Admin,
+    email: web::Path<String>,
+    body: web::Json<SuppressionUpdateData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SuppressionError> {
+    let email = email.into_inner().to_lowercase();
+    let reason = parse_reason(&body.0.reason).map_err(SuppressionError::ValidationError)?;
+    let suppression = sqlx::query_as!(
+        Suppression,
+        r#"
+        UPDATE suppressions SET reason = $2
+        WHERE email = $1
+        RETURNING email, reason, source, created_at
+        "#,
+        email,
+        reason
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to update the suppression.")?
+    .ok_or(SuppressionError::NotFound(email))?;
+    Ok(HttpResponse::Ok().json(suppression))
+}
+
+#[tracing::instrument(name = "Lift the suppression of an address", skip(_admin, pool))]
+pub async fn delete_suppression(
+    _admin: Admin,
+    email: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SuppressionError> {
+    let email = email.into_inner().to_lowercase();
+    let deleted = sqlx::query!(r#"DELETE FROM suppressions WHERE email = $1"#, email)
+        .execute(pool.as_ref())
+        .await
+        .context("Failed to delete the suppression.")?;
+    if deleted.rows_affected() == 0 {
+        return Err(SuppressionError::NotFound(email));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Suppress every address of a CSV file with an `email` and an optional `reason` column.
+/// The file is imported in full or not at all, addresses already suppressed are skipped.
+#[tracing::instrument(name = "Import suppressed addresses", skip(_admin, body, pool))]
+pub async fn import_suppressions(
+    _admin: Admin,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SuppressionError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body.as_ref());
+    let mut suppressions = Vec::new();
+    let created_at = Utc::now();
+    for (i, row) in reader.deserialize::<ImportRow>().enumerate() {
+        // the header is line 1
+        let line = i + 2;
+        let row = row.map_err(|e| {
+            SuppressionError::ValidationError(format!("Line {} is not valid: {}", line, e))
+        })?;
+        let email = parse_email(row.email).map_err(|e| {
+            SuppressionError::ValidationError(format!("Line {} is not valid: {}", line, e))
+        })?;
+        let reason = match row.reason.filter(|r| !r.is_empty()) {
+            Some(reason) => parse_reason(&reason).map_err(|e| {
+                SuppressionError::ValidationError(format!("Line {} is not valid: {}", line, e))
+            })?,
+            None => "imported".into(),
+        };
+        suppressions.push(Suppression {
+            email,
+            reason,
+            source: "import".into(),
+            created_at,
+        });
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let mut imported = 0;
+    for suppression in &suppressions {
+        if insert_suppression(&mut transaction, suppression)
+            .await
+            .context("Failed to insert an imported suppression.")?
+        {
+            imported += 1;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import suppressions.")?;
+    Ok(HttpResponse::Ok().json(ImportOutcome {
+        imported,
+        skipped: suppressions.len() as u64 - imported,
+    }))
+}
+
+/// Make sure an address is never emailed again.
+/// Returns `false` when the address was already suppressed.
+#[tracing::instrument(name = "Suppress an email address", skip(executor, email))]
+pub async fn suppress_email(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    reason: &str,
+    source: &str,
+) -> Result<bool, sqlx::Error> {
+    let suppression = Suppression {
+        email: email.to_lowercase(),
+        reason: reason.into(),
+        source: source.into(),
+        created_at: Utc::now(),
+    };
+    insert_suppression(executor, &suppression).await
+}
+
+/// Every path sending an email must check this first.
+#[tracing::instrument(name = "Check whether an email address is suppressed", skip(executor, email))]
+pub async fn is_suppressed(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let suppression = sqlx::query!(
+        r#"SELECT email FROM suppressions WHERE email = $1"#,
+        email.to_lowercase()
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(suppression.is_some())
+}
+
+async fn insert_suppression(
+    executor: impl PgExecutor<'_>,
+    suppression: &Suppression,
+) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email, reason, source, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        suppression.email,
+        suppression.reason,
+        suppression.source,
+        suppression.created_at
+    )
+    .execute(executor)
+    .await?;
+    Ok(inserted.rows_affected() > 0)
+}
+
+fn parse_email(email: String) -> Result<String, String> {
+    SubscriberEmail::parse(email).map(|e| e.as_ref().to_lowercase())
+}
+
+fn parse_reason(reason: &str) -> Result<String, String> {
+    let reason = reason.trim();
+    if reason.is_empty() {
+        Err("The suppression reason can't be empty.".into())
+    } else {
+        Ok(reason.to_string())
+    }
+}
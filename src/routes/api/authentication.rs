@@ -0,0 +1,74 @@
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, Error, HttpRequest, HttpResponse, ResponseError};
+use secrecy::ExposeSecret;
+use std::future::Future;
+use crate::configuration::ApiSettings;
+use crate::routes::{bearer_token, constant_time_eq, error_chain_fmt, ProblemDetails};
+
+#[derive(thiserror::Error)]
+pub enum ApiAuthError {
+    #[error("A valid API key is required.")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiAuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiAuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiAuthError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            ApiAuthError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = ProblemDetails::from_response_error(self).response();
+        if let ApiAuthError::AuthError(_) = self {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        response
+    }
+}
+
+/// Middleware letting through the requests carrying one of our API keys,
+/// the others are refused before their body is read.
+pub fn require_api_key<S, B>(
+    request: ServiceRequest,
+    service: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+{
+    let authenticated = match request.app_data::<web::Data<ApiSettings>>() {
+        Some(settings) => {
+            authenticate(request.request(), settings).map_err(ApiAuthError::AuthError)
+        }
+        None => Err(ApiAuthError::UnexpectedError(anyhow::anyhow!(
+            "The API settings are not registered."
+        ))),
+    };
+    let response = authenticated.map(|()| service.call(request));
+    async move { response?.await }
+}
+
+fn authenticate(request: &HttpRequest, settings: &ApiSettings) -> Result<(), anyhow::Error> {
+    let token = bearer_token(request.headers())?;
+    // every key is checked, so the time taken doesn't depend on which one matches
+    let matches = settings.keys.iter().fold(false, |found, key| {
+        constant_time_eq(token.expose_secret(), key.expose_secret()) | found
+    });
+    if !matches {
+        anyhow::bail!("Invalid API key.");
+    }
+    Ok(())
+}
@@ -0,0 +1,5 @@
+mod authentication;
+mod subscriptions;
+
+pub use authentication::*;
+pub use subscriptions::*;
@@ -0,0 +1,213 @@
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::domain::{EmailPolicy, ListSlug, Locale, NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::routes::{
+    error_chain_fmt, register_subscriber, InvalidParam, ProblemDetails, SubscribeError,
+    DEFAULT_LIST_SLUG,
+};
+use crate::localization::request_locale;
+use crate::startup::{ApplicationBaseUrl, DefaultLocale};
+
+#[derive(serde::Deserialize)]
+pub struct SubscriptionRequest {
+    email: String,
+    name: String,
+    // list slugs, the default list is used when missing
+    lists: Option<Vec<String>>,
+    // the language of the subscriber's emails, the one of the Accept-Language header when missing
+    locale: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct Subscription {
+    subscriber_id: Uuid,
+    status: &'static str,
+    lists: Vec<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum ApiSubscribeError {
+    #[error("The subscription request has invalid parameters.")]
+    ValidationError(Vec<InvalidParam>),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiSubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiSubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiSubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ApiSubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ApiSubscribeError::ValidationError(invalid_params) => {
+                ProblemDetails::validation(invalid_params.clone()).response()
+            }
+            ApiSubscribeError::UnexpectedError(_) => {
+                ProblemDetails::from_response_error(self).response()
+            }
+        }
+    }
+}
+
+impl From<SubscribeError> for ApiSubscribeError {
+    fn from(e: SubscribeError) -> Self {
+        match e {
+            SubscribeError::ValidationError(reason) => {
+                Self::ValidationError(vec![InvalidParam::new("body", reason)])
+            }
+            SubscribeError::UnknownList(slug) => Self::ValidationError(vec![InvalidParam::new(
+                "lists",
+                format!("{} is not a known list.", slug),
+            )]),
+            SubscribeError::RejectedEmail(reason) => {
+                Self::ValidationError(vec![InvalidParam::new("email", reason)])
+            }
+            SubscribeError::UnexpectedError(e) => Self::UnexpectedError(e),
+        }
+    }
+}
+
+/// `POST /api/v1/subscriptions`, the JSON counterpart of the subscription form.
+/// Every invalid field is reported, not only the first one.
+/// Only applications holding one of our API keys can call it, see `require_api_key`.
+#[tracing::instrument(
+    name = "Adding a new subscriber through the API",
+    skip(request, body, pool, email_client, email_policy, base_url, default_locale),
+    fields(
+        subscriber_email = %body.email,
+        subscriber_name = %body.name
+    )
+)]
+pub async fn api_subscribe(
+    request: HttpRequest,
+    body: web::Json<SubscriptionRequest>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    email_policy: web::Data<EmailPolicy>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    default_locale: web::Data<DefaultLocale>,
+) -> Result<HttpResponse, ApiSubscribeError> {
+    let fallback_locale = request_locale(&request).unwrap_or(default_locale.0);
+    let (new_subscriber, list_slugs) = parse_request(body.into_inner(), fallback_locale)
+        .map_err(ApiSubscribeError::ValidationError)?;
+    let subscriber_id = register_subscriber(
+        &pool,
+        &email_client,
+        &email_policy,
+        &base_url.0,
+        new_subscriber,
+        &list_slugs,
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(Subscription {
+        subscriber_id,
+        status: "pending_confirmation",
+        lists: list_slugs.iter().map(|s| s.to_string()).collect(),
+    }))
+}
+
+// `fallback_locale` is used when the request doesn't pick one
+fn parse_request(
+    request: SubscriptionRequest,
+    fallback_locale: Locale,
+) -> Result<(NewSubscriber, Vec<ListSlug>), Vec<InvalidParam>> {
+    let mut invalid_params = Vec::new();
+    let name = SubscriberName::parse(request.name)
+        .map_err(|reason| invalid_params.push(InvalidParam::new("name", reason)))
+        .ok();
+    let email = SubscriberEmail::parse(request.email)
+        .map_err(|reason| invalid_params.push(InvalidParam::new("email", reason)))
+        .ok();
+    let locale = match request.locale.as_deref().map(Locale::parse) {
+        Some(Ok(locale)) => locale,
+        Some(Err(reason)) => {
+            invalid_params.push(InvalidParam::new("locale", reason));
+            fallback_locale
+        }
+        None => fallback_locale,
+    };
+    let lists = request
+        .lists
+        .unwrap_or_else(|| vec![DEFAULT_LIST_SLUG.into()]);
+    if lists.is_empty() {
+        invalid_params.push(InvalidParam::new("lists", "At least one list is required."));
+    }
+    let mut list_slugs = Vec::new();
+    for (i, slug) in lists.into_iter().enumerate() {
+        match ListSlug::parse(slug) {
+            Ok(slug) => list_slugs.push(slug),
+            Err(reason) => invalid_params.push(InvalidParam::new(format!("lists[{}]", i), reason)),
+        }
+    }
+    match (name, email) {
+        (Some(name), Some(email)) if invalid_params.is_empty() => {
+            Ok((NewSubscriber { email, name, locale }, list_slugs))
+        }
+        _ => Err(invalid_params),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_request, SubscriptionRequest};
+    use crate::domain::Locale;
+    use claim::assert_ok;
+
+    fn request(name: &str, email: &str, lists: Option<Vec<&str>>) -> SubscriptionRequest {
+        SubscriptionRequest {
+            email: email.into(),
+            name: name.into(),
+            lists: lists.map(|l| l.into_iter().map(String::from).collect()),
+            locale: None,
+        }
+    }
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        let invalid_params = match parse_request(
+            request(" ", "not-an-email", Some(vec!["Bad Slug"])),
+            Locale::En,
+        ) {
+            Ok(_) => panic!("An invalid request was accepted."),
+            Err(invalid_params) => invalid_params,
+        };
+        let names: Vec<&str> = invalid_params.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["name", "email", "lists[0]"]);
+    }
+
+    #[test]
+    fn the_default_list_is_used_when_lists_are_missing() {
+        let (_, list_slugs) = assert_ok!(parse_request(
+            request("le guin", "ursula_le_guin@gmail.com", None),
+            Locale::En
+        ));
+        assert_eq!(list_slugs.len(), 1);
+    }
+
+    #[test]
+    fn the_requested_locale_wins_over_the_fallback_one() {
+        let mut with_locale = request("le guin", "ursula_le_guin@gmail.com", None);
+        with_locale.locale = Some("fr-CA".into());
+
+        let (subscriber, _) = assert_ok!(parse_request(with_locale, Locale::En));
+        assert_eq!(subscriber.locale, Locale::Fr);
+        let (subscriber, _) = assert_ok!(parse_request(
+            request("le guin", "ursula_le_guin@gmail.com", None),
+            Locale::Fr
+        ));
+        assert_eq!(subscriber.locale, Locale::Fr);
+    }
+}
@@ -0,0 +1,235 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use actix_web::http::header::{
+    EntityTag, ETag, HttpDate, IfModifiedSince, IfNoneMatch, LastModified,
+};
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::PgPool;
+use std::time::SystemTime;
+use uuid::Uuid;
+use crate::domain::NewsletterTemplate;
+use crate::templating::escape_html;
+use crate::startup::ApplicationBaseUrl;
+use super::{anonymous_values, render_title, ArchiveError};
+
+// the most recent public issues included in the feeds
+const FEED_SIZE: i64 = 20;
+const FEED_TITLE: &str = "Newsletter archive";
+const FEED_DESCRIPTION: &str = "The most recent issues of our newsletter.";
+
+#[derive(Debug, Clone, Copy)]
+enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+struct FeedEntry {
+    id: Uuid,
+    slug: String,
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Serve the RSS feed", skip(request, pool, base_url))]
+pub async fn rss_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ArchiveError> {
+    feed(&request, &pool, &base_url.0, FeedFormat::Rss).await
+}
+
+#[tracing::instrument(name = "Serve the Atom feed", skip(request, pool, base_url))]
+pub async fn atom_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ArchiveError> {
+    feed(&request, &pool, &base_url.0, FeedFormat::Atom).await
+}
+
+/// Serve a feed, or a 304 Not Modified if the poller's copy is still current.
+/// Only the latest change to an issue is looked up to answer a conditional request,
+/// the issues themselves are loaded when the feed has to be rendered.
+async fn feed(
+    request: &HttpRequest,
+    pool: &PgPool,
+    base_url: &str,
+    format: FeedFormat,
+) -> Result<HttpResponse, ArchiveError> {
+    let last_modified = get_last_modified(pool)
+        .await
+        .context("Failed to retrieve the date of the latest change to an issue.")?;
+    let etag = EntityTag::new_strong(match last_modified {
+        Some(last_modified) => format!("{:x}", last_modified.timestamp_micros()),
+        None => "empty".into(),
+    });
+    let is_fresh = is_fresh(request, &etag, last_modified);
+    let mut response = if is_fresh {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response.insert_header(ETag(etag));
+    if let Some(last_modified) = last_modified {
+        response.insert_header(LastModified(HttpDate::from(SystemTime::from(last_modified))));
+    }
+    if is_fresh {
+        return Ok(response.finish());
+    }
+
+    let entries = get_feed_entries(pool)
+        .await
+        .context("Failed to retrieve the issues of the feed.")?;
+    let (content_type, body) = match format {
+        FeedFormat::Rss => ("application/rss+xml; charset=utf-8", render_rss(base_url, &entries)?),
+        FeedFormat::Atom => (
+            "application/atom+xml; charset=utf-8",
+            render_atom(base_url, &entries, last_modified.unwrap_or_else(Utc::now))?,
+        ),
+    };
+    Ok(response.content_type(content_type).body(body))
+}
+
+// `If-None-Match` takes precedence, `If-Modified-Since` has a one second resolution
+fn is_fresh(
+    request: &HttpRequest,
+    etag: &EntityTag,
+    last_modified: Option<DateTime<Utc>>,
+) -> bool {
+    if let Some(if_none_match) = request.get_header::<IfNoneMatch>() {
+        return match if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+    match (request.get_header::<IfModifiedSince>(), last_modified) {
+        (Some(IfModifiedSince(since)), Some(last_modified)) => {
+            let since: DateTime<Utc> = SystemTime::from(since).into();
+            last_modified.timestamp() <= since.timestamp()
+        }
+        _ => false,
+    }
+}
+
+// hidden issues count too, taking down an issue changes the feeds
+#[tracing::instrument(name = "Get the date of the latest change to an issue", skip(pool))]
+async fn get_last_modified(pool: &PgPool) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let record = sqlx::query!(r#"SELECT MAX(updated_at) AS last_modified FROM newsletter_issues"#)
+        .fetch_one(pool)
+        .await?;
+    Ok(record.last_modified)
+}
+
+#[tracing::instrument(name = "Get the issues of the feed", skip(pool))]
+async fn get_feed_entries(pool: &PgPool) -> Result<Vec<FeedEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        FeedEntry,
+        r#"
+        SELECT id, slug, title, html_content, published_at, updated_at
+        FROM newsletter_issues
+        WHERE visibility = 'public'
+        ORDER BY published_at DESC, id
+        LIMIT $1
+        "#,
+        FEED_SIZE
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// the title and the HTML content of an entry, before XML escaping
+fn render_entry(base_url: &str, entry: &FeedEntry) -> Result<(String, String), anyhow::Error> {
+    let home_url = format!("{}/", base_url);
+    let values = anonymous_values(&home_url);
+    let html_content = NewsletterTemplate::parse(&entry.html_content)
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("The content of an archived issue is not a valid template.")?
+        .render_html(&values);
+    Ok((render_title(&entry.title, &values), html_content))
+}
+
+fn render_rss(base_url: &str, entries: &[FeedEntry]) -> Result<String, anyhow::Error> {
+    let mut items = String::new();
+    for entry in entries {
+        let (title, html_content) = render_entry(base_url, entry)?;
+        items.push_str(&format!(
+            "<item>\
+            <title>{}</title>\
+            <link>{}/archive/{}</link>\
+            <guid isPermaLink=\"false\">urn:uuid:{}</guid>\
+            <pubDate>{}</pubDate>\
+            <description>{}</description>\
+            </item>",
+            escape_html(&title),
+            escape_html(base_url),
+            escape_html(&entry.slug),
+            entry.id,
+            entry.published_at.to_rfc2822(),
+            escape_html(&html_content),
+        ));
+    }
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+        <rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\
+        <channel>\
+        <title>{title}</title>\
+        <link>{base_url}/archive</link>\
+        <description>{description}</description>\
+        <atom:link href=\"{base_url}/feed.rss\" rel=\"self\" type=\"application/rss+xml\"/>\
+        {items}\
+        </channel>\
+        </rss>",
+        title = FEED_TITLE,
+        base_url = escape_html(base_url),
+        description = FEED_DESCRIPTION,
+        items = items,
+    ))
+}
+
+fn render_atom(
+    base_url: &str,
+    entries: &[FeedEntry],
+    updated: DateTime<Utc>,
+) -> Result<String, anyhow::Error> {
+    let mut items = String::new();
+    for entry in entries {
+        let (title, html_content) = render_entry(base_url, entry)?;
+        items.push_str(&format!(
+            "<entry>\
+            <id>urn:uuid:{}</id>\
+            <title>{}</title>\
+            <link rel=\"alternate\" href=\"{}/archive/{}\"/>\
+            <published>{}</published>\
+            <updated>{}</updated>\
+            <content type=\"html\">{}</content>\
+            </entry>",
+            entry.id,
+            escape_html(&title),
+            escape_html(base_url),
+            escape_html(&entry.slug),
+            entry.published_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            entry.updated_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            escape_html(&html_content),
+        ));
+    }
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+        <feed xmlns=\"http://www.w3.org/2005/Atom\">\
+        <id>{base_url}/archive</id>\
+        <title>{title}</title>\
+        <subtitle>{description}</subtitle>\
+        <updated>{updated}</updated>\
+        <link rel=\"self\" href=\"{base_url}/feed.atom\"/>\
+        <link rel=\"alternate\" href=\"{base_url}/archive\"/>\
+        {items}\
+        </feed>",
+        base_url = escape_html(base_url),
+        title = FEED_TITLE,
+        description = FEED_DESCRIPTION,
+        updated = updated.to_rfc3339_opts(SecondsFormat::Secs, true),
+        items = items,
+    ))
+}
@@ -0,0 +1,301 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::http::StatusCode;
+use actix_web::http::header::ContentType;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::domain::{IssueVisibility, MergeValues, NewsletterTemplate};
+use crate::routes::{error_chain_fmt, preferences_url, render_page, unsubscribe_url};
+use crate::templating::{escape_html, render_template};
+use crate::startup::ApplicationBaseUrl;
+
+mod feed;
+pub use feed::*;
+
+const PAGE_SIZE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveParameters {
+    // 1-based, the most recent issues come first
+    page: Option<i64>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveIssueParameters {
+    // the preferences token of a subscriber, required by subscribers-only issues
+    token: Option<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum ArchiveError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no such page in the archive.")]
+    NotFound,
+    #[error("The issue is reserved to subscribers.")]
+    SubscribersOnly,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ArchiveError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ArchiveError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ArchiveError::NotFound => StatusCode::NOT_FOUND,
+            ArchiveError::SubscribersOnly => StatusCode::FORBIDDEN,
+            ArchiveError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let (heading, message) = match self {
+            ArchiveError::ValidationError(e) => ("Invalid request", e.clone()),
+            ArchiveError::NotFound => (
+                "Page not found",
+                "This issue doesn't exist or is no longer available.".to_string(),
+            ),
+            ArchiveError::SubscribersOnly => (
+                "Subscribers only",
+                "This issue is reserved to our subscribers, \
+                please open it from the link in one of our emails."
+                    .to_string(),
+            ),
+            ArchiveError::UnexpectedError(_) => (
+                "Something went wrong",
+                "We couldn't load the archive right now, please try again later.".to_string(),
+            ),
+        };
+        let content = render_template(
+            include_str!("error.html"),
+            &[("heading", heading), ("message", &escape_html(&message))],
+        );
+        HttpResponse::build(self.status_code())
+            .content_type(ContentType::html())
+            .body(render_page(heading, &content))
+    }
+}
+
+struct ArchiveEntry {
+    slug: String,
+    title: String,
+    visibility: String,
+    published_at: DateTime<Utc>,
+    list_name: String,
+}
+
+struct ArchivedIssue {
+    list_id: Uuid,
+    title: String,
+    html_content: String,
+    visibility: String,
+    published_at: DateTime<Utc>,
+    list_name: String,
+}
+
+struct Reader {
+    name: String,
+    preferences_token: String,
+}
+
+#[tracing::instrument(name = "Show the newsletter archive", skip(parameters, pool, base_url))]
+pub async fn archive(
+    parameters: web::Query<ArchiveParameters>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ArchiveError> {
+    let page = parameters.page.unwrap_or(1);
+    if page < 1 {
+        return Err(ArchiveError::ValidationError(
+            "The page number must be a positive integer.".into(),
+        ));
+    }
+    // one more than a page, to know whether there is an older page
+    let mut entries = sqlx::query_as!(
+        ArchiveEntry,
+        r#"
+        SELECT i.slug, i.title, i.visibility, i.published_at, l.name AS list_name
+        FROM newsletter_issues i
+        JOIN lists l ON l.id = i.list_id
+        WHERE i.visibility <> 'hidden'
+        ORDER BY i.published_at DESC, i.id
+        LIMIT $1 OFFSET $2
+        "#,
+        PAGE_SIZE + 1,
+        (page - 1) * PAGE_SIZE
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("Failed to retrieve the archived issues.")?;
+    if entries.is_empty() && page > 1 {
+        return Err(ArchiveError::NotFound);
+    }
+    let has_older_page = entries.len() as i64 > PAGE_SIZE;
+    entries.truncate(PAGE_SIZE as usize);
+
+    let home_url = format!("{}/", base_url.0);
+    let values = anonymous_values(&home_url);
+    let issues = if entries.is_empty() {
+        "<p>No issue has been published yet.</p>".to_string()
+    } else {
+        let items: Vec<String> = entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "<li><a href=\"/archive/{}\">{}</a> <small>{} · {}{}</small></li>",
+                    escape_html(&entry.slug),
+                    escape_html(&render_title(&entry.title, &values)),
+                    entry.published_at.format("%B %-d, %Y"),
+                    escape_html(&entry.list_name),
+                    if entry.visibility == IssueVisibility::SubscribersOnly.as_str() {
+                        " · subscribers only"
+                    } else {
+                        ""
+                    }
+                )
+            })
+            .collect();
+        format!("<ul>\n{}\n</ul>", items.join("\n"))
+    };
+    let mut pagination = Vec::new();
+    if page > 1 {
+        pagination.push(format!("<a href=\"/archive?page={}\">Newer issues</a>", page - 1));
+    }
+    if has_older_page {
+        pagination.push(format!("<a href=\"/archive?page={}\">Older issues</a>", page + 1));
+    }
+    let content = render_template(
+        include_str!("archive.html"),
+        &[("issues", &issues), ("pagination", &pagination.join(" "))],
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(render_page("Newsletter archive", &content)))
+}
+
+#[tracing::instrument(
+    name = "Show an archived issue",
+    skip(parameters, pool, base_url)
+)]
+pub async fn archive_issue(
+    slug: web::Path<String>,
+    parameters: web::Query<ArchiveIssueParameters>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ArchiveError> {
+    let issue = get_archived_issue(&pool, &slug)
+        .await
+        .context("Failed to retrieve the archived issue.")?
+        .ok_or(ArchiveError::NotFound)?;
+    let visibility = IssueVisibility::parse(&issue.visibility).map_err(|e| anyhow::anyhow!(e))?;
+    let reader = match &parameters.token {
+        Some(token) => get_reader(&pool, token, issue.list_id)
+            .await
+            .context("Failed to retrieve the subscriber associated with the provided token.")?,
+        None => None,
+    };
+    match (visibility, &reader) {
+        (IssueVisibility::Hidden, _) => return Err(ArchiveError::NotFound),
+        (IssueVisibility::SubscribersOnly, None) => return Err(ArchiveError::SubscribersOnly),
+        _ => {}
+    }
+
+    // merge fields are personalised for subscribers and neutral for anyone else
+    let home_url = format!("{}/", base_url.0);
+    let (preferences_link, unsubscribe_link) = match &reader {
+        Some(reader) => (
+            preferences_url(&base_url.0, &reader.preferences_token),
+            unsubscribe_url(&base_url.0, &reader.preferences_token),
+        ),
+        None => (home_url.clone(), home_url.clone()),
+    };
+    let values = match &reader {
+        Some(reader) => MergeValues {
+            name: &reader.name,
+            unsubscribe_url: &unsubscribe_link,
+            preferences_url: &preferences_link,
+        },
+        None => anonymous_values(&home_url),
+    };
+    let title = render_title(&issue.title, &values);
+    let html_content = NewsletterTemplate::parse(&issue.html_content)
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("The content of an archived issue is not a valid template.")?
+        .render_html(&values);
+    let content = render_template(
+        include_str!("issue.html"),
+        &[
+            ("title", &escape_html(&title)),
+            ("published_on", &issue.published_at.format("%B %-d, %Y").to_string()),
+            ("list", &escape_html(&issue.list_name)),
+            ("content", &html_content),
+        ],
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(render_page(&title, &content)))
+}
+
+fn anonymous_values(home_url: &str) -> MergeValues<'_> {
+    MergeValues {
+        name: "reader",
+        unsubscribe_url: home_url,
+        preferences_url: home_url,
+    }
+}
+
+// titles were validated when the issue was published
+fn render_title(title: &str, values: &MergeValues) -> String {
+    NewsletterTemplate::parse(title)
+        .map(|t| t.render_text(values))
+        .unwrap_or_else(|_| title.to_string())
+}
+
+#[tracing::instrument(name = "Get an archived issue by slug", skip(pool))]
+async fn get_archived_issue(
+    pool: &PgPool,
+    slug: &str,
+) -> Result<Option<ArchivedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT i.list_id, i.title, i.html_content, i.visibility, i.published_at, l.name AS list_name
+        FROM newsletter_issues i
+        JOIN lists l ON l.id = i.list_id
+        WHERE i.slug = $1
+        "#,
+        slug
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// a confirmed subscriber of the list, tokens of other readers are ignored
+#[tracing::instrument(name = "Get the reader of an archived issue", skip(pool, preferences_token))]
+async fn get_reader(
+    pool: &PgPool,
+    preferences_token: &str,
+    list_id: Uuid,
+) -> Result<Option<Reader>, sqlx::Error> {
+    sqlx::query_as!(
+        Reader,
+        r#"
+        SELECT s.name, s.preferences_token
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        WHERE s.preferences_token = $1 AND s.status = 'confirmed'
+            AND m.list_id = $2 AND m.status = 'confirmed'
+        "#,
+        preferences_token,
+        list_id
+    )
+    .fetch_optional(pool)
+    .await
+}
@@ -0,0 +1,162 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use std::sync::Arc;
+use crate::challenge_verifier::ChallengeVerifier;
+use crate::configuration::BotProtectionSettings;
+
+// keeps form tokens from being mistaken for other signed values
+const FORM_TOKEN_CONTEXT: &[u8] = b"subscription-form";
+
+/// What the subscription form checks before accepting a submission.
+pub struct BotProtection {
+    pub min_fill_time: Duration,
+    pub max_form_age: Duration,
+    pub challenge_verifier: Option<Arc<dyn ChallengeVerifier>>,
+}
+
+impl BotProtection {
+    pub fn new(
+        settings: &BotProtectionSettings,
+        challenge_verifier: Option<Arc<dyn ChallengeVerifier>>,
+    ) -> Self {
+        Self {
+            min_fill_time: Duration::seconds(settings.min_fill_seconds),
+            max_form_age: Duration::hours(settings.max_form_age_hours),
+            challenge_verifier,
+        }
+    }
+}
+
+/// The fields of the subscription form that only bots get wrong.
+pub struct FormSubmission<'a> {
+    // hidden from humans, bots fill in every field
+    pub honeypot: Option<&'a str>,
+    // when the form was served, see `sign_form_token`
+    pub form_token: Option<&'a str>,
+    pub challenge_response: Option<&'a str>,
+}
+
+/// Why a submission looks like it comes from a bot, `None` when it doesn't.
+/// Errors are failures to verify the challenge.
+pub async fn find_bot_signal(
+    protection: &BotProtection,
+    secret: &Secret<String>,
+    submission: &FormSubmission<'_>,
+    now: DateTime<Utc>,
+) -> Result<Option<&'static str>, anyhow::Error> {
+    if submission.honeypot.map_or(false, |h| !h.is_empty()) {
+        return Ok(Some("the honeypot field is filled in"));
+    }
+    let issued_at = match submission.form_token.and_then(|t| verify_form_token(secret, t)) {
+        Some(issued_at) => issued_at,
+        None => return Ok(Some("the form token is missing or invalid")),
+    };
+    if now - issued_at < protection.min_fill_time {
+        return Ok(Some("the form was filled in too fast"));
+    }
+    if now - issued_at > protection.max_form_age {
+        return Ok(Some("the form token has expired"));
+    }
+    if let Some(verifier) = &protection.challenge_verifier {
+        let response = match submission.challenge_response {
+            Some(response) if !response.is_empty() => response,
+            _ => return Ok(Some("the challenge response is missing")),
+        };
+        if !verifier.verify(response).await? {
+            return Ok(Some("the challenge response is wrong"));
+        }
+    }
+    Ok(None)
+}
+
+/// The token embedded in the subscription form, a signed timestamp of when it was served.
+pub fn sign_form_token(secret: &Secret<String>, issued_at: DateTime<Utc>) -> String {
+    let timestamp = issued_at.timestamp().to_string();
+    let mut mac = new_mac(secret);
+    mac.update(timestamp.as_bytes());
+    format!(
+        "{}.{}",
+        timestamp,
+        base64::encode_config(mac.finalize().into_bytes(), base64::URL_SAFE_NO_PAD)
+    )
+}
+
+fn verify_form_token(secret: &Secret<String>, token: &str) -> Option<DateTime<Utc>> {
+    let (timestamp, tag) = token.split_once('.')?;
+    let tag = base64::decode_config(tag, base64::URL_SAFE_NO_PAD).ok()?;
+    let mut mac = new_mac(secret);
+    mac.update(timestamp.as_bytes());
+    mac.verify_slice(&tag).ok()?;
+    Utc.timestamp_opt(timestamp.parse().ok()?, 0).single()
+}
+
+fn new_mac(secret: &Secret<String>) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(FORM_TOKEN_CONTEXT);
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_bot_signal, sign_form_token, BotProtection, FormSubmission};
+    use crate::challenge_verifier::{ChallengeVerifier, FakeChallengeVerifier};
+    use chrono::{Duration, Utc};
+    use claim::{assert_none, assert_ok, assert_some};
+    use secrecy::Secret;
+    use std::sync::Arc;
+
+    fn protection(challenge: Option<&'static str>) -> BotProtection {
+        BotProtection {
+            min_fill_time: Duration::seconds(3),
+            max_form_age: Duration::hours(24),
+            challenge_verifier: challenge
+                .map(|c| Arc::new(FakeChallengeVerifier::new(c)) as Arc<dyn ChallengeVerifier>),
+        }
+    }
+
+    fn secret() -> Secret<String> {
+        Secret::new("secret".into())
+    }
+
+    async fn signal(
+        protection: &BotProtection,
+        honeypot: Option<&str>,
+        form_token: Option<&str>,
+        challenge_response: Option<&str>,
+    ) -> Option<&'static str> {
+        let submission = FormSubmission {
+            honeypot,
+            form_token,
+            challenge_response,
+        };
+        assert_ok!(find_bot_signal(protection, &secret(), &submission, Utc::now()).await)
+    }
+
+    #[tokio::test]
+    async fn a_form_filled_in_by_a_human_passes() {
+        let token = sign_form_token(&secret(), Utc::now() - Duration::seconds(10));
+        assert_none!(signal(&protection(None), Some(""), Some(&token), None).await);
+        assert_none!(signal(&protection(Some("ok")), None, Some(&token), Some("ok")).await);
+    }
+
+    #[tokio::test]
+    async fn bot_signals_are_detected() {
+        let token = sign_form_token(&secret(), Utc::now() - Duration::seconds(10));
+        let fast = sign_form_token(&secret(), Utc::now());
+        let stale = sign_form_token(&secret(), Utc::now() - Duration::days(2));
+        let forged = sign_form_token(&Secret::new("other".into()), Utc::now() - Duration::seconds(10));
+        let none = protection(None);
+        assert_some!(signal(&none, Some("https://spam.example"), Some(&token), None).await);
+        assert_some!(signal(&none, None, None, None).await);
+        assert_some!(signal(&none, None, Some(&fast), None).await);
+        assert_some!(signal(&none, None, Some(&stale), None).await);
+        assert_some!(signal(&none, None, Some(&forged), None).await);
+        assert_some!(signal(&none, None, Some("12345.abc"), None).await);
+        let challenge = protection(Some("ok"));
+        assert_some!(signal(&challenge, None, Some(&token), None).await);
+        assert_some!(signal(&challenge, None, Some(&token), Some("wrong")).await);
+    }
+}
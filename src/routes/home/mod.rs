@@ -1,8 +1,15 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web::http::header::ContentType;
+use chrono::Utc;
+use crate::routes::{render_page, sign_form_token};
+use crate::templating::render_template;
+use crate::startup::HmacSecret;
 
-pub async fn home() -> HttpResponse {
+pub async fn home(secret: web::Data<HmacSecret>) -> HttpResponse {
+    // the subscription form is rejected if submitted too soon after being served
+    let form_token = sign_form_token(&secret.0, Utc::now());
+    let content = render_template(include_str!("home.html"), &[("form_token", &form_token)]);
     HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(include_str!("home.html"))
-}
\ No newline at end of file
+        .body(render_page("Home", &content))
+}
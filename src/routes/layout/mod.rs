@@ -0,0 +1,85 @@
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use crate::domain::Locale;
+use crate::templating::{escape_html, render_template};
+
+/// Wrap a fragment of HTML into the layout shared by every page of the site.
+///
+/// `title` is escaped, `content` is inserted as is and must be trusted markup.
+pub fn render_page(title: &str, content: &str) -> String {
+    render_localized_page(Locale::En, title, content)
+}
+
+/// Like `render_page`, for a page written in `locale`.
+pub fn render_localized_page(locale: Locale, title: &str, content: &str) -> String {
+    render_template(
+        include_str!("layout.html"),
+        &[
+            ("lang", locale.as_str()),
+            ("title", &escape_html(title)),
+            ("content", content),
+        ],
+    )
+}
+
+/// An error shown to readers as a page in their language.
+pub trait LocalizedError: ResponseError {
+    /// The title and the HTML content of the page explaining the error.
+    fn page(&self, locale: Locale) -> (String, String);
+}
+
+/// Renders the page of `error` in `locale`.
+pub struct Localized<E> {
+    pub error: E,
+    pub locale: Locale,
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for Localized<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<E: std::fmt::Debug> std::fmt::Debug for Localized<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<E: LocalizedError> ResponseError for Localized<E> {
+    fn status_code(&self) -> StatusCode {
+        self.error.status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        localized_error_response(&self.error, self.locale)
+    }
+}
+
+/// The page of `error` in `locale`, with its status code.
+pub fn localized_error_response(error: &impl LocalizedError, locale: Locale) -> HttpResponse {
+    let (title, content) = error.page(locale);
+    HttpResponse::build(error.status_code())
+        .content_type(ContentType::html())
+        .body(render_localized_page(locale, &title, &content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_localized_page, render_page};
+    use crate::domain::Locale;
+
+    #[test]
+    fn the_title_is_escaped_but_the_content_is_not() {
+        let page = render_page("<b>", "<p>Hello</p>");
+        assert!(page.contains("<title>&lt;b&gt;</title>"));
+        assert!(page.contains("<p>Hello</p>"));
+    }
+
+    #[test]
+    fn the_page_declares_its_language() {
+        assert!(render_page("Title", "").contains("<html lang=\"en\">"));
+        assert!(render_localized_page(Locale::Fr, "Titre", "").contains("<html lang=\"fr\">"));
+    }
+}
@@ -1,14 +1,36 @@
 // provide an aggregated view for all available modules
+mod admin;
+mod api;
+mod archive;
+mod authentication;
+mod bot_protection;
 mod health_check;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_resend_confirmation;
 mod newsletters;
 mod home;
+mod layout;
 mod login;
+mod preferences;
+mod problem;
+mod tracking;
+mod webhooks;
 // re-export useful functions
+pub use admin::*;
+pub use api::*;
+pub use archive::*;
+pub use authentication::*;
+pub use bot_protection::*;
 pub use health_check::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_resend_confirmation::*;
 pub use newsletters::*;
 pub use home::*;
-pub use login::*;
\ No newline at end of file
+pub use layout::*;
+pub use login::*;
+pub use preferences::*;
+pub use problem::*;
+pub use tracking::*;
+pub use webhooks::*;
\ No newline at end of file
@@ -0,0 +1,185 @@
+use crate::domain::MergeField;
+use crate::templating::{escape_html, render_template};
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag};
+
+/// The two alternatives of an issue authored in Markdown.
+/// Merge fields are left in place, to be substituted for each recipient.
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub text: String,
+}
+
+pub fn render_markdown(markdown: &str) -> RenderedMarkdown {
+    RenderedMarkdown {
+        html: render_html(markdown),
+        text: render_text(markdown),
+    }
+}
+
+/// Wrap the HTML body of an issue into the layout shared by every email.
+///
+/// `title` is escaped, `content` is inserted as is.
+pub fn render_email_layout(title: &str, content: &str) -> String {
+    render_template(
+        include_str!("email.html"),
+        &[("title", &escape_html(title)), ("content", content)],
+    )
+}
+
+fn render_html(markdown: &str) -> String {
+    let mut html = String::with_capacity(markdown.len() * 3 / 2);
+    pulldown_cmark::html::push_html(&mut html, Parser::new(markdown));
+    let mut html = ammonia::clean(&html);
+    // link destinations are percent-encoded by the renderer,
+    // bring back the merge fields editors used as URLs
+    for field in MergeField::ALL {
+        for encoded in [
+            format!("%7B%7B{}%7D%7D", field.as_str()),
+            format!("%7B%7B%20{}%20%7D%7D", field.as_str()),
+        ] {
+            html = html.replace(&encoded, &format!("{{{{ {} }}}}", field.as_str()));
+        }
+    }
+    html
+}
+
+fn render_text(markdown: &str) -> String {
+    let mut text = String::with_capacity(markdown.len());
+    // where the text of the enclosing heading, quote or link begins
+    let mut starts = Vec::new();
+    // the next number of each enclosing list, `None` when unordered
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::Heading(..) | Tag::BlockQuote | Tag::Link(..) | Tag::Image(..)) => {
+                starts.push(text.len());
+            }
+            Event::End(Tag::Heading(level, ..)) => {
+                let start = starts.pop().unwrap_or_default();
+                let width = text[start..].chars().count();
+                match level {
+                    HeadingLevel::H1 => text.push_str(&format!("\n{}", "=".repeat(width))),
+                    HeadingLevel::H2 => text.push_str(&format!("\n{}", "-".repeat(width))),
+                    _ => {}
+                }
+                text.push_str("\n\n");
+            }
+            Event::End(Tag::BlockQuote) => {
+                let start = starts.pop().unwrap_or_default();
+                let quoted = text.split_off(start);
+                for line in quoted.trim_end().lines() {
+                    text.push_str(format!("> {}", line).trim_end());
+                    text.push('\n');
+                }
+                text.push('\n');
+            }
+            Event::End(Tag::Link(_, destination, _) | Tag::Image(_, destination, _)) => {
+                let start = starts.pop().unwrap_or_default();
+                // autolinks already show their destination
+                if text[start..] != *destination {
+                    text.push_str(&format!(" ({})", destination));
+                }
+            }
+            Event::End(Tag::Paragraph) => {
+                text.push_str(if lists.is_empty() { "\n\n" } else { "\n" });
+            }
+            Event::Start(Tag::List(first_number)) => {
+                if !lists.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                lists.push(first_number);
+            }
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                let indent = "  ".repeat(lists.len().saturating_sub(1));
+                let marker = match lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                text.push_str(&indent);
+                text.push_str(&marker);
+            }
+            Event::End(Tag::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::End(Tag::CodeBlock(_)) => text.push('\n'),
+            Event::Text(content) | Event::Code(content) => text.push_str(&content),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => text.push('\n'),
+            Event::Rule => text.push_str("---\n\n"),
+            // raw HTML has no plain text alternative
+            _ => {}
+        }
+    }
+    text.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_email_layout, render_markdown};
+
+    const ISSUE: &str = r#"# Issue #42
+
+Hi {{ name }}, welcome to **this week's** issue with *news* and `code`.
+This line is softly wrapped.
+
+## What's new
+
+- Markdown authoring
+- [Merge fields]({{preferences_url}}) in links
+  1. nested
+  2. ordered
+
+> Quoted text
+> over two lines
+
+```
+let answer = 42;
+```
+
+---
+
+See <https://example.com> or [leave]({{unsubscribe_url}}).
+![A cat](https://example.com/cat.png)
+"#;
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        insta::assert_snapshot!(render_markdown(ISSUE).html);
+    }
+
+    #[test]
+    fn markdown_is_rendered_to_plain_text() {
+        insta::assert_snapshot!(render_markdown(ISSUE).text);
+    }
+
+    #[test]
+    fn rendered_html_is_sanitized() {
+        let rendered = render_markdown(
+            "<script>alert(1)</script>\n\n\
+            <a href=\"javascript:alert(1)\" onclick=\"alert(1)\">click</a>\n\n\
+            <iframe src=\"https://example.com\"></iframe>",
+        );
+        insta::assert_snapshot!(rendered.html);
+        assert!(!rendered.text.contains("script"));
+    }
+
+    #[test]
+    fn rendering_is_deterministic() {
+        let first = render_markdown(ISSUE);
+        let second = render_markdown(ISSUE);
+        assert_eq!(first.html, second.html);
+        assert_eq!(first.text, second.text);
+    }
+
+    #[test]
+    fn the_email_layout_escapes_the_title() {
+        insta::assert_snapshot!(render_email_layout("News & <views>", "<p>Hello</p>"));
+    }
+}
@@ -0,0 +1,703 @@
+use actix_web::HttpResponse;
+use actix_web::web;
+use sqlx::{PgPool, QueryBuilder};
+use actix_web::ResponseError;
+use crate::domain::{
+    ContentFormat, DeliveryState, IssueSlug, IssueVisibility, ListSlug, Locale, MergeValues, NewsletterTemplate, Segment, SubscriberEmail,
+};
+use crate::email_client::{EmailClient, SendEmailError};
+use crate::routes::{
+    add_tracking, error_chain_fmt, get_lists_by_slug, preferences_url, unsubscribe_url,
+    ProblemDetails, TrackingContext, DEFAULT_LIST_SLUG,
+};
+use crate::localization::{message, stored_locale};
+use crate::startup::{ApplicationBaseUrl, DefaultLocale, HmacSecret};
+use actix_web::http::StatusCode;
+use anyhow::Context;
+use chrono::Utc;
+use secrecy::Secret;
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+
+mod markdown;
+mod sanitize;
+mod subject_test;
+mod throttle;
+
+pub use markdown::*;
+pub use sanitize::*;
+pub use subject_test::*;
+pub use throttle::*;
+
+const MAX_TITLE_LENGTH: usize = 256;
+// applies to each of the `markdown`, `html` and `text` bodies
+const MAX_CONTENT_LENGTH: usize = 256 * 1024;
+// how many times a slug derived from the title is picked again
+// when concurrent publications take it first
+const MAX_SLUG_ATTEMPTS: u32 = 5;
+
+pub struct ConfirmedSubscriber {
+    id: Uuid,
+    email: SubscriberEmail,
+    name: String,
+    content_format: ContentFormat,
+    preferences_token: String,
+    tracking_enabled: bool,
+    // the default locale applies when missing
+    locale: Option<Locale>,
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PublishError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PublishError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PublishError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ProblemDetails::from_response_error(self).response()
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+    // slug of the list receiving the issue, the default list when missing
+    list: Option<String>,
+    // only send to the subscribers of the list matching this expression
+    segment: Option<String>,
+    // track opens and clicks of subscribers who didn't opt out
+    #[serde(default)]
+    tracking: bool,
+    // identifies the issue in the web archive, derived from the title when missing
+    slug: Option<String>,
+    // who can read the issue in the web archive, `public` when missing
+    visibility: Option<String>,
+    // send candidate subjects to a sample of the list before the remainder gets the best one
+    subject_test: Option<SubjectTestData>,
+    // spread the deliveries over time, the worker sends them
+    throttle: Option<ThrottleData>,
+}
+
+#[derive(serde::Serialize)]
+struct PublishedIssue {
+    issue_id: Uuid,
+    slug: String,
+    sent: u64,
+    failed: u64,
+    // queued by the throttle, waiting for the subject test to be decided or held by a pause
+    pending: u64,
+    // not sent because the issue was cancelled while it was being sent
+    cancelled: u64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Content {
+    // rendered to both alternatives, explicit `html` and `text` take precedence
+    markdown: Option<String>,
+    html: Option<String>,
+    text: Option<String>,
+}
+
+pub struct IssueContent {
+    html: NewsletterTemplate,
+    text: NewsletterTemplate,
+    html_source: String,
+    text_source: String,
+    // HTML rendered from Markdown is a fragment to wrap into the email layout
+    use_layout: bool,
+}
+
+pub(crate) fn parse_title(title: &str) -> Result<NewsletterTemplate, PublishError> {
+    if title.trim().is_empty() {
+        return Err(PublishError::ValidationError(
+            "The title of the issue is empty.".into(),
+        ));
+    }
+    if title.graphemes(true).count() > MAX_TITLE_LENGTH {
+        return Err(PublishError::ValidationError(format!(
+            "The title of the issue is longer than {} characters.",
+            MAX_TITLE_LENGTH
+        )));
+    }
+    NewsletterTemplate::parse(title).map_err(PublishError::ValidationError)
+}
+
+impl IssueContent {
+    fn parse(content: Content) -> Result<IssueContent, PublishError> {
+        for (name, body) in [
+            ("markdown", &content.markdown),
+            ("html", &content.html),
+            ("text", &content.text),
+        ] {
+            if body.as_ref().map_or(false, |b| b.len() > MAX_CONTENT_LENGTH) {
+                return Err(PublishError::ValidationError(format!(
+                    "The `{}` body is larger than {} bytes.",
+                    name, MAX_CONTENT_LENGTH
+                )));
+            }
+        }
+        let rendered = content.markdown.as_deref().map(render_markdown);
+        let use_layout = content.html.is_none();
+        let (html, text) = match (content.html, content.text, rendered) {
+            (Some(html), Some(text), _) => (html, text),
+            (html, text, Some(rendered)) => (
+                html.unwrap_or(rendered.html),
+                text.unwrap_or(rendered.text),
+            ),
+            _ => {
+                return Err(PublishError::ValidationError(
+                    "The content needs either a `markdown` body or both an `html` and a `text` body."
+                        .into(),
+                ))
+            }
+        };
+        let html = sanitize_html(&html).map_err(PublishError::ValidationError)?;
+        if html.trim().is_empty() || text.trim().is_empty() {
+            return Err(PublishError::ValidationError(
+                "The body of the issue is empty.".into(),
+            ));
+        }
+        Ok(Self {
+            html: NewsletterTemplate::parse(&html).map_err(PublishError::ValidationError)?,
+            text: NewsletterTemplate::parse(&text).map_err(PublishError::ValidationError)?,
+            html_source: html,
+            text_source: text,
+            use_layout,
+        })
+    }
+
+    /// The content of an issue as it was stored when it was published.
+    pub fn from_stored(html: String, text: String, use_layout: bool) -> Result<Self, String> {
+        Ok(Self {
+            html: NewsletterTemplate::parse(&html)?,
+            text: NewsletterTemplate::parse(&text)?,
+            html_source: html,
+            text_source: text,
+            use_layout,
+        })
+    }
+}
+
+/// Everything but the recipient of an issue's email.
+pub struct IssueDelivery<'a> {
+    pub issue_id: Uuid,
+    pub subject: &'a NewsletterTemplate,
+    pub content: &'a IssueContent,
+    pub tracking: bool,
+    pub base_url: &'a str,
+    pub hmac_secret: &'a Secret<String>,
+    // the locale of the footer for subscribers without one
+    pub default_locale: Locale,
+}
+
+/// Send an issue to the confirmed subscribers of a list.
+///
+/// Responds with `200 OK` and a JSON summary: the `issue_id` and `slug` of the issue,
+/// then how many emails were `sent`, `failed`, are still `pending` or were `cancelled`.
+/// Failed deliveries don't fail the request, callers must check `failed`
+/// rather than the status code.
+pub async fn publish_newsletter(
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    default_locale: web::Data<DefaultLocale>,
+) -> Result<HttpResponse, PublishError> {
+    let body = body.into_inner();
+    let title = parse_title(&body.title)?;
+    let content = IssueContent::parse(body.content)?;
+    let list_slug = ListSlug::parse(body.list.unwrap_or_else(|| DEFAULT_LIST_SLUG.into()))
+        .map_err(PublishError::ValidationError)?;
+    let list = get_lists_by_slug(pool.as_ref(), std::slice::from_ref(&list_slug))
+        .await
+        .context("Failed to retrieve the target list.")?
+        .pop()
+        .ok_or_else(|| {
+            PublishError::ValidationError(format!("{} is not a known list.", list_slug))
+        })?;
+    let segment = body
+        .segment
+        .as_deref()
+        .map(Segment::parse)
+        .transpose()
+        .map_err(PublishError::ValidationError)?;
+    let subject_test = body.subject_test.map(SubjectTest::parse).transpose()?;
+    if subject_test.is_some() && !body.tracking {
+        return Err(PublishError::ValidationError(
+            "A subject test needs `tracking` to measure opens and clicks.".into(),
+        ));
+    }
+    let throttle = body.throttle.map(Throttle::parse).transpose()?;
+    let visibility = body
+        .visibility
+        .as_deref()
+        .map(IssueVisibility::parse)
+        .transpose()
+        .map_err(PublishError::ValidationError)?
+        .unwrap_or(IssueVisibility::Public);
+    let explicit_slug = body
+        .slug
+        .map(IssueSlug::parse)
+        .transpose()
+        .map_err(PublishError::ValidationError)?;
+    let mut attempts = 0;
+    let (issue_id, slug) = loop {
+        let slug = match &explicit_slug {
+            Some(slug) => slug.clone(),
+            None => available_slug(&pool, IssueSlug::from_title(&body.title))
+                .await
+                .context("Failed to pick a slug for the issue.")?,
+        };
+        let issue = NewIssue {
+            list_id: list.id,
+            title: &body.title,
+            slug: &slug,
+            visibility,
+            tracking_enabled: body.tracking,
+        };
+        match insert_issue(&pool, &issue, &content).await {
+            Ok(issue_id) => break (issue_id, slug),
+            Err(e) if is_slug_conflict(&e) => {
+                if explicit_slug.is_some() {
+                    return Err(PublishError::ValidationError(format!(
+                        "{} is already the slug of another issue.",
+                        slug
+                    )));
+                }
+                // another issue was published with the same title in the meantime,
+                // the next free suffix accounts for it
+                attempts += 1;
+                if attempts == MAX_SLUG_ATTEMPTS {
+                    return Err(anyhow::anyhow!(e)
+                        .context("Failed to find a free slug for the issue.")
+                        .into());
+                }
+            }
+            Err(e) => {
+                return Err(anyhow::anyhow!(e)
+                    .context("Failed to store the newsletter issue.")
+                    .into())
+            }
+        }
+    };
+    if let Some(subject_test) = &subject_test {
+        insert_subject_test(&pool, issue_id, subject_test)
+            .await
+            .context("Failed to store the subject test of the issue.")?;
+    }
+    if let Some(throttle) = &throttle {
+        insert_send_throttle(&pool, issue_id, throttle)
+            .await
+            .context("Failed to store the throttle of the issue.")?;
+    }
+    let subscribers: Vec<ConfirmedSubscriber> = get_confirmed_subscribers(&pool, list.id, segment.as_ref())
+        .await?
+        .into_iter()
+        .filter_map(|subscriber| match subscriber {
+            Ok(subscriber) => Some(subscriber),
+            Err(error) => {
+                tracing::warn!(
+                    error.cause_chain = ?error,
+                    "Skipping a confirmed subscdriber. \
+                    Their stored contact details are invalid",
+                );
+                None
+            }
+        })
+        .collect();
+    // without a subject test everyone gets the title as subject
+    let variants = match &subject_test {
+        Some(subject_test) => {
+            let subscriber_ids: Vec<Uuid> = subscribers.iter().map(|s| s.id).collect();
+            assign_variants(
+                issue_id,
+                &subscriber_ids,
+                subject_test.sample_percent,
+                subject_test.templates.len(),
+            )
+        }
+        None => vec![None; subscribers.len()],
+    };
+    let (mut sent, mut failed, mut pending, mut cancelled) = (0, 0, 0, 0);
+    // editors may pause or cancel the issue while it is being sent,
+    // recording each delivery tells us the state the next one must honour
+    let mut state = DeliveryState::Active;
+    for (subscriber, variant) in subscribers.iter().zip(variants) {
+        let variant_index = variant.map(|v| v as i32);
+        let status = if state == DeliveryState::Cancelled {
+            "cancelled"
+        } else if throttle.is_some()
+            || state == DeliveryState::Paused
+            || (subject_test.is_some() && variant.is_none())
+        {
+            // a throttled issue is entirely left to the worker, so is the remainder of a
+            // subject test and whatever is left of a paused issue
+            "pending"
+        } else {
+            let subject = match (&subject_test, variant) {
+                (Some(subject_test), Some(variant)) => &subject_test.templates[variant],
+                _ => &title,
+            };
+            let delivery = IssueDelivery {
+                issue_id,
+                subject,
+                content: &content,
+                tracking: body.tracking,
+                base_url: &base_url.0,
+                hmac_secret: &hmac_secret.0,
+                default_locale: default_locale.0,
+            };
+            // one failed delivery must not prevent the others,
+            // it is recorded and counted in the stats of the issue
+            match deliver_issue(&email_client, &delivery, subscriber).await {
+                Ok(()) => "sent",
+                Err(error) => {
+                    tracing::error!(
+                        error.cause_chain = ?error,
+                        "Failed to send newsletter issue to {}",
+                        subscriber.email
+                    );
+                    "failed"
+                }
+            }
+        };
+        let recorded = insert_delivery(&pool, issue_id, subscriber.id, status, variant_index)
+            .await
+            .context("Failed to record a delivery of the newsletter issue.")?;
+        match recorded.status.as_str() {
+            "sent" => sent += 1,
+            "failed" => failed += 1,
+            "pending" => pending += 1,
+            _ => cancelled += 1,
+        }
+        state = DeliveryState::parse(&recorded.delivery_state).map_err(|e| anyhow::anyhow!(e))?;
+    }
+
+    Ok(HttpResponse::Ok().json(PublishedIssue {
+        issue_id,
+        slug: slug.to_string(),
+        sent,
+        failed,
+        pending,
+        cancelled,
+    }))
+}
+
+/// Render an issue for `subscriber` and send it.
+pub async fn deliver_issue(
+    email_client: &EmailClient,
+    delivery: &IssueDelivery<'_>,
+    subscriber: &ConfirmedSubscriber,
+) -> Result<(), SendEmailError> {
+    // every issue carries a link to the subscriber's own preference center
+    let preferences_link = preferences_url(delivery.base_url, &subscriber.preferences_token);
+    let unsubscribe_link = unsubscribe_url(delivery.base_url, &subscriber.preferences_token);
+    let values = MergeValues {
+        name: &subscriber.name,
+        unsubscribe_url: &unsubscribe_link,
+        preferences_url: &preferences_link,
+    };
+    let content = delivery.content;
+    let subject = delivery.subject.render_text(&values);
+    let locale = subscriber.locale.unwrap_or(delivery.default_locale);
+    let text_content = format!(
+        "{}\n\n--\n{}",
+        content.text.render_text(&values),
+        message(locale, "newsletter-footer-text", &[("link", &preferences_link)])
+    );
+    let html_content = match subscriber.content_format {
+        ContentFormat::Html => {
+            let mut html_body = content.html.render_html(&values);
+            if delivery.tracking && subscriber.tracking_enabled {
+                let context = TrackingContext {
+                    base_url: delivery.base_url,
+                    secret: delivery.hmac_secret,
+                    issue_id: delivery.issue_id,
+                    subscriber_id: subscriber.id,
+                };
+                html_body = add_tracking(&html_body, &context);
+            }
+            let mut html_content = format!(
+                "{}<hr /><p><a href=\"{}\">{}</a></p>",
+                html_body,
+                preferences_link,
+                message(locale, "manage-subscription", &[])
+            );
+            if content.use_layout {
+                html_content = render_email_layout(&subject, &html_content);
+            }
+            Some(html_content)
+        }
+        ContentFormat::Text => None,
+    };
+    email_client
+        .send_issue(
+            &subscriber.email,
+            &subject,
+            html_content.as_deref(),
+            &text_content,
+            delivery.issue_id,
+        )
+        .await
+}
+
+// the slugs colliding with `slug`, including the ones it would get with a suffix
+#[tracing::instrument(name = "Get the slugs already taken", skip(pool))]
+async fn get_taken_slugs(pool: &PgPool, slug: &IssueSlug) -> Result<Vec<String>, sqlx::Error> {
+    let slugs = sqlx::query!(
+        r#"SELECT slug FROM newsletter_issues WHERE slug = $1 OR slug LIKE $1 || '-%'"#,
+        slug.as_ref()
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| r.slug)
+    .collect();
+    Ok(slugs)
+}
+
+fn is_slug_conflict(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(e) => e.constraint() == Some("newsletter_issues_slug_key"),
+        _ => false,
+    }
+}
+
+/// `slug` itself when no issue uses it yet,
+/// otherwise the first free slug among `slug-2`, `slug-3`, ...
+async fn available_slug(pool: &PgPool, slug: IssueSlug) -> Result<IssueSlug, sqlx::Error> {
+    let taken = get_taken_slugs(pool, &slug).await?;
+    let is_free = |candidate: &IssueSlug| taken.iter().all(|t| t != candidate.as_ref());
+    if is_free(&slug) {
+        return Ok(slug);
+    }
+    let candidate = (2..)
+        .map(|n| slug.with_suffix(n))
+        .find(is_free)
+        .expect("There are fewer taken slugs than suffixes");
+    Ok(candidate)
+}
+
+#[tracing::instrument(name = "Store the subject test of an issue", skip(pool, subject_test))]
+async fn insert_subject_test(
+    pool: &PgPool,
+    issue_id: Uuid,
+    subject_test: &SubjectTest,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subject_tests (issue_id, metric, decide_at)
+        VALUES ($1, $2, $3)
+        "#,
+        issue_id,
+        subject_test.metric.as_str(),
+        Utc::now() + subject_test.wait
+    )
+    .execute(pool)
+    .await?;
+    for (variant, subject) in subject_test.subjects.iter().enumerate() {
+        sqlx::query!(
+            r#"
+            INSERT INTO subject_variants (issue_id, variant, subject)
+            VALUES ($1, $2, $3)
+            "#,
+            issue_id,
+            variant as i32,
+            subject
+        )
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "Store the throttle of an issue", skip(pool, throttle))]
+async fn insert_send_throttle(
+    pool: &PgPool,
+    issue_id: Uuid,
+    throttle: &Throttle,
+) -> Result<(), sqlx::Error> {
+    let window = throttle.window.as_ref();
+    sqlx::query!(
+        r#"
+        INSERT INTO send_throttles (issue_id, max_per_hour, window_start, window_end, timezone)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        issue_id,
+        throttle.max_per_hour.map(|n| n as i32),
+        window.map(|w| w.start),
+        window.map(|w| w.end),
+        window.map(|w| w.timezone.name())
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+struct RecordedDelivery {
+    status: String,
+    // the state of the issue once the delivery was recorded
+    delivery_state: String,
+}
+
+// The issue row is locked while the delivery is recorded, so a cancellation either
+// happens first and the delivery is recorded as cancelled rather than pending,
+// or waits for it and cancels it along with the other pending deliveries.
+#[tracing::instrument(name = "Record a delivery of a newsletter issue", skip(pool))]
+async fn insert_delivery(
+    pool: &PgPool,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    status: &str,
+    variant: Option<i32>,
+) -> Result<RecordedDelivery, sqlx::Error> {
+    sqlx::query_as!(
+        RecordedDelivery,
+        r#"
+        WITH issue AS (
+            SELECT delivery_state FROM newsletter_issues WHERE id = $1 FOR SHARE
+        ), inserted AS (
+            INSERT INTO newsletter_deliveries (issue_id, subscriber_id, status, variant, attempted_at)
+            SELECT
+                $1,
+                $2,
+                CASE WHEN issue.delivery_state = 'cancelled' AND $3 = 'pending'
+                    THEN 'cancelled' ELSE $3 END,
+                $4,
+                $5
+            FROM issue
+            RETURNING status
+        )
+        SELECT inserted.status AS "status!", issue.delivery_state AS "delivery_state!"
+        FROM issue, inserted
+        "#,
+        issue_id,
+        subscriber_id,
+        status,
+        variant,
+        Utc::now()
+    )
+    .fetch_one(pool)
+    .await
+}
+
+struct NewIssue<'a> {
+    list_id: Uuid,
+    title: &'a str,
+    slug: &'a IssueSlug,
+    visibility: IssueVisibility,
+    tracking_enabled: bool,
+}
+
+#[tracing::instrument(name = "Store a newsletter issue", skip(pool, issue, content))]
+async fn insert_issue(
+    pool: &PgPool,
+    issue: &NewIssue<'_>,
+    content: &IssueContent,
+) -> Result<Uuid, sqlx::Error> {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            id, list_id, title, slug, visibility,
+            text_content, html_content, use_layout, tracking_enabled, published_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $10)
+        "#,
+        issue_id,
+        issue.list_id,
+        issue.title,
+        issue.slug.as_ref(),
+        issue.visibility.as_str(),
+        content.text_source,
+        content.html_source,
+        content.use_layout,
+        issue.tracking_enabled,
+        Utc::now()
+    )
+    .execute(pool)
+    .await?;
+    Ok(issue_id)
+}
+
+#[derive(sqlx::FromRow)]
+pub struct ConfirmedSubscriberRow {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub content_format: String,
+    pub preferences_token: String,
+    pub tracking_enabled: bool,
+    pub locale: Option<String>,
+}
+
+impl TryFrom<ConfirmedSubscriberRow> for ConfirmedSubscriber {
+    type Error = anyhow::Error;
+
+    fn try_from(r: ConfirmedSubscriberRow) -> Result<Self, Self::Error> {
+        let email = SubscriberEmail::parse(r.email).map_err(|e| anyhow::anyhow!(e))?;
+        let content_format = ContentFormat::parse(&r.content_format)
+            .map_err(|e| anyhow::anyhow!(e))?;
+        Ok(ConfirmedSubscriber {
+            id: r.id,
+            email,
+            name: r.name,
+            content_format,
+            preferences_token: r.preferences_token,
+            tracking_enabled: r.tracking_enabled,
+            locale: stored_locale(r.locale.as_deref()),
+        })
+    }
+}
+
+#[tracing::instrument(
+    name = "Get confirmed subscribers",
+    skip(pool)
+)]
+async fn get_confirmed_subscribers(
+    pool: &PgPool,
+    list_id: Uuid,
+    segment: Option<&Segment>,
+) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
+    let mut query = QueryBuilder::new(
+        r#"
+        SELECT s.id, s.email, s.name, s.content_format, s.preferences_token, s.tracking_enabled,
+            s.locale
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        WHERE s.status = 'confirmed' AND m.status = 'confirmed'
+            AND NOT EXISTS (SELECT 1 FROM suppressions sp WHERE sp.email = lower(s.email))
+            AND m.list_id = "#,
+    );
+    query.push_bind(list_id);
+    if let Some(segment) = segment {
+        query.push(" AND ");
+        segment.push_sql(&mut query);
+    }
+    let confirmed_subscribers = query
+        .build_query_as::<ConfirmedSubscriberRow>()
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(ConfirmedSubscriber::try_from)
+        .collect();
+    Ok(confirmed_subscribers)
+}
@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex};
+
+/// Strip from the HTML body of an issue what an email client must never run:
+/// scripts, iframes, event handlers and `javascript:` URLs.
+///
+/// Every link or image left afterwards must point to an absolute http(s) URL
+/// or to a merge field, otherwise the whole body is rejected.
+pub fn sanitize_html(html: &str) -> Result<String, String> {
+    // the filter must be 'static, invalid URLs are collected on the side
+    let invalid_urls = Arc::new(Mutex::new(Vec::new()));
+    let collected = Arc::clone(&invalid_urls);
+    let sanitized = ammonia::Builder::default()
+        .attribute_filter(move |_element, attribute, value| {
+            if matches!(attribute, "href" | "src" | "cite") && !is_valid_url(value) {
+                collected.lock().unwrap().push(value.to_string());
+            }
+            Some(value.into())
+        })
+        .clean(html)
+        .to_string();
+    let invalid_urls = invalid_urls.lock().unwrap();
+    if invalid_urls.is_empty() {
+        Ok(sanitized)
+    } else {
+        Err(format!(
+            "Links must be absolute http(s) URLs, {} are not.",
+            invalid_urls.join(", ")
+        ))
+    }
+}
+
+fn is_valid_url(value: &str) -> bool {
+    let value = value.trim();
+    // merge fields are substituted with absolute URLs for each recipient
+    let is_merge_field = value.starts_with("{{") && value.ends_with("}}");
+    is_merge_field
+        || reqwest::Url::parse(value)
+            .map(|url| matches!(url.scheme(), "http" | "https") && url.has_host())
+            .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sanitize_html;
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn scripts_iframes_and_event_handlers_are_stripped() {
+        let html = "<p onclick=\"alert(1)\">Hello</p>\
+            <script>alert(1)</script>\
+            <iframe src=\"https://example.com\"></iframe>\
+            <a href=\"javascript:alert(1)\">click</a>";
+        assert_ok_eq!(
+            sanitize_html(html),
+            "<p>Hello</p><a rel=\"noopener noreferrer\">click</a>".to_string()
+        );
+    }
+
+    #[test]
+    fn absolute_http_links_and_merge_fields_are_accepted() {
+        let html = "<a href=\"https://example.com/a\">a</a>\
+            <a href=\"http://example.com/b\">b</a>\
+            <a href=\"{{ unsubscribe_url }}\">c</a>\
+            <img src=\"https://example.com/cat.png\">";
+        assert_ok_eq!(
+            sanitize_html(html),
+            "<a href=\"https://example.com/a\" rel=\"noopener noreferrer\">a</a>\
+            <a href=\"http://example.com/b\" rel=\"noopener noreferrer\">b</a>\
+            <a href=\"{{ unsubscribe_url }}\" rel=\"noopener noreferrer\">c</a>\
+            <img src=\"https://example.com/cat.png\">"
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn relative_links_are_rejected() {
+        assert_err!(sanitize_html("<a href=\"/about\">About</a>"));
+        assert_err!(sanitize_html("<img src=\"cat.png\">"));
+    }
+
+    #[test]
+    fn links_with_other_schemes_are_rejected() {
+        assert_err!(sanitize_html("<a href=\"mailto:editor@example.com\">Write to us</a>"));
+        assert_err!(sanitize_html("<a href=\"ftp://example.com/archive\">Archive</a>"));
+    }
+}
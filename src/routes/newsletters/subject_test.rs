@@ -0,0 +1,208 @@
+use chrono::Duration;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use crate::domain::NewsletterTemplate;
+use super::{parse_title, PublishError};
+
+const MAX_VARIANTS: usize = 5;
+const MAX_WAIT_MINUTES: i64 = 7 * 24 * 60;
+
+#[derive(serde::Deserialize)]
+pub struct SubjectTestData {
+    subjects: Vec<String>,
+    // share of the list, in percent, receiving one of the candidate subjects
+    sample_percent: u32,
+    // how long to collect opens or clicks before picking the winner
+    wait_minutes: i64,
+    metric: String,
+}
+
+/// What makes a subject the winner of a test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubjectTestMetric {
+    // unique opens per delivery
+    Open,
+    // unique clicks per delivery
+    Click,
+}
+
+impl SubjectTestMetric {
+    pub fn parse(s: &str) -> Result<SubjectTestMetric, String> {
+        match s {
+            "open" => Ok(Self::Open),
+            "click" => Ok(Self::Click),
+            other => Err(format!(
+                "{} is not a supported metric. Use either `open` or `click`.",
+                other
+            )),
+        }
+    }
+
+    /// The kind of tracking event the metric counts.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubjectTestMetric::Open => "open",
+            SubjectTestMetric::Click => "click",
+        }
+    }
+}
+
+pub struct SubjectTest {
+    pub subjects: Vec<String>,
+    pub templates: Vec<NewsletterTemplate>,
+    pub sample_percent: u32,
+    pub wait: Duration,
+    pub metric: SubjectTestMetric,
+}
+
+impl SubjectTest {
+    pub fn parse(data: SubjectTestData) -> Result<SubjectTest, PublishError> {
+        if data.subjects.len() < 2 || data.subjects.len() > MAX_VARIANTS {
+            return Err(PublishError::ValidationError(format!(
+                "A subject test needs between 2 and {} subjects.",
+                MAX_VARIANTS
+            )));
+        }
+        if !(1..100).contains(&data.sample_percent) {
+            return Err(PublishError::ValidationError(
+                "The sample of a subject test must be between 1 and 99 percent of the list."
+                    .into(),
+            ));
+        }
+        if !(1..=MAX_WAIT_MINUTES).contains(&data.wait_minutes) {
+            return Err(PublishError::ValidationError(format!(
+                "A subject test must wait between 1 and {} minutes.",
+                MAX_WAIT_MINUTES
+            )));
+        }
+        let metric = SubjectTestMetric::parse(&data.metric).map_err(PublishError::ValidationError)?;
+        let templates = data
+            .subjects
+            .iter()
+            .map(|s| parse_title(s))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            subjects: data.subjects,
+            templates,
+            sample_percent: data.sample_percent,
+            wait: Duration::minutes(data.wait_minutes),
+            metric,
+        })
+    }
+}
+
+/// The variant received by each subscriber, `None` for the remainder
+/// who will get the winning subject.
+///
+/// Subscribers are shuffled by a hash of their id salted with the issue id,
+/// so the sample is random from one issue to the next
+/// but the same for a given issue, e.g. when the assignment is replayed in tests.
+/// Variants are dealt in turn so that they get samples of the same size.
+pub fn assign_variants(
+    issue_id: Uuid,
+    subscriber_ids: &[Uuid],
+    sample_percent: u32,
+    variants: usize,
+) -> Vec<Option<usize>> {
+    let total = subscriber_ids.len();
+    let sample_size = (total * sample_percent as usize + 99) / 100;
+    // every variant gets at least one recipient when the list is large enough
+    let sample_size = sample_size.max(variants).min(total);
+    let mut order: Vec<(Vec<u8>, usize)> = subscriber_ids
+        .iter()
+        .enumerate()
+        .map(|(i, subscriber_id)| {
+            let mut hasher = Sha256::new();
+            hasher.update(issue_id.as_bytes());
+            hasher.update(subscriber_id.as_bytes());
+            (hasher.finalize().to_vec(), i)
+        })
+        .collect();
+    order.sort();
+    let mut assignments = vec![None; total];
+    for (rank, (_, i)) in order.into_iter().take(sample_size).enumerate() {
+        assignments[i] = Some(rank % variants);
+    }
+    assignments
+}
+
+/// The outcome of a variant when the test is decided.
+#[derive(Debug, Clone, Copy)]
+pub struct VariantResult {
+    pub variant: usize,
+    pub sent: i64,
+    // recipients who opened, or clicked, at least once
+    pub engaged: i64,
+}
+
+/// The variant with the best engagement rate, the first one on a tie.
+pub fn pick_winner(results: &[VariantResult]) -> usize {
+    results
+        .iter()
+        .max_by(|a, b| {
+            // compare engaged / sent without going through floats
+            (a.engaged * b.sent.max(1))
+                .cmp(&(b.engaged * a.sent.max(1)))
+                .then(b.variant.cmp(&a.variant))
+        })
+        .map(|r| r.variant)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assign_variants, pick_winner, VariantResult};
+    use uuid::Uuid;
+
+    fn subscriber_ids(n: usize) -> Vec<Uuid> {
+        (0..n).map(|_| Uuid::new_v4()).collect()
+    }
+
+    #[test]
+    fn a_sample_of_the_list_is_split_evenly_between_variants() {
+        let ids = subscriber_ids(100);
+        let assignments = assign_variants(Uuid::new_v4(), &ids, 20, 2);
+        let count = |variant| assignments.iter().filter(|a| **a == variant).count();
+        assert_eq!(count(Some(0)), 10);
+        assert_eq!(count(Some(1)), 10);
+        assert_eq!(count(None), 80);
+    }
+
+    #[test]
+    fn the_assignment_is_the_same_for_the_same_issue() {
+        let ids = subscriber_ids(50);
+        let issue_id = Uuid::new_v4();
+        assert_eq!(
+            assign_variants(issue_id, &ids, 10, 3),
+            assign_variants(issue_id, &ids, 10, 3)
+        );
+    }
+
+    #[test]
+    fn every_variant_is_sent_on_small_lists() {
+        let ids = subscriber_ids(3);
+        let mut assignments = assign_variants(Uuid::new_v4(), &ids, 1, 2);
+        assignments.sort();
+        assert_eq!(assignments, vec![None, Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn the_variant_with_the_best_rate_wins() {
+        let results = [
+            VariantResult { variant: 0, sent: 10, engaged: 3 },
+            VariantResult { variant: 1, sent: 5, engaged: 2 },
+            VariantResult { variant: 2, sent: 10, engaged: 1 },
+        ];
+        assert_eq!(pick_winner(&results), 1);
+    }
+
+    #[test]
+    fn the_first_variant_wins_a_tie() {
+        let results = [
+            VariantResult { variant: 1, sent: 4, engaged: 2 },
+            VariantResult { variant: 0, sent: 2, engaged: 1 },
+            VariantResult { variant: 2, sent: 0, engaged: 0 },
+        ];
+        assert_eq!(pick_winner(&results), 0);
+    }
+}
@@ -0,0 +1,210 @@
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use super::PublishError;
+
+const MAX_PER_HOUR: u32 = 1_000_000;
+// how far ahead a completion is estimated before giving up
+const MAX_ESTIMATE_DAYS: i64 = 366;
+
+#[derive(serde::Deserialize)]
+pub struct ThrottleData {
+    max_per_hour: Option<u32>,
+    window: Option<DeliveryWindowData>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeliveryWindowData {
+    // local times of day, e.g. `08:00` and `20:00`
+    start: String,
+    end: String,
+    // an IANA name, e.g. `Europe/Berlin`
+    timezone: String,
+}
+
+/// How fast, and when, the deliveries of an issue may go out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Throttle {
+    pub max_per_hour: Option<u32>,
+    pub window: Option<DeliveryWindow>,
+}
+
+/// The time of day deliveries are allowed, every day.
+/// A window ending before it starts runs overnight, e.g. from 22:00 to 06:00.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeliveryWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub timezone: Tz,
+}
+
+impl Throttle {
+    pub fn parse(data: ThrottleData) -> Result<Throttle, PublishError> {
+        if let Some(max_per_hour) = data.max_per_hour {
+            if !(1..=MAX_PER_HOUR).contains(&max_per_hour) {
+                return Err(PublishError::ValidationError(format!(
+                    "The maximum number of deliveries per hour must be between 1 and {}.",
+                    MAX_PER_HOUR
+                )));
+            }
+        }
+        let window = data
+            .window
+            .map(|w| DeliveryWindow::parse(&w.start, &w.end, &w.timezone))
+            .transpose()
+            .map_err(PublishError::ValidationError)?;
+        if data.max_per_hour.is_none() && window.is_none() {
+            return Err(PublishError::ValidationError(
+                "A throttle needs a `max_per_hour`, a `window` or both.".into(),
+            ));
+        }
+        Ok(Self {
+            max_per_hour: data.max_per_hour,
+            window,
+        })
+    }
+
+    /// When `pending` deliveries will have been sent if they start at `now`,
+    /// assuming the worker keeps up with the hourly cap.
+    /// Without a cap, deliveries are as fast as the worker
+    /// and the estimate is the moment they can start.
+    pub fn estimate_completion(&self, pending: i64, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if pending <= 0 {
+            return None;
+        }
+        let mut needed = match self.max_per_hour {
+            Some(max_per_hour) => (pending * 3600 + max_per_hour as i64 - 1) / max_per_hour as i64,
+            None => 0,
+        };
+        let window = match self.window {
+            Some(window) => window,
+            None => return Some(now + Duration::seconds(needed)),
+        };
+        let mut t = now;
+        while t < now + Duration::days(MAX_ESTIMATE_DAYS) {
+            let (open, close) = window.next_opening(t);
+            let available = (close - open).num_seconds();
+            if needed <= available {
+                return Some(open + Duration::seconds(needed));
+            }
+            needed -= available;
+            t = close;
+        }
+        None
+    }
+}
+
+impl DeliveryWindow {
+    pub fn parse(start: &str, end: &str, timezone: &str) -> Result<DeliveryWindow, String> {
+        let parse_time = |s: &str| {
+            NaiveTime::parse_from_str(s, "%H:%M")
+                .map_err(|_| format!("{} is not a time of day formatted as HH:MM.", s))
+        };
+        let start = parse_time(start)?;
+        let end = parse_time(end)?;
+        if start == end {
+            return Err("The delivery window must not start and end at the same time.".into());
+        }
+        let timezone = timezone
+            .parse()
+            .map_err(|_| format!("{} is not a known timezone.", timezone))?;
+        Ok(Self {
+            start,
+            end,
+            timezone,
+        })
+    }
+
+    /// The window open at `t`, or the next one, as a pair of opening and closing instants.
+    /// The opening is never before `t`.
+    pub fn next_opening(&self, t: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        // yesterday's window may still be open when it runs overnight
+        let mut day = t.with_timezone(&self.timezone).date_naive() - Duration::days(1);
+        loop {
+            let open = self.localize(day.and_time(self.start));
+            let close_day = if self.end > self.start { day } else { day + Duration::days(1) };
+            let close = self.localize(close_day.and_time(self.end));
+            if close > t {
+                return (open.max(t), close);
+            }
+            day += Duration::days(1);
+        }
+    }
+
+    // the instant of a local time, moved past the gap when clocks go forward
+    fn localize(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        match self.timezone.from_local_datetime(&local) {
+            LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => t.with_timezone(&Utc),
+            LocalResult::None => self.localize(local + Duration::hours(1)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeliveryWindow, Throttle};
+    use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+    use claim::{assert_err, assert_ok};
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap())
+    }
+
+    fn berlin_office_hours() -> DeliveryWindow {
+        DeliveryWindow::parse("08:00", "20:00", "Europe/Berlin").unwrap()
+    }
+
+    #[test]
+    fn windows_are_parsed_and_validated() {
+        assert_ok!(DeliveryWindow::parse("22:00", "06:00", "America/New_York"));
+        assert_err!(DeliveryWindow::parse("8am", "20:00", "Europe/Berlin"));
+        assert_err!(DeliveryWindow::parse("08:00", "08:00", "Europe/Berlin"));
+        assert_err!(DeliveryWindow::parse("08:00", "20:00", "Europe/Atlantis"));
+    }
+
+    #[test]
+    fn the_window_open_now_starts_now() {
+        // 10:00 in Berlin during winter time
+        let now = utc("2023-01-10 09:00");
+        let (open, close) = berlin_office_hours().next_opening(now);
+        assert_eq!(open, now);
+        assert_eq!(close, utc("2023-01-10 19:00"));
+    }
+
+    #[test]
+    fn a_closed_window_opens_the_next_morning() {
+        let (open, close) = berlin_office_hours().next_opening(utc("2023-07-10 20:00"));
+        // summer time, Berlin is two hours ahead of UTC
+        assert_eq!(open, utc("2023-07-11 06:00"));
+        assert_eq!(close, utc("2023-07-11 18:00"));
+    }
+
+    #[test]
+    fn an_overnight_window_is_open_after_midnight() {
+        let window = DeliveryWindow::parse("22:00", "06:00", "UTC").unwrap();
+        let (open, close) = window.next_opening(utc("2023-01-10 02:00"));
+        assert_eq!(open, utc("2023-01-10 02:00"));
+        assert_eq!(close, utc("2023-01-10 06:00"));
+    }
+
+    #[test]
+    fn completion_spreads_over_the_next_windows() {
+        let throttle = Throttle {
+            max_per_hour: Some(5000),
+            window: Some(berlin_office_hours()),
+        };
+        // 12 hours a day at 5,000 per hour, so 60,000 a day: two days and four hours
+        let estimate = throttle.estimate_completion(140_000, utc("2023-01-10 07:00"));
+        assert_eq!(estimate, Some(utc("2023-01-12 11:00")));
+    }
+
+    #[test]
+    fn completion_without_a_window_only_depends_on_the_cap() {
+        let throttle = Throttle {
+            max_per_hour: Some(100),
+            window: None,
+        };
+        let estimate = throttle.estimate_completion(250, utc("2023-01-10 07:00"));
+        assert_eq!(estimate, Some(utc("2023-01-10 09:30")));
+        assert_eq!(throttle.estimate_completion(0, utc("2023-01-10 07:00")), None);
+    }
+}
@@ -0,0 +1,132 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::domain::SubscriberEmail;
+use crate::templating::{escape_html, render_template};
+use super::{html_page, record_subscriber_change, PreferencesError, TokenParameters};
+
+const EMAIL_CHANGE_VALIDITY_DAYS: i64 = 7;
+
+struct EmailChangeRequest {
+    subscriber_id: Uuid,
+    new_email: String,
+    new_email_display: Option<String>,
+    created_at: DateTime<Utc>,
+    old_email: String,
+}
+
+// like unsubscribing, following the link must not change anything on its own:
+// mail scanners prefetch links, so the link leads to a confirmation form
+#[tracing::instrument(
+    name = "Show the email change confirmation form",
+    skip(parameters, pool)
+)]
+pub async fn confirm_email_change_form(
+    parameters: web::Query<TokenParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let request = get_email_change_request(&pool, &parameters.token).await?;
+    let content = render_template(
+        include_str!("confirm_email.html"),
+        &[
+            (
+                "new_email",
+                &escape_html(request.new_email_display.as_deref().unwrap_or(&request.new_email)),
+            ),
+            ("token", &escape_html(&parameters.token)),
+        ],
+    );
+    Ok(html_page("Confirm your new address", &content))
+}
+
+/// Swap the subscriber address for the one they just proved to own.
+#[tracing::instrument(
+    name = "Confirm an email change",
+    skip(form, pool)
+)]
+pub async fn confirm_email_change(
+    form: web::Form<TokenParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let request = get_email_change_request(&pool, &form.token).await?;
+
+    let new_email = SubscriberEmail::parse(request.new_email.clone())
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("The requested email address is invalid.")?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET email = $1, normalized_email = lower($1), email_unicode = $2, email_display = $3
+        WHERE id = $4
+        "#,
+        new_email.as_ref(),
+        new_email.unicode(),
+        // requests made before display forms were kept
+        request.new_email_display.as_deref().unwrap_or(&request.new_email),
+        request.subscriber_id
+    )
+    .execute(&mut transaction)
+    .await;
+    if let Err(sqlx::Error::Database(e)) = &updated {
+        // somebody subscribed with the same address in the meantime
+        if e.code().as_deref() == Some("23505") {
+            return Err(PreferencesError::ValidationError(format!(
+                "{} is already subscribed to our newsletter.",
+                request.new_email
+            )));
+        }
+    }
+    updated.context("Failed to update the subscriber email.")?;
+    sqlx::query!(
+        r#"DELETE FROM email_change_requests WHERE subscriber_id = $1"#,
+        request.subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the email change requests.")?;
+    record_subscriber_change(
+        &mut transaction,
+        request.subscriber_id,
+        "email_changed",
+        Some(&request.old_email),
+        Some(&request.new_email),
+    )
+    .await
+    .context("Failed to record the email change.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change a subscriber email.")?;
+
+    Ok(html_page("Email address updated", include_str!("email_changed.html")))
+}
+
+async fn get_email_change_request(
+    pool: &PgPool,
+    token: &str,
+) -> Result<EmailChangeRequest, PreferencesError> {
+    let request = sqlx::query_as!(
+        EmailChangeRequest,
+        r#"
+        SELECT r.subscriber_id, r.new_email, r.new_email_display, r.created_at, s.email AS old_email
+        FROM email_change_requests r
+        JOIN subscriptions s ON s.id = r.subscriber_id
+        WHERE r.confirmation_token = $1
+        "#,
+        token,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the email change request.")?
+    .ok_or(PreferencesError::InvalidToken)?;
+    if request.created_at < Utc::now() - Duration::days(EMAIL_CHANGE_VALIDITY_DAYS) {
+        return Err(PreferencesError::InvalidToken);
+    }
+    Ok(request)
+}
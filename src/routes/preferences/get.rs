@@ -0,0 +1,67 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use crate::templating::{escape_html, render_template};
+use super::{
+    get_list_memberships, get_subscriber_by_preferences_token, html_page, PreferencesError,
+    TokenParameters,
+};
+
+#[tracing::instrument(
+    name = "Show the preference center",
+    skip(parameters, pool)
+)]
+pub async fn preferences_form(
+    parameters: web::Query<TokenParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber = get_subscriber_by_preferences_token(&pool, &parameters.token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the provided token.")?
+        .ok_or(PreferencesError::InvalidToken)?;
+
+    let memberships = get_list_memberships(&pool, subscriber.id)
+        .await
+        .context("Failed to retrieve the list memberships of the subscriber.")?;
+    let lists: String = memberships
+        .iter()
+        .map(|m| {
+            format!(
+                "<label><input type=\"checkbox\" name=\"list_{}\" value=\"on\" {}> {}</label>",
+                m.slug,
+                if m.is_active() { "checked" } else { "" },
+                escape_html(&m.name)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let status_message = match subscriber.status.as_str() {
+        "confirmed" => "You are subscribed to our newsletter.",
+        "pending_confirmation" => "Your subscription is waiting for confirmation.",
+        _ => "You are not subscribed to our newsletter.",
+    };
+    let checked = |format: &str| {
+        if subscriber.content_format == format {
+            "checked"
+        } else {
+            ""
+        }
+    };
+    let content = render_template(
+        include_str!("preferences.html"),
+        &[
+            ("status_message", status_message),
+            ("token", &escape_html(&parameters.token)),
+            ("name", &escape_html(&subscriber.name)),
+            ("email", &escape_html(&subscriber.email_display)),
+            ("lists", &lists),
+            ("html_checked", checked("html")),
+            ("text_checked", checked("text")),
+            (
+                "tracking_checked",
+                if subscriber.tracking_enabled { "checked" } else { "" },
+            ),
+        ],
+    );
+    Ok(html_page("Your subscription", &content))
+}
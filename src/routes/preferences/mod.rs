@@ -0,0 +1,195 @@
+mod confirm_email;
+mod get;
+mod post;
+mod unsubscribe;
+pub use confirm_email::{confirm_email_change, confirm_email_change_form};
+pub use get::preferences_form;
+pub use post::update_preferences;
+pub use unsubscribe::{unsubscribe, unsubscribe_form};
+
+use actix_web::{HttpResponse, ResponseError};
+use actix_web::http::StatusCode;
+use actix_web::http::header::ContentType;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::domain::Locale;
+use crate::localization::message;
+use crate::routes::{error_chain_fmt, localized_error_response, render_page, LocalizedError};
+use crate::templating::{escape_html, render_template};
+
+#[derive(serde::Deserialize)]
+pub struct TokenParameters {
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("The preferences token is invalid.")]
+    InvalidToken,
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PreferencesError::InvalidToken => StatusCode::UNAUTHORIZED,
+            PreferencesError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PreferencesError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // the preference center itself is only available in English,
+    // its unsubscribe pages use `Localized`
+    fn error_response(&self) -> HttpResponse {
+        localized_error_response(self, Locale::En)
+    }
+}
+
+impl LocalizedError for PreferencesError {
+    fn page(&self, locale: Locale) -> (String, String) {
+        let (heading, text) = match self {
+            PreferencesError::InvalidToken => (
+                message(locale, "invalid-link-heading", &[]),
+                message(locale, "invalid-preferences-link-message", &[]),
+            ),
+            // validation errors come from the preference center
+            PreferencesError::ValidationError(e) => {
+                (message(locale, "invalid-preferences-title", &[]), e.clone())
+            }
+            PreferencesError::UnexpectedError(_) => (
+                message(locale, "something-went-wrong", &[]),
+                message(locale, "preferences-error-message", &[]),
+            ),
+        };
+        let content = render_template(
+            include_str!("error.html"),
+            &[("heading", &escape_html(&heading)), ("message", &escape_html(&text))],
+        );
+        (heading, content)
+    }
+}
+
+pub struct SubscriberRecord {
+    pub id: Uuid,
+    pub email: String,
+    // the address as the subscriber typed it
+    pub email_display: String,
+    pub name: String,
+    pub status: String,
+    pub content_format: String,
+    pub tracking_enabled: bool,
+    pub locale: Option<String>,
+}
+
+pub struct ListMembership {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub status: Option<String>,
+}
+
+impl ListMembership {
+    fn is_active(&self) -> bool {
+        matches!(
+            self.status.as_deref(),
+            Some("confirmed") | Some("pending_confirmation")
+        )
+    }
+}
+
+pub fn preferences_url(base_url: &str, preferences_token: &str) -> String {
+    format!("{}/preferences?token={}", base_url, preferences_token)
+}
+
+pub fn unsubscribe_url(base_url: &str, preferences_token: &str) -> String {
+    format!("{}/preferences/unsubscribe?token={}", base_url, preferences_token)
+}
+
+fn html_page(title: &str, content: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(render_page(title, content))
+}
+
+#[tracing::instrument(
+    name = "Get subscriber from preferences token",
+    skip(pool, preferences_token)
+)]
+async fn get_subscriber_by_preferences_token(
+    pool: &PgPool,
+    preferences_token: &str,
+) -> Result<Option<SubscriberRecord>, sqlx::Error> {
+    let record = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, email_display, name, status, content_format, tracking_enabled, locale
+        FROM subscriptions
+        WHERE preferences_token = $1
+        "#,
+        preferences_token,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(record)
+}
+
+/// Every list, along with the subscriber's membership status if they ever joined it.
+#[tracing::instrument(name = "Get list memberships of a subscriber", skip(pool))]
+async fn get_list_memberships(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ListMembership>, sqlx::Error> {
+    sqlx::query_as!(
+        ListMembership,
+        r#"
+        SELECT l.id AS list_id, l.slug, l.name, m.status AS "status?"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.id AND m.subscriber_id = $1
+        ORDER BY l.created_at, l.slug
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Keep track of a change to a subscriber,
+/// it must be stored in the same transaction as the change itself.
+#[tracing::instrument(
+    name = "Record a change to a subscriber",
+    skip(transaction, old_value, new_value)
+)]
+pub async fn record_subscriber_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    action: &str,
+    old_value: Option<&str>,
+    new_value: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_audit_log
+            (id, subscriber_id, action, old_value, new_value, occurred_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        action,
+        old_value,
+        new_value,
+        Utc::now()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::domain::{ContentFormat, EmailPolicy, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::routes::{
+    find_delivery_rejection, find_email_rejection, generate_subscription_token, is_suppressed,
+};
+use crate::templating::{escape_html, render_template};
+use crate::startup::ApplicationBaseUrl;
+use super::{
+    get_list_memberships, get_subscriber_by_preferences_token, html_page, record_subscriber_change,
+    PreferencesError,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesFormData {
+    token: String,
+    name: String,
+    email: String,
+    content_format: String,
+    // only set by forms rendering the list checkboxes,
+    // so that clients unaware of lists don't leave all of them
+    update_lists: Option<String>,
+    // same as `update_lists`, for the tracking checkbox
+    update_tracking: Option<String>,
+    tracking: Option<String>,
+    // one `list_<slug>` entry for each checked list
+    #[serde(flatten)]
+    checkboxes: HashMap<String, String>,
+}
+
+struct ValidatedPreferences {
+    name: SubscriberName,
+    email: SubscriberEmail,
+    content_format: ContentFormat,
+    selected_lists: Option<Vec<String>>,
+    tracking_enabled: Option<bool>,
+}
+
+impl TryFrom<PreferencesFormData> for ValidatedPreferences {
+    type Error = String;
+
+    fn try_from(value: PreferencesFormData) -> Result<Self, Self::Error> {
+        let name = SubscriberName::parse(value.name)?;
+        let email = SubscriberEmail::parse(value.email)?;
+        let content_format = ContentFormat::parse(&value.content_format)?;
+        let selected_lists = value.update_lists.map(|_| {
+            value
+                .checkboxes
+                .keys()
+                .filter_map(|k| k.strip_prefix("list_"))
+                .map(String::from)
+                .collect()
+        });
+        let tracking_enabled = value.update_tracking.map(|_| value.tracking.is_some());
+        Ok(Self { name, email, content_format, selected_lists, tracking_enabled })
+    }
+}
+
+/// Apply the changes submitted from the preference center.
+/// A new email address only replaces the current one
+/// once the subscriber has confirmed they own it.
+#[tracing::instrument(
+    name = "Update subscriber preferences",
+    skip(form, pool, email_client, base_url, email_policy)
+)]
+pub async fn update_preferences(
+    form: web::Form<PreferencesFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    email_policy: web::Data<EmailPolicy>,
+) -> Result<HttpResponse, PreferencesError> {
+    let token = form.0.token.clone();
+    let subscriber = get_subscriber_by_preferences_token(&pool, &token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the provided token.")?
+        .ok_or(PreferencesError::InvalidToken)?;
+    let preferences: ValidatedPreferences = form
+        .0
+        .try_into()
+        .map_err(PreferencesError::ValidationError)?;
+
+    let email_changed = preferences.email.as_ref() != subscriber.email;
+    if email_changed && email_is_taken(&pool, &preferences.email, subscriber.id)
+        .await
+        .context("Failed to check whether the new email address is available.")?
+    {
+        return Err(PreferencesError::ValidationError(format!(
+            "{} is already subscribed to our newsletter.",
+            preferences.email
+        )));
+    }
+    if email_changed {
+        if let Some(reason) = find_email_rejection(&pool, &email_policy, &preferences.email).await? {
+            return Err(PreferencesError::ValidationError(reason));
+        }
+        if let Some(reason) = find_delivery_rejection(&email_client, &preferences.email) {
+            return Err(PreferencesError::ValidationError(reason));
+        }
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    if preferences.name.as_ref() != subscriber.name {
+        update_name(&mut transaction, subscriber.id, &preferences.name)
+            .await
+            .context("Failed to update the subscriber name.")?;
+        record_subscriber_change(
+            &mut transaction,
+            subscriber.id,
+            "name_changed",
+            Some(&subscriber.name),
+            Some(preferences.name.as_ref()),
+        )
+        .await
+        .context("Failed to record the name change.")?;
+    }
+    if preferences.content_format.as_str() != subscriber.content_format {
+        update_content_format(&mut transaction, subscriber.id, preferences.content_format)
+            .await
+            .context("Failed to update the subscriber content format.")?;
+        record_subscriber_change(
+            &mut transaction,
+            subscriber.id,
+            "content_format_changed",
+            Some(&subscriber.content_format),
+            Some(preferences.content_format.as_str()),
+        )
+        .await
+        .context("Failed to record the content format change.")?;
+    }
+    if let Some(tracking_enabled) = preferences.tracking_enabled {
+        if tracking_enabled != subscriber.tracking_enabled {
+            update_tracking(&mut transaction, subscriber.id, tracking_enabled)
+                .await
+                .context("Failed to update the subscriber tracking preference.")?;
+            record_subscriber_change(
+                &mut transaction,
+                subscriber.id,
+                "tracking_changed",
+                Some(&subscriber.tracking_enabled.to_string()),
+                Some(&tracking_enabled.to_string()),
+            )
+            .await
+            .context("Failed to record the tracking preference change.")?;
+        }
+    }
+    if let Some(selected_lists) = &preferences.selected_lists {
+        let memberships = get_list_memberships(&pool, subscriber.id)
+            .await
+            .context("Failed to retrieve the list memberships of the subscriber.")?;
+        // a subscriber who already proved to own their address doesn't need to confirm again
+        let joined_status = if subscriber.status == "confirmed" {
+            "confirmed"
+        } else {
+            "pending_confirmation"
+        };
+        for membership in memberships {
+            let wanted = selected_lists.contains(&membership.slug);
+            let slug = Some(membership.slug.as_str());
+            let (new_status, action, old_value, new_value) = match (wanted, membership.is_active()) {
+                (true, false) => (joined_status, "list_joined", None, slug),
+                (false, true) => ("unsubscribed", "list_left", slug, None),
+                _ => continue,
+            };
+            upsert_list_membership(&mut transaction, subscriber.id, membership.list_id, new_status)
+                .await
+                .context("Failed to update a list membership.")?;
+            record_subscriber_change(
+                &mut transaction,
+                subscriber.id,
+                action,
+                old_value,
+                new_value,
+            )
+            .await
+            .context("Failed to record the list membership change.")?;
+        }
+    }
+    let email_change_token = if email_changed {
+        let confirmation_token = generate_subscription_token();
+        store_email_change_request(
+            &mut transaction,
+            subscriber.id,
+            &preferences.email,
+            &confirmation_token,
+        )
+        .await
+        .context("Failed to store the email change request.")?;
+        record_subscriber_change(
+            &mut transaction,
+            subscriber.id,
+            "email_change_requested",
+            Some(&subscriber.email),
+            Some(preferences.email.as_ref()),
+        )
+        .await
+        .context("Failed to record the email change request.")?;
+        Some(confirmation_token)
+    } else {
+        None
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update subscriber preferences.")?;
+
+    let email_change_message = match email_change_token {
+        Some(confirmation_token) => {
+            send_email_change_confirmation(
+                &email_client,
+                &pool,
+                &preferences.email,
+                &base_url.0,
+                &confirmation_token,
+            )
+            .await
+            .context("Failed to send the email change confirmation.")?;
+            format!(
+                "<p>We sent a confirmation link to {}, \
+                your address will change once you follow it.</p>",
+                escape_html(preferences.email.as_ref())
+            )
+        }
+        None => String::new(),
+    };
+    let content = render_template(
+        include_str!("saved.html"),
+        &[
+            ("email_change_message", &email_change_message),
+            ("token", &escape_html(&token)),
+        ],
+    );
+    Ok(html_page("Preferences saved", &content))
+}
+
+// another subscriber has the address, whatever its case,
+// the subscriber may still change the case of their own
+#[tracing::instrument(name = "Check if an email is already subscribed", skip(pool))]
+async fn email_is_taken(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM subscriptions WHERE normalized_email = lower($1) AND id <> $2
+        ) AS "taken!"
+        "#,
+        email.as_ref(),
+        subscriber_id,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row.taken)
+}
+
+#[tracing::instrument(name = "Update subscriber name", skip(transaction, name))]
+async fn update_name(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    name: &SubscriberName,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET name = $1 WHERE id = $2"#,
+        name.as_ref(),
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Update subscriber content format", skip(transaction))]
+async fn update_content_format(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    content_format: ContentFormat,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET content_format = $1 WHERE id = $2"#,
+        content_format.as_str(),
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Update the subscriber tracking preference", skip(transaction))]
+async fn update_tracking(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    tracking_enabled: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET tracking_enabled = $1 WHERE id = $2"#,
+        tracking_enabled,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Update a list membership", skip(transaction))]
+async fn upsert_list_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (subscriber_id, list_id) DO UPDATE SET status = EXCLUDED.status
+        "#,
+        subscriber_id,
+        list_id,
+        status,
+        Utc::now()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Store an email change request",
+    skip(transaction, new_email, confirmation_token)
+)]
+async fn store_email_change_request(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    new_email: &SubscriberEmail,
+    confirmation_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_change_requests
+            (confirmation_token, subscriber_id, new_email, new_email_display)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        confirmation_token,
+        subscriber_id,
+        new_email.as_ref(),
+        new_email.display()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send an email change confirmation",
+    skip(email_client, pool, new_email, base_url, confirmation_token)
+)]
+async fn send_email_change_confirmation(
+    email_client: &EmailClient,
+    pool: &PgPool,
+    new_email: &SubscriberEmail,
+    base_url: &str,
+    confirmation_token: &str,
+) -> Result<(), anyhow::Error> {
+    if is_suppressed(pool, new_email.as_ref())
+        .await
+        .context("Failed to check whether the address is suppressed.")?
+    {
+        tracing::info!("Skipping the email change confirmation, the address is suppressed.");
+        return Ok(());
+    }
+    let confirmation_link = format!(
+        "{}/preferences/confirm-email?token={}",
+        base_url,
+        confirmation_token
+    );
+    let plain_body = format!(
+        "You asked to receive our newsletter at this address.\n\
+        Visit {} to confirm the change.",
+        confirmation_link
+    );
+    let html_body = format!(
+        "You asked to receive our newsletter at this address.<br />\
+        Click <a href=\"{}\">here</a> to confirm the change.",
+        confirmation_link
+    );
+    email_client
+        .send_email(new_email, "Confirm your new email address", &html_body, &plain_body)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use crate::domain::Locale;
+use crate::localization::{message, request_locale, stored_locale};
+use crate::routes::{render_localized_page, Localized};
+use crate::templating::{escape_html, render_template};
+use crate::startup::DefaultLocale;
+use super::{
+    get_subscriber_by_preferences_token, record_subscriber_change, PreferencesError,
+    SubscriberRecord, TokenParameters,
+};
+
+// following a link must never unsubscribe on its own,
+// mail scanners prefetch links, so the link leads to a confirmation form
+#[tracing::instrument(
+    name = "Show the unsubscribe form",
+    skip(request, parameters, pool, default_locale)
+)]
+pub async fn unsubscribe_form(
+    request: HttpRequest,
+    parameters: web::Query<TokenParameters>,
+    pool: web::Data<PgPool>,
+    default_locale: web::Data<DefaultLocale>,
+) -> Result<HttpResponse, Localized<PreferencesError>> {
+    let locale = request_locale(&request).unwrap_or(default_locale.0);
+    let subscriber = get_subscriber(&pool, &parameters.token)
+        .await
+        .map_err(|error| Localized { error, locale })?;
+    let locale = stored_locale(subscriber.locale.as_deref()).unwrap_or(locale);
+    let text = |id| escape_html(&message(locale, id, &[]));
+    let content = render_template(
+        include_str!("unsubscribe.html"),
+        &[
+            ("heading", &text("unsubscribe-title")),
+            ("question", &text("unsubscribe-question")),
+            ("button", &text("unsubscribe-button")),
+            ("manage_preferences", &text("unsubscribe-manage-preferences")),
+            ("token", &escape_html(&parameters.token)),
+        ],
+    );
+    Ok(page(locale, &message(locale, "unsubscribe-title", &[]), &content))
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(request, form, pool, default_locale)
+)]
+pub async fn unsubscribe(
+    request: HttpRequest,
+    form: web::Form<TokenParameters>,
+    pool: web::Data<PgPool>,
+    default_locale: web::Data<DefaultLocale>,
+) -> Result<HttpResponse, Localized<PreferencesError>> {
+    let locale = request_locale(&request).unwrap_or(default_locale.0);
+    let subscriber_locale = unsubscribe_subscriber(&pool, &form.token)
+        .await
+        .map_err(|error| Localized { error, locale })?;
+    let locale = subscriber_locale.unwrap_or(locale);
+    let text = |id| escape_html(&message(locale, id, &[]));
+    let content = render_template(
+        include_str!("unsubscribed.html"),
+        &[
+            ("heading", &text("unsubscribed-heading")),
+            ("message", &text("unsubscribed-message")),
+            ("back_to_homepage", &text("back-to-homepage")),
+        ],
+    );
+    Ok(page(locale, &message(locale, "unsubscribed-title", &[]), &content))
+}
+
+// returns the locale stored for the subscriber
+async fn unsubscribe_subscriber(
+    pool: &PgPool,
+    preferences_token: &str,
+) -> Result<Option<Locale>, PreferencesError> {
+    let subscriber = get_subscriber(pool, preferences_token).await?;
+
+    if subscriber.status != "unsubscribed" {
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        sqlx::query!(
+            r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1"#,
+            subscriber.id
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to update the subscriber status to `unsubscribed`.")?;
+        record_subscriber_change(
+            &mut transaction,
+            subscriber.id,
+            "unsubscribed",
+            Some(&subscriber.status),
+            Some("unsubscribed"),
+        )
+        .await
+        .context("Failed to record the unsubscription.")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to unsubscribe a subscriber.")?;
+    }
+    Ok(stored_locale(subscriber.locale.as_deref()))
+}
+
+async fn get_subscriber(
+    pool: &PgPool,
+    preferences_token: &str,
+) -> Result<SubscriberRecord, PreferencesError> {
+    get_subscriber_by_preferences_token(pool, preferences_token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the provided token.")?
+        .ok_or(PreferencesError::InvalidToken)
+}
+
+fn page(locale: Locale, title: &str, content: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(render_localized_page(locale, title, content))
+}
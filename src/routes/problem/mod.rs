@@ -0,0 +1,234 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{Accept, ContentType, HeaderValue, CONTENT_TYPE};
+use actix_web::http::StatusCode;
+use actix_web::{Error, HttpMessage, HttpResponse, ResponseError};
+use std::future::Future;
+use tracing_actix_web::RequestId;
+use crate::routes::render_page;
+use crate::templating::{escape_html, render_template};
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+// server errors don't leak their cause to the client, it is only logged
+const SERVER_ERROR_DETAIL: &str = "Something went wrong on our side, please try again later.";
+
+/// An error reported to API clients as RFC 7807 problem details.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ProblemDetails {
+    // a URI reference identifying the kind of problem, `about:blank` when the status says it all
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    // one entry for each invalid field of the request
+    #[serde(rename = "invalid-params", skip_serializing_if = "Vec::is_empty")]
+    pub invalid_params: Vec<InvalidParam>,
+    // matches the request id of the logs, set when the response is rendered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct InvalidParam {
+    pub name: String,
+    pub reason: String,
+}
+
+impl InvalidParam {
+    pub fn new(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl ProblemDetails {
+    /// A problem without a more specific type than its status.
+    pub fn new(status: StatusCode, detail: impl Into<String>) -> Self {
+        Self {
+            problem_type: "about:blank".into(),
+            title: status.canonical_reason().unwrap_or("Error").into(),
+            status: status.as_u16(),
+            detail: detail.into(),
+            invalid_params: Vec::new(),
+            request_id: None,
+        }
+    }
+
+    /// The request has invalid fields, every one of them is listed.
+    pub fn validation(invalid_params: Vec<InvalidParam>) -> Self {
+        Self {
+            problem_type: "/problems/validation".into(),
+            title: "Your request parameters didn't validate.".into(),
+            invalid_params,
+            ..Self::new(
+                StatusCode::BAD_REQUEST,
+                "Fix the invalid parameters and try again.",
+            )
+        }
+    }
+
+    /// The problem describing an error that didn't provide its own,
+    /// the message of client errors is their detail.
+    pub fn from_error(status: StatusCode, error: &Error) -> Self {
+        Self::describe(status, error)
+    }
+
+    /// The problem describing a route error, its message is the detail of client errors.
+    pub fn from_response_error(error: &impl ResponseError) -> Self {
+        Self::describe(error.status_code(), error)
+    }
+
+    fn describe(status: StatusCode, error: &dyn std::fmt::Display) -> Self {
+        if status.is_server_error() {
+            Self::new(status, SERVER_ERROR_DETAIL)
+        } else {
+            Self::new(status, error.to_string())
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    /// The response of an error, every route error renders itself this way.
+    /// `render_errors` finds the problem there and renders it as the client prefers.
+    pub fn response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code())
+            .content_type(PROBLEM_JSON)
+            .json(self);
+        response.extensions_mut().insert(self.clone());
+        response
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorFormat {
+    Json,
+    Html,
+}
+
+/// Middleware rendering every error response as problem details or as an HTML page.
+///
+/// The `Accept` header decides: browsers get a page, API clients get problem details.
+/// Without a preference, routes rendering their own error pages keep them
+/// and the others default to problem details.
+/// The error itself stays attached to the response, so its whole cause chain is still logged.
+pub fn render_errors<S, B>(
+    request: ServiceRequest,
+    service: &S,
+) -> impl Future<Output = Result<ServiceResponse<BoxBody>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody + 'static,
+{
+    let preferred = request.get_header::<Accept>().map(|accept| accept.preference());
+    let preferred = match preferred.as_ref().map(|mime| mime.essence_str()) {
+        Some("text/html") => Some(ErrorFormat::Html),
+        Some("application/json") | Some(PROBLEM_JSON) => Some(ErrorFormat::Json),
+        _ => None,
+    };
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.to_string())
+        .unwrap_or_default();
+    let response = service.call(request);
+    async move {
+        let response = response.await?;
+        let error = match response.response().error() {
+            Some(error) => error,
+            None => return Ok(response.map_into_boxed_body()),
+        };
+        let is_html = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map_or(false, |v| v.starts_with("text/html"));
+        let format = match (preferred, is_html) {
+            (Some(format), _) => format,
+            (None, true) => ErrorFormat::Html,
+            (None, false) => ErrorFormat::Json,
+        };
+        if format == ErrorFormat::Html && is_html {
+            return Ok(response.map_into_boxed_body());
+        }
+        let status = response.status();
+        let mut problem = response
+            .response()
+            .extensions()
+            .get::<ProblemDetails>()
+            .cloned()
+            .unwrap_or_else(|| ProblemDetails::from_error(status, error));
+        problem.request_id = Some(request_id);
+        let (content_type, body) = match format {
+            ErrorFormat::Json => (
+                HeaderValue::from_static(PROBLEM_JSON),
+                serde_json::to_string(&problem).unwrap_or_default(),
+            ),
+            ErrorFormat::Html => (
+                HeaderValue::from_str(&ContentType::html().to_string())
+                    .expect("The HTML content type is a valid header value"),
+                render_error_page(&problem),
+            ),
+        };
+        Ok(response.map_body(|head, _| {
+            head.headers.insert(CONTENT_TYPE, content_type);
+            BoxBody::new(body)
+        }))
+    }
+}
+
+fn render_error_page(problem: &ProblemDetails) -> String {
+    let mut message = problem.detail.clone();
+    for param in &problem.invalid_params {
+        message.push(' ');
+        message.push_str(&param.reason);
+    }
+    let content = render_template(
+        include_str!("error.html"),
+        &[
+            ("heading", &escape_html(&problem.title)),
+            ("message", &escape_html(&message)),
+            ("request_id", &escape_html(problem.request_id.as_deref().unwrap_or(""))),
+        ],
+    );
+    render_page(&problem.title, &content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProblemDetails, PROBLEM_JSON, SERVER_ERROR_DETAIL};
+    use crate::routes::PublishError;
+    use actix_web::http::header::CONTENT_TYPE;
+    use actix_web::ResponseError;
+
+    fn problem_of(error: &impl ResponseError) -> ProblemDetails {
+        let response = error.error_response();
+        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), PROBLEM_JSON);
+        let problem = response.extensions().get::<ProblemDetails>().cloned();
+        problem.expect("The response carries its problem details")
+    }
+
+    #[test]
+    fn route_errors_render_their_own_problem_details() {
+        let error = PublishError::ValidationError("The title of the issue is empty.".into());
+
+        let problem = problem_of(&error);
+
+        assert_eq!(problem.status, 400);
+        assert_eq!(problem.detail, "The title of the issue is empty.");
+    }
+
+    #[test]
+    fn route_errors_do_not_leak_the_cause_of_server_errors() {
+        let error = PublishError::UnexpectedError(anyhow::anyhow!("Connection refused"));
+
+        let problem = problem_of(&error);
+
+        assert_eq!(problem.status, 500);
+        assert_eq!(problem.detail, SERVER_ERROR_DETAIL);
+    }
+}
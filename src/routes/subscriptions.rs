@@ -1,26 +1,50 @@
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
 use sqlx::{PgPool, Transaction, Postgres};
 use uuid::Uuid;
 use chrono::Utc;
 use unicode_segmentation::UnicodeSegmentation;
-use crate::domain::{NewSubscriber, SubscriberName, SubscriberEmail};
+use crate::domain::{EmailPolicy, ListSlug, Locale, NewSubscriber, SubscriberName, SubscriberEmail};
 use crate::email_client::EmailClient;
-use crate::startup::ApplicationBaseUrl;
+use crate::localization::{message, request_locale};
+use crate::startup::{ApplicationBaseUrl, DefaultLocale, HmacSecret};
+use crate::routes::{
+    find_bot_signal, find_email_rejection, find_unknown_slug, get_lists_by_slug, is_suppressed, preferences_url,
+    reissue_confirmation_token, BotProtection, FormSubmission, ProblemDetails, DEFAULT_LIST_SLUG,
+};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use actix_web::http::StatusCode;
+use std::future::{ready, Ready};
+
+// the unique constraint on the normalized addresses of subscribers
+const NORMALIZED_EMAIL_CONSTRAINT: &str = "subscriptions_normalized_email_key";
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    // comma separated list slugs, the default list is used when missing
+    lists: Option<String>,
+    // the honeypot, hidden from humans
+    website: Option<String>,
+    // when the form was served, signed
+    form_token: Option<String>,
+    challenge_response: Option<String>,
+    // the language picked by the reader, their browser's one otherwise
+    locale: Option<String>,
 }
 
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("{0} is not a known list.")]
+    UnknownList(String),
+    // refused by the email policy, e.g. a disposable address
+    #[error("{0}")]
+    RejectedEmail(String),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error)
 }
@@ -34,10 +58,46 @@ impl std::fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError(_)
+            | SubscribeError::UnknownList(_)
+            | SubscribeError::RejectedEmail(_) => StatusCode::BAD_REQUEST,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        ProblemDetails::from_response_error(self).response()
+    }
+}
+
+/// The application data the subscription form is checked and registered with.
+pub struct SubscriptionSettings {
+    base_url: web::Data<ApplicationBaseUrl>,
+    secret: web::Data<HmacSecret>,
+    bot_protection: web::Data<BotProtection>,
+    email_policy: web::Data<EmailPolicy>,
+    default_locale: web::Data<DefaultLocale>,
+}
+
+impl FromRequest for SubscriptionSettings {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(SubscriptionSettings::from_app_data(request))
+    }
+}
+
+impl SubscriptionSettings {
+    fn from_app_data(request: &HttpRequest) -> Result<Self, actix_web::Error> {
+        Ok(Self {
+            base_url: web::Data::extract(request).into_inner()?,
+            secret: web::Data::extract(request).into_inner()?,
+            bot_protection: web::Data::extract(request).into_inner()?,
+            email_policy: web::Data::extract(request).into_inner()?,
+            default_locale: web::Data::extract(request).into_inner()?,
+        })
+    }
 }
 
 /// Store a new subscriber to our database.
@@ -49,28 +109,107 @@ impl ResponseError for SubscribeError {
 /// 
 /// * `form` - Extracted values from a url encoded request payload.
 /// * `pool` - Extracted values from a shared database connection pool.
+///
+/// Submissions looking like they come from a bot get the same response
+/// as any other, but nothing is stored and no email is sent.
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(request, form, pool, email_client, settings),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
     )
 )]
 pub async fn subscribe(
+    request: HttpRequest,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
-    base_url: web::Data<ApplicationBaseUrl>,
+    settings: SubscriptionSettings,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let submission = FormSubmission {
+        honeypot: form.website.as_deref(),
+        form_token: form.form_token.as_deref(),
+        challenge_response: form.challenge_response.as_deref(),
+    };
+    if let Some(signal) =
+        find_bot_signal(&settings.bot_protection, &settings.secret.0, &submission, Utc::now())
+        .await
+        .context("Failed to verify the challenge of the subscription form.")?
+    {
+        tracing::info!(signal, "Dropping a suspicious subscription");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let list_slugs = ListSlug::parse_many(form.0.lists.as_deref().unwrap_or(DEFAULT_LIST_SLUG))
+        .map_err(SubscribeError::ValidationError)?;
+    // an unsupported choice is ignored, the form only offers supported ones
+    let locale = form
+        .0
+        .locale
+        .as_deref()
+        .and_then(|l| Locale::parse(l).ok())
+        .or_else(|| request_locale(&request))
+        .unwrap_or(settings.default_locale.0);
+    let new_subscriber = parse_subscriber(form.0, locale).map_err(SubscribeError::ValidationError)?;
+    register_subscriber(
+        &pool,
+        &email_client,
+        &settings.email_policy,
+        &settings.base_url.0,
+        new_subscriber,
+        &list_slugs,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Store a new subscriber, pending confirmation, as a member of `list_slugs`
+/// and send them the confirmation email. Returns the id of the subscriber.
+pub async fn register_subscriber(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    email_policy: &EmailPolicy,
+    base_url: &str,
+    new_subscriber: NewSubscriber,
+    list_slugs: &[ListSlug],
+) -> Result<Uuid, SubscribeError> {
+    if let Some(reason) = find_email_rejection(pool, email_policy, &new_subscriber.email).await? {
+        return Err(SubscribeError::RejectedEmail(reason));
+    }
+    if let Some(reason) = find_delivery_rejection(email_client, &new_subscriber.email) {
+        return Err(SubscribeError::RejectedEmail(reason));
+    }
+    let lists = get_lists_by_slug(pool, list_slugs)
+        .await
+        .context("Failed to retrieve the requested lists.")?;
+    if let Some(unknown) = find_unknown_slug(list_slugs, &lists) {
+        return Err(SubscribeError::UnknownList(unknown.to_string()));
+    }
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let list_ids: Vec<Uuid> = lists.iter().map(|l| l.id).collect();
+    let preferences_token = generate_subscription_token();
+    let subscriber_id =
+        match insert_subscriber(&mut transaction, &new_subscriber, &preferences_token).await {
+            // the address is already subscribed, maybe written with a different case
+            Err(sqlx::Error::Database(e)) if e.constraint() == Some(NORMALIZED_EMAIL_CONSTRAINT) => {
+                drop(transaction);
+                return register_existing_subscriber(
+                    pool,
+                    email_client,
+                    base_url,
+                    new_subscriber,
+                    &list_ids,
+                )
+                .await;
+            }
+            inserted => inserted.context("Failed to insert new subscriber in the database.")?,
+        };
+    insert_list_memberships(&mut transaction, subscriber_id, &list_ids)
         .await
-        .context("Failed to insert new subscriber in the database.")?;
+        .context("Failed to add the new subscriber to the requested lists.")?;
     let subscription_token = generate_subscription_token();
     store_token(&mut transaction, subscriber_id, &subscription_token)
         .await
@@ -80,14 +219,82 @@ pub async fn subscribe(
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
     send_confirmation_email(
-        &email_client, 
+        email_client,
+        pool,
         new_subscriber,
-        &base_url.0,
+        base_url,
         &subscription_token,
+        &preferences_token,
     )
     .await
     .context("Failed to send a confirmation email.")?;
-    Ok(HttpResponse::Ok().finish())
+    Ok(subscriber_id)
+}
+
+// the response is the same as for a new address, so signing up can't be used
+// to find out who subscribed to the newsletter, pending subscribers get a fresh confirmation email.
+// The subscriber joins the lists of `list_ids` they aren't a member of yet.
+async fn register_existing_subscriber(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    new_subscriber: NewSubscriber,
+    list_ids: &[Uuid],
+) -> Result<Uuid, SubscribeError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber_id = get_subscriber_id_by_email(&mut transaction, &new_subscriber.email)
+        .await
+        .context("Failed to look up the subscriber of an email address.")?;
+    insert_missing_list_memberships(&mut transaction, subscriber_id, list_ids)
+        .await
+        .context("Failed to add an existing subscriber to the requested lists.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update an existing subscriber.")?;
+    if let Some(pending) =
+        reissue_confirmation_token(pool, &new_subscriber.email, new_subscriber.locale).await?
+    {
+        send_confirmation_email(
+            email_client,
+            pool,
+            pending.subscriber,
+            base_url,
+            &pending.subscription_token,
+            &pending.preferences_token,
+        )
+        .await
+        .context("Failed to send a confirmation email.")?;
+    }
+    Ok(subscriber_id)
+}
+
+#[tracing::instrument(name = "Get subscriber id by email", skip(transaction, email))]
+async fn get_subscriber_id_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Uuid, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE normalized_email = lower($1)"#,
+        email.as_ref(),
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok(row.id)
+}
+
+/// Why our email transport can't reach `email`, `None` when it can.
+pub fn find_delivery_rejection(email_client: &EmailClient, email: &SubscriberEmail) -> Option<String> {
+    if email_client.can_deliver_to(email) {
+        return None;
+    }
+    Some(format!(
+        "We can't send emails to {} yet, please use an address with only ASCII characters before the @.",
+        email
+    ))
 }
 
 pub struct StoreTokenError(sqlx::Error);
@@ -154,46 +361,59 @@ pub async fn store_token(
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
+    skip(email_client, pool, new_subscriber, base_url, subscription_token, preferences_token)
 )]
 pub async fn send_confirmation_email(
     email_client: &EmailClient,
+    pool: &PgPool,
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+    preferences_token: &str,
+) -> Result<(), anyhow::Error> {
+    if is_suppressed(pool, new_subscriber.email.as_ref())
+        .await
+        .context("Failed to check whether the address is suppressed.")?
+    {
+        tracing::info!("Skipping the confirmation email, the address is suppressed.");
+        return Ok(());
+    }
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}", 
         base_url,
         subscription_token
     );
+    let preferences_link = preferences_url(base_url, preferences_token);
+    let locale = new_subscriber.locale;
+    let welcome = message(locale, "confirmation-email-welcome", &[]);
     let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
+        "{}\n{}\n\n{}",
+        welcome,
+        message(locale, "confirmation-email-confirm-text", &[("link", &confirmation_link)]),
+        message(locale, "confirmation-email-manage-text", &[("link", &preferences_link)])
     );
     let html_body = format!(
-        "Welcome to our newsletter!<br />\
-        Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
+        "{}<br />{}<br /><br /><a href=\"{}\">{}</a>",
+        welcome,
+        message(locale, "confirmation-email-confirm-html", &[("link", &confirmation_link)]),
+        preferences_link,
+        message(locale, "manage-subscription", &[])
     );
     email_client
         .send_email(
             &new_subscriber.email, 
-            "Welcome", 
+            &message(locale, "confirmation-email-subject", &[]),
             &html_body, 
             &plain_body,
         )
-        .await
+        .await?;
+    Ok(())
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
-
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { email, name })
-    }
+fn parse_subscriber(form: FormData, locale: Locale) -> Result<NewSubscriber, String> {
+    let name = SubscriberName::parse(form.name)?;
+    let email = SubscriberEmail::parse(form.email)?;
+    Ok(NewSubscriber { email, name, locale })
 }
 
 // this procedure macro will capture the
@@ -201,29 +421,87 @@ impl TryFrom<FormData> for NewSubscriber {
 // attach them to structured logging provided by the tracing crate
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(new_subscriber, transaction)
+    skip(new_subscriber, transaction, preferences_token)
 )]
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
+    preferences_token: &str,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions
+            (id, email, normalized_email, email_unicode, email_display,
+            name, subscribed_at, status, preferences_token, locale)
+        VALUES ($1, $2, lower($2), $3, $4, $5, $6, 'pending_confirmation', $7, $8)
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
+        new_subscriber.email.unicode(),
+        new_subscriber.email.display(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        preferences_token,
+        new_subscriber.locale.as_str()
     )
     .execute(transaction)
-    .await
-    .map_err(|e| {e})?;
+    .await?;
     Ok(subscriber_id)
 }
 
+/// Add a subscriber to lists, pending the confirmation of their address.
+#[tracing::instrument(
+    name = "Saving new list memberships in the database",
+    skip(transaction)
+)]
+pub async fn insert_list_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status, created_at)
+        SELECT $1, list_id, 'pending_confirmation', $3
+        FROM UNNEST($2::uuid[]) AS list_id
+        "#,
+        subscriber_id,
+        list_ids,
+        Utc::now()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+// memberships of confirmed subscribers are confirmed right away,
+// the others wait for the confirmation of the address
+#[tracing::instrument(name = "Add missing list memberships", skip(transaction))]
+async fn insert_missing_list_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status, created_at)
+        SELECT s.id, list_id,
+            CASE WHEN s.status = 'confirmed' THEN 'confirmed' ELSE 'pending_confirmation' END,
+            $3
+        FROM subscriptions s, UNNEST($2::uuid[]) AS list_id
+        WHERE s.id = $1
+        ON CONFLICT (subscriber_id, list_id) DO NOTHING
+        "#,
+        subscriber_id,
+        list_ids,
+        Utc::now()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
 pub fn is_valid_name(s: &str) -> bool {
     let is_empty_or_whitespace = s.trim().is_empty();
     let is_too_long = s.graphemes(true).count() > 256;
@@ -233,7 +511,7 @@ pub fn is_valid_name(s: &str) -> bool {
     !(is_empty_or_whitespace || is_too_long || contains_forbidden_characters)
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -0,0 +1,222 @@
+use actix_web::{HttpRequest, HttpResponse, web, ResponseError};
+use actix_web::http::StatusCode;
+use actix_web::http::header::ContentType;
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::domain::Locale;
+use crate::localization::{message, request_locale, stored_locale};
+use crate::routes::{error_chain_fmt, render_localized_page, Localized, LocalizedError};
+use crate::templating::{escape_html, render_template};
+use crate::startup::DefaultLocale;
+
+// confirmation links older than this are treated as expired,
+// the subscriber can ask for a new one on the invalid link page
+const TOKEN_VALIDITY_DAYS: i64 = 7;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    subscription_token: Option<String>
+}
+
+#[derive(thiserror::Error)]
+pub enum ConfirmError {
+    #[error("The confirmation link is missing its subscription token.")]
+    MissingToken,
+    #[error("The subscription token is invalid or has expired.")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConfirmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConfirmError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConfirmError::MissingToken => StatusCode::BAD_REQUEST,
+            ConfirmError::InvalidToken => StatusCode::UNAUTHORIZED,
+            ConfirmError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// the confirmation link is opened by a human in a browser,
+// so every outcome is rendered as a page rather than a bare status code
+impl LocalizedError for ConfirmError {
+    fn page(&self, locale: Locale) -> (String, String) {
+        let text = |id| escape_html(&message(locale, id, &[]));
+        match self {
+            ConfirmError::MissingToken => {
+                let title = message(locale, "missing-token-title", &[]);
+                let content = render_template(
+                    include_str!("missing_token.html"),
+                    &[
+                        ("heading", &escape_html(&title)),
+                        ("message", &text("missing-token-message")),
+                        ("hint", &text("missing-token-hint")),
+                    ],
+                );
+                (title, content)
+            }
+            ConfirmError::InvalidToken => {
+                let content = render_template(
+                    include_str!("invalid_token.html"),
+                    &[
+                        ("heading", &text("invalid-link-heading")),
+                        ("message", &text("invalid-token-message")),
+                        ("hint", &text("invalid-token-hint")),
+                        ("email_label", &text("email-label")),
+                        ("email_placeholder", &text("email-placeholder")),
+                        ("button", &text("resend-confirmation-button")),
+                    ],
+                );
+                (message(locale, "invalid-token-title", &[]), content)
+            }
+            ConfirmError::UnexpectedError(_) => {
+                let title = message(locale, "something-went-wrong", &[]);
+                let content = render_template(
+                    include_str!("error.html"),
+                    &[
+                        ("heading", &escape_html(&title)),
+                        ("message", &text("confirm-error-message")),
+                    ],
+                );
+                (title, content)
+            }
+        }
+    }
+}
+
+// what following a valid confirmation link did
+enum Confirmation {
+    Confirmed,
+    AlreadyConfirmed,
+}
+
+struct TokenOwner {
+    subscriber_id: Uuid,
+    status: String,
+    token_created_at: DateTime<Utc>,
+    locale: Option<Locale>,
+}
+
+/// Pages are shown in the subscriber's locale,
+/// or in the one of their browser until we know who they are.
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(request, parameters, pool, default_locale)
+)]
+pub async fn confirm(
+    request: HttpRequest,
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    default_locale: web::Data<DefaultLocale>,
+) -> Result<HttpResponse, Localized<ConfirmError>> {
+    let locale = request_locale(&request).unwrap_or(default_locale.0);
+    let (confirmation, subscriber_locale) = confirm_token(&pool, parameters.0.subscription_token)
+        .await
+        .map_err(|error| Localized { error, locale })?;
+    let locale = subscriber_locale.unwrap_or(locale);
+    let (title, text) = match confirmation {
+        Confirmation::Confirmed => ("confirmed-title", "confirmed-message"),
+        Confirmation::AlreadyConfirmed => ("already-confirmed-title", "already-confirmed-message"),
+    };
+    let title = message(locale, title, &[]);
+    let content = render_template(
+        include_str!("confirmed.html"),
+        &[
+            ("heading", &escape_html(&title)),
+            ("message", &escape_html(&message(locale, text, &[]))),
+            ("back_to_homepage", &escape_html(&message(locale, "back-to-homepage", &[]))),
+        ],
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(render_localized_page(locale, &title, &content)))
+}
+
+async fn confirm_token(
+    pool: &PgPool,
+    subscription_token: Option<String>,
+) -> Result<(Confirmation, Option<Locale>), ConfirmError> {
+    let subscription_token = subscription_token.ok_or(ConfirmError::MissingToken)?;
+    let owner = get_token_owner(pool, &subscription_token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the provided token.")?
+        .ok_or(ConfirmError::InvalidToken)?;
+
+    match owner.status.as_str() {
+        "confirmed" => return Ok((Confirmation::AlreadyConfirmed, owner.locale)),
+        "pending_confirmation" => {}
+        // e.g. an unsubscribed reader clicking an old confirmation link
+        _ => return Err(ConfirmError::InvalidToken),
+    }
+    if owner.token_created_at < Utc::now() - Duration::days(TOKEN_VALIDITY_DAYS) {
+        return Err(ConfirmError::InvalidToken);
+    }
+    confirm_subscriber(pool, owner.subscriber_id)
+        .await
+        .context("Failed to update the subscriber status to `confirmed`.")?;
+    Ok((Confirmation::Confirmed, owner.locale))
+}
+
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, pool)
+)]
+pub async fn confirm_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Get the subscriber owning a token",
+    skip(subscription_token, pool)
+)]
+async fn get_token_owner(
+    pool: &PgPool,
+    subscription_token: &str
+) -> Result<Option<TokenOwner>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT t.subscriber_id, t.created_at, s.status, s.locale
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token = $1
+        "#,
+        subscription_token,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(result.map(|r| TokenOwner {
+        subscriber_id: r.subscriber_id,
+        status: r.status,
+        token_created_at: r.created_at,
+        locale: stored_locale(r.locale.as_deref()),
+    }))
+}
@@ -0,0 +1,215 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::http::StatusCode;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::Instrument;
+use uuid::Uuid;
+use crate::domain::{Locale, NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::localization::stored_locale;
+use crate::routes::{
+    error_chain_fmt, generate_subscription_token, send_confirmation_email, store_token,
+    ProblemDetails,
+};
+use crate::startup::{ApplicationBaseUrl, DefaultLocale};
+
+// how many confirmation emails (including the one sent at signup)
+// a single address can receive within a rolling hour
+const MAX_CONFIRMATION_EMAILS_PER_HOUR: i64 = 3;
+
+#[derive(serde::Deserialize)]
+pub struct ResendFormData {
+    email: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum ResendConfirmationError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ResendConfirmationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ResendConfirmationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ResendConfirmationError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ResendConfirmationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ProblemDetails::from_response_error(self).response()
+    }
+}
+
+/// A subscriber waiting for confirmation, with the token to send them.
+pub struct PendingConfirmation {
+    pub subscriber: NewSubscriber,
+    pub subscription_token: String,
+    pub preferences_token: String,
+}
+
+struct PendingSubscriber {
+    id: Uuid,
+    subscriber: NewSubscriber,
+    preferences_token: String,
+}
+
+/// Issue a fresh confirmation token to a subscriber still waiting for confirmation.
+/// The response is the same whether or not the address belongs to a pending subscriber,
+/// so this endpoint can't be used to find out who subscribed to the newsletter.
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(form, pool, email_client, base_url, default_locale),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn resend_confirmation(
+    form: web::Form<ResendFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    default_locale: web::Data<DefaultLocale>,
+) -> Result<HttpResponse, ResendConfirmationError> {
+    let email = SubscriberEmail::parse(form.0.email)
+        .map_err(ResendConfirmationError::ValidationError)?;
+    let pending = match reissue_confirmation_token(&pool, &email, default_locale.0).await? {
+        Some(pending) => pending,
+        None => return Ok(HttpResponse::Ok().finish()),
+    };
+
+    // the email goes out after we respond: waiting for Postmark would make the
+    // response slower for pending addresses than for unknown ones, and a delivery
+    // failure must not change the response either
+    actix_web::rt::spawn(
+        async move {
+            if let Err(e) = send_confirmation_email(
+                &email_client,
+                &pool,
+                pending.subscriber,
+                &base_url.0,
+                &pending.subscription_token,
+                &pending.preferences_token,
+            )
+            .await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    "Failed to resend a confirmation email."
+                );
+            }
+        }
+        .in_current_span(),
+    );
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Store a fresh confirmation token for the subscriber of `email` if they are still pending,
+/// `None` when they aren't or got too many confirmation emails within the last hour.
+pub async fn reissue_confirmation_token(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+    default_locale: Locale,
+) -> Result<Option<PendingConfirmation>, anyhow::Error> {
+    // the subscriber row stays locked until the new token is stored,
+    // so concurrent requests can't all get under the hourly cap
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let pending = get_pending_subscriber(&mut transaction, email, default_locale)
+        .await
+        .context("Failed to look up a pending subscriber.")?;
+    let pending = match pending {
+        Some(pending) => pending,
+        None => return Ok(None),
+    };
+
+    let recently_issued = count_tokens_issued_in_the_last_hour(&mut transaction, pending.id)
+        .await
+        .context("Failed to count the confirmation tokens issued recently.")?;
+    if recently_issued >= MAX_CONFIRMATION_EMAILS_PER_HOUR {
+        tracing::warn!(
+            subscriber_id = %pending.id,
+            "Too many confirmation emails requested within the last hour, skipping."
+        );
+        return Ok(None);
+    }
+
+    let subscription_token = generate_subscription_token();
+    store_token(&mut transaction, pending.id, &subscription_token)
+        .await
+        .context("Failed to store a new confirmation token.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new confirmation token.")?;
+    Ok(Some(PendingConfirmation {
+        subscriber: pending.subscriber,
+        subscription_token,
+        preferences_token: pending.preferences_token,
+    }))
+}
+
+#[tracing::instrument(
+    name = "Get pending subscriber by email",
+    skip(transaction, email)
+)]
+async fn get_pending_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+    default_locale: Locale,
+) -> Result<Option<PendingSubscriber>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT id, email, name, preferences_token, locale
+        FROM subscriptions
+        WHERE normalized_email = lower($1) AND status = 'pending_confirmation'
+        FOR UPDATE
+        "#,
+        email.as_ref(),
+    )
+    .fetch_optional(transaction)
+    .await?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(row.email).map_err(|e| anyhow::anyhow!(e))?,
+        name: SubscriberName::parse(row.name).map_err(|e| anyhow::anyhow!(e))?,
+        locale: stored_locale(row.locale.as_deref()).unwrap_or(default_locale),
+    };
+    Ok(Some(PendingSubscriber {
+        id: row.id,
+        subscriber,
+        preferences_token: row.preferences_token,
+    }))
+}
+
+#[tracing::instrument(
+    name = "Count confirmation tokens issued in the last hour",
+    skip(transaction)
+)]
+async fn count_tokens_issued_in_the_last_hour(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscription_tokens
+        WHERE subscriber_id = $1 AND created_at > now() - interval '1 hour'
+        "#,
+        subscriber_id,
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok(row.count)
+}
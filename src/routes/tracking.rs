@@ -0,0 +1,261 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::http::header::{self, CacheControl, CacheDirective};
+use actix_web::http::StatusCode;
+use anyhow::Context;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::routes::{error_chain_fmt, ProblemDetails};
+use crate::startup::HmacSecret;
+
+// a transparent 1x1 GIF
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00,
+    0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[derive(thiserror::Error)]
+pub enum TrackingError {
+    #[error("The tracking token is invalid.")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for TrackingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TrackingError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TrackingError::InvalidToken => StatusCode::NOT_FOUND,
+            TrackingError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ProblemDetails::from_response_error(self).response()
+    }
+}
+
+/// What a tracking token stands for.
+/// The kind is part of the signed claims,
+/// so that an open token can't be replayed as a click.
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct TrackingClaims {
+    kind: String,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    // the destination of a click
+    url: Option<String>,
+}
+
+/// Everything needed to make the HTML of an issue trackable for one recipient.
+pub struct TrackingContext<'a> {
+    pub base_url: &'a str,
+    pub secret: &'a Secret<String>,
+    pub issue_id: Uuid,
+    pub subscriber_id: Uuid,
+}
+
+/// Send every external link of `html` through the click redirect
+/// and append the open pixel.
+/// Links to the application itself, e.g. the unsubscribe link, are left alone.
+pub fn add_tracking(html: &str, context: &TrackingContext) -> String {
+    let base_url = context.base_url.to_string();
+    let secret = context.secret.clone();
+    let issue_id = context.issue_id;
+    let subscriber_id = context.subscriber_id;
+    let tracked = ammonia::Builder::default()
+        .attribute_filter(move |element, attribute, value| {
+            let is_external = (value.starts_with("http://") || value.starts_with("https://"))
+                && !value.starts_with(&base_url);
+            if element == "a" && attribute == "href" && is_external {
+                let claims = TrackingClaims {
+                    kind: "click".into(),
+                    issue_id,
+                    subscriber_id,
+                    url: Some(value.to_string()),
+                };
+                Some(format!("{}/t/c/{}", base_url, sign(&secret, &claims)).into())
+            } else {
+                Some(value.into())
+            }
+        })
+        .clean(html)
+        .to_string();
+    let claims = TrackingClaims {
+        kind: "open".into(),
+        issue_id,
+        subscriber_id,
+        url: None,
+    };
+    format!(
+        "{}<img src=\"{}/t/o/{}\" width=\"1\" height=\"1\" alt=\"\">",
+        tracked,
+        context.base_url,
+        sign(context.secret, &claims)
+    )
+}
+
+#[tracing::instrument(name = "Track an open", skip(token, pool, secret))]
+pub async fn track_open(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, TrackingError> {
+    let claims = verify(&secret.0, &token, "open").ok_or(TrackingError::InvalidToken)?;
+    record_event(&pool, &claims)
+        .await
+        .context("Failed to record an open.")?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(PIXEL))
+}
+
+#[tracing::instrument(name = "Track a click", skip(token, pool, secret))]
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, TrackingError> {
+    let claims = verify(&secret.0, &token, "click").ok_or(TrackingError::InvalidToken)?;
+    let url = claims.url.clone().ok_or(TrackingError::InvalidToken)?;
+    record_event(&pool, &claims)
+        .await
+        .context("Failed to record a click.")?;
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .finish())
+}
+
+fn sign(secret: &Secret<String>, claims: &TrackingClaims) -> String {
+    let payload = serde_json::to_vec(claims).unwrap();
+    let mut mac = new_mac(secret);
+    mac.update(&payload);
+    format!(
+        "{}.{}",
+        base64::encode_config(&payload, base64::URL_SAFE_NO_PAD),
+        base64::encode_config(mac.finalize().into_bytes(), base64::URL_SAFE_NO_PAD)
+    )
+}
+
+fn verify(secret: &Secret<String>, token: &str, kind: &str) -> Option<TrackingClaims> {
+    let (payload, tag) = token.split_once('.')?;
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+    let tag = base64::decode_config(tag, base64::URL_SAFE_NO_PAD).ok()?;
+    let mut mac = new_mac(secret);
+    mac.update(&payload);
+    mac.verify_slice(&tag).ok()?;
+    let claims: TrackingClaims = serde_json::from_slice(&payload).ok()?;
+    (claims.kind == kind).then(|| claims)
+}
+
+fn new_mac(secret: &Secret<String>) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size")
+}
+
+// subscribers may have opted out of tracking since the issue was sent
+#[tracing::instrument(name = "Record a tracking event", skip(pool))]
+async fn record_event(pool: &PgPool, claims: &TrackingClaims) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO tracking_events (id, issue_id, subscriber_id, kind, url, occurred_at)
+        SELECT $1, i.id, s.id, $4, $5, $6
+        FROM newsletter_issues i, subscriptions s
+        WHERE i.id = $2 AND s.id = $3 AND s.tracking_enabled
+        "#,
+        Uuid::new_v4(),
+        claims.issue_id,
+        claims.subscriber_id,
+        claims.kind,
+        claims.url,
+        Utc::now()
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_tracking, sign, verify, TrackingClaims, TrackingContext};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-secret".into())
+    }
+
+    fn claims(kind: &str) -> TrackingClaims {
+        TrackingClaims {
+            kind: kind.into(),
+            issue_id: Uuid::new_v4(),
+            subscriber_id: Uuid::new_v4(),
+            url: Some("https://example.com".into()),
+        }
+    }
+
+    #[test]
+    fn a_signed_token_is_verified() {
+        let claims = claims("click");
+        let token = sign(&secret(), &claims);
+        assert_eq!(verify(&secret(), &token, "click"), Some(claims));
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let token = sign(&Secret::new("another-secret".into()), &claims("click"));
+        assert_eq!(verify(&secret(), &token, "click"), None);
+    }
+
+    #[test]
+    fn a_tampered_token_is_rejected() {
+        let token = sign(&secret(), &claims("click"));
+        let (_, tag) = token.split_once('.').unwrap();
+        let mut forged = claims("click");
+        forged.url = Some("https://evil.example.com".into());
+        let payload = serde_json::to_vec(&forged).unwrap();
+        let forged_token = format!(
+            "{}.{}",
+            base64::encode_config(payload, base64::URL_SAFE_NO_PAD),
+            tag
+        );
+        assert_eq!(verify(&secret(), &forged_token, "click"), None);
+    }
+
+    #[test]
+    fn an_open_token_is_not_a_click_token() {
+        let token = sign(&secret(), &claims("open"));
+        assert_eq!(verify(&secret(), &token, "click"), None);
+    }
+
+    #[test]
+    fn external_links_are_rewritten_and_a_pixel_is_appended() {
+        let secret = secret();
+        let context = TrackingContext {
+            base_url: "http://127.0.0.1",
+            secret: &secret,
+            issue_id: Uuid::new_v4(),
+            subscriber_id: Uuid::new_v4(),
+        };
+        let tracked = add_tracking(
+            "<a href=\"https://example.com\">a</a>\
+            <a href=\"http://127.0.0.1/preferences?token=x\">b</a>",
+            &context,
+        );
+        assert!(tracked.starts_with("<a href=\"http://127.0.0.1/t/c/"));
+        assert!(tracked.contains("<a href=\"http://127.0.0.1/preferences?token=x\""));
+        assert!(tracked.ends_with("width=\"1\" height=\"1\" alt=\"\">"));
+        assert!(tracked.contains("<img src=\"http://127.0.0.1/t/o/"));
+    }
+}
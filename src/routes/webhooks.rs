@@ -0,0 +1,280 @@
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::configuration::WebhookSettings;
+use crate::routes::{
+    basic_authentication, error_chain_fmt, record_subscriber_change, suppress_email,
+    ProblemDetails,
+};
+
+/// The bounce types after which an address must not be mailed again.
+pub const HARD_BOUNCE_TYPES: [&str; 2] = ["HardBounce", "BadEmailAddress"];
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            WebhookError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            WebhookError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = ProblemDetails::from_response_error(self).response();
+        if let WebhookError::AuthError(_) = self {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(r#"Basic realm="webhooks""#),
+            );
+        }
+        response
+    }
+}
+
+/// The records Postmark posts to our webhook, keyed by their `RecordType`.
+/// Deliveries, opens and clicks are accepted but ignored.
+#[derive(serde::Deserialize)]
+#[serde(tag = "RecordType")]
+enum PostmarkEvent {
+    Bounce {
+        #[serde(rename = "Type")]
+        bounce_type: String,
+        #[serde(rename = "Email")]
+        email: String,
+        #[serde(rename = "Metadata", default)]
+        metadata: HashMap<String, String>,
+    },
+    SpamComplaint {
+        #[serde(rename = "Email")]
+        email: String,
+        #[serde(rename = "Metadata", default)]
+        metadata: HashMap<String, String>,
+    },
+    SubscriptionChange {
+        #[serde(rename = "Recipient")]
+        recipient: String,
+        #[serde(rename = "SuppressSending")]
+        suppress_sending: bool,
+        #[serde(rename = "SuppressionReason")]
+        suppression_reason: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl PostmarkEvent {
+    fn email(&self) -> Option<&str> {
+        match self {
+            Self::Bounce { email, .. } | Self::SpamComplaint { email, .. } => Some(email),
+            Self::SubscriptionChange { recipient, .. } => Some(recipient),
+            Self::Other => None,
+        }
+    }
+
+    /// The newsletter issue the event answers to, see `EmailClient::send_issue`.
+    fn issue_id(&self) -> Option<Uuid> {
+        match self {
+            Self::Bounce { metadata, .. } | Self::SpamComplaint { metadata, .. } => {
+                metadata.get("issue_id")?.parse().ok()
+            }
+            _ => None,
+        }
+    }
+
+    fn event_type(&self) -> Option<&str> {
+        match self {
+            Self::Bounce { bounce_type, .. } => Some(bounce_type),
+            Self::SubscriptionChange {
+                suppression_reason, ..
+            } => suppression_reason.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The status the subscriber ends up in, if the event means we must stop mailing them.
+    /// Soft bounces and the like are only recorded.
+    fn subscriber_status(&self) -> Option<&'static str> {
+        match self {
+            Self::Bounce { bounce_type, .. } => HARD_BOUNCE_TYPES
+                .contains(&bounce_type.as_str())
+                .then(|| "bounced"),
+            Self::SpamComplaint { .. } => Some("complained"),
+            Self::SubscriptionChange {
+                suppress_sending: true,
+                suppression_reason,
+                ..
+            } => match suppression_reason.as_deref() {
+                Some("HardBounce") => Some("bounced"),
+                Some("SpamComplaint") => Some("complained"),
+                Some("ManualSuppression") => Some("unsubscribed"),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Receive bounces, spam complaints and subscription changes from Postmark.
+/// Addresses we can no longer mail are suppressed, and their subscribers
+/// are moved out of the `confirmed` status.
+#[tracing::instrument(
+    name = "Receive a Postmark webhook",
+    skip(request, body, pool, settings),
+    fields(record_type = tracing::field::Empty)
+)]
+pub async fn postmark_webhook(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    settings: web::Data<WebhookSettings>,
+) -> Result<HttpResponse, WebhookError> {
+    let credentials = basic_authentication(request.headers()).map_err(WebhookError::AuthError)?;
+    if !credentials.matches(&settings.username, &settings.password) {
+        return Err(WebhookError::AuthError(anyhow::anyhow!(
+            "Invalid username or password."
+        )));
+    }
+
+    let payload: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| WebhookError::ValidationError(format!("Invalid JSON payload: {}", e)))?;
+    let record_type = payload["RecordType"].as_str().unwrap_or_default().to_string();
+    tracing::Span::current().record("record_type", record_type.as_str());
+    let event: PostmarkEvent = serde_json::from_value(payload.clone()).map_err(|e| {
+        WebhookError::ValidationError(format!("Invalid {} record: {}", record_type, e))
+    })?;
+    let email = match event.email() {
+        Some(email) => email,
+        None => return Ok(HttpResponse::Ok().finish()),
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber = get_subscriber_by_email(&mut transaction, email)
+        .await
+        .context("Failed to retrieve the subscriber associated with the event.")?;
+    store_event(
+        &mut transaction,
+        subscriber.as_ref().map(|s| s.id),
+        event.issue_id(),
+        email,
+        &record_type,
+        event.event_type(),
+        &payload,
+    )
+    .await
+    .context("Failed to store the email event.")?;
+    if let Some(new_status) = event.subscriber_status() {
+        suppress_email(&mut transaction, email, new_status, "postmark")
+            .await
+            .context("Failed to suppress the address.")?;
+    }
+    if let (Some(subscriber), Some(new_status)) = (subscriber, event.subscriber_status()) {
+        if subscriber.status != new_status {
+            update_subscriber_status(&mut transaction, subscriber.id, new_status)
+                .await
+                .context("Failed to update the subscriber status.")?;
+            record_subscriber_change(
+                &mut transaction,
+                subscriber.id,
+                new_status,
+                Some(&subscriber.status),
+                Some(new_status),
+            )
+            .await
+            .context("Failed to record the subscriber status change.")?;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store an email event.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+struct SubscriberStatus {
+    id: Uuid,
+    status: String,
+}
+
+#[tracing::instrument(name = "Get subscriber by email", skip(transaction, email))]
+async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<Option<SubscriberStatus>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberStatus,
+        r#"SELECT id, status FROM subscriptions WHERE normalized_email = lower($1) FOR UPDATE"#,
+        email
+    )
+    .fetch_optional(transaction)
+    .await
+}
+
+#[tracing::instrument(name = "Store an email event", skip(transaction, email, payload))]
+async fn store_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Option<Uuid>,
+    issue_id: Option<Uuid>,
+    email: &str,
+    record_type: &str,
+    event_type: Option<&str>,
+    payload: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events
+            (id, subscriber_id, issue_id, email, record_type, event_type, payload, received_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        issue_id,
+        email,
+        record_type,
+        event_type,
+        payload,
+        Utc::now()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Update the subscriber status", skip(transaction))]
+async fn update_subscriber_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE id = $1"#,
+        subscriber_id,
+        status
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
@@ -1,12 +1,27 @@
 use actix_web::{web, App, HttpServer};
 use actix_web::dev::Server;
 use std::net::TcpListener;
-use crate::routes::{ health_check, subscribe, confirm, publish_newsletter, home, login_form, login };
+use crate::routes::{
+    health_check, subscribe, confirm, resend_confirmation, publish_newsletter, home, login_form, login,
+    preferences_form, update_preferences, confirm_email_change, confirm_email_change_form,
+    unsubscribe_form, unsubscribe,
+    get_lists, create_list, tag_subscriber, untag_subscriber, update_subscriber_attributes,
+    postmark_webhook, get_suppressions, create_suppression, import_suppressions, get_suppression,
+    update_suppression, delete_suppression, get_issue_stats, get_issue_progress, pause_issue, resume_issue, cancel_issue, track_open, track_click, archive, archive_issue, update_issue_visibility,
+    rss_feed, atom_feed, api_subscribe, require_api_key, render_errors, get_email_domain_rules,
+    set_email_domain_rule, delete_email_domain_rule,
+};
 use sqlx::PgPool;
 use tracing_actix_web::TracingLogger;
 use crate::email_client::EmailClient;
-use crate::configuration::{Settings, DatabaseSettings};
+use crate::configuration::{AdminSettings, ApiSettings, Settings, DatabaseSettings, WebhookSettings};
 use sqlx::postgres::PgPoolOptions;
+use secrecy::Secret;
+use std::sync::Arc;
+use crate::challenge_verifier::ChallengeVerifier;
+use crate::routes::BotProtection;
+use crate::domain::{EmailPolicy, Locale};
+use crate::rate_limiting::RateLimiter;
 
 pub struct Application {
     port: u16,
@@ -15,19 +30,17 @@ pub struct Application {
 
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, std::io::Error> {
+        Self::build_with_challenge_verifier(configuration, None).await
+    }
+
+    /// `challenge_verifier` checks the challenge of the subscription form, e.g. a captcha.
+    pub async fn build_with_challenge_verifier(
+        configuration: Settings,
+        challenge_verifier: Option<Arc<dyn ChallengeVerifier>>,
+    ) -> Result<Self, std::io::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
     
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.authorization_token,
-            timeout
-        );
+        let email_client = configuration.email_client.client();
     
         let address = format!(
             "{}:{}",
@@ -35,11 +48,27 @@ impl Application {
         );
         let listener = TcpListener::bind(&address)?;
         let port = listener.local_addr().unwrap().port();
+        let email_policy = configuration.email_policy.policy()?;
+        let default_locale = configuration
+            .localization
+            .default_locale()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let rate_limiter = RateLimiter::from_settings(&configuration.rate_limits, connection_pool.clone());
         let server = run(
-            listener, 
-            connection_pool, 
-            email_client,
-            configuration.application.base_url,
+            listener,
+            AppState {
+                db_pool: connection_pool,
+                email_client,
+                base_url: configuration.application.base_url,
+                hmac_secret: configuration.application.hmac_secret,
+                webhook_settings: configuration.webhooks,
+                admin_settings: configuration.admin,
+                api_settings: configuration.api,
+                bot_protection: BotProtection::new(&configuration.bot_protection, challenge_verifier),
+                rate_limiter,
+                email_policy,
+                default_locale,
+            },
         )?;
 
         Ok(Self { port, server })
@@ -66,17 +95,47 @@ pub fn get_connection_pool(
 
 pub struct ApplicationBaseUrl(pub String);
 
+#[derive(Clone)]
+pub struct HmacSecret(pub Secret<String>);
+
+// the locale of readers who didn't ask for a supported one
+#[derive(Clone, Copy)]
+pub struct DefaultLocale(pub Locale);
+
+// what the routes and middlewares of every worker share
+pub struct AppState {
+    pub db_pool: PgPool,
+    pub email_client: EmailClient,
+    pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    pub webhook_settings: WebhookSettings,
+    pub admin_settings: AdminSettings,
+    pub api_settings: ApiSettings,
+    pub bot_protection: BotProtection,
+    pub rate_limiter: RateLimiter,
+    pub email_policy: EmailPolicy,
+    pub default_locale: Locale,
+}
+
 // start the server and return a Tokio server handler,
 // the reason to use listener as an input is,
 // we want to run the server on a random port,
 // but the port number is not available within the context of this library,
 // so we need to pass it into this function
-pub fn run(
-    listener: TcpListener,
-    db_pool: PgPool,
-    email_client: EmailClient,
-    base_url: String,
-) -> Result<Server, std::io::Error> {
+pub fn run(listener: TcpListener, state: AppState) -> Result<Server, std::io::Error> {
+    let AppState {
+        db_pool,
+        email_client,
+        base_url,
+        hmac_secret,
+        webhook_settings,
+        admin_settings,
+        api_settings,
+        bot_protection,
+        rate_limiter,
+        email_policy,
+        default_locale,
+    } = state;
     // wrap the db connection with actix_web's data extractor.
     // the reason is:
     // actix web will spawn an App on each cpu core,
@@ -88,24 +147,92 @@ pub fn run(
     
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let hmac_secret = web::Data::new(HmacSecret(hmac_secret));
+    let webhook_settings = web::Data::new(webhook_settings);
+    let admin_settings = web::Data::new(admin_settings);
+    let api_settings = web::Data::new(api_settings);
+    let bot_protection = web::Data::new(bot_protection);
+    let email_policy = web::Data::new(email_policy);
+    let default_locale = web::Data::new(DefaultLocale(default_locale));
     // this outer block handles the transport layer logic
     let server = HttpServer::new(move || {
         // this app block handles the application layer logic
         App::new()
+            // over the limit requests never reach the routes,
+            // their 429 is still rendered for the client and logged
+            .wrap(rate_limiter.clone())
+            // runs inside TracingLogger: the request id is known
+            // and the logger still sees the original error to record its cause chain
+            .wrap_fn(render_errors)
             // TracingLogger is a replacement for actix_web's native logger,
             // it provides an easy way to parse actix_web's loggings and
             // integrate them with tracing spans
             .wrap(TracingLogger::default())
             .route("/", web::get().to(home))
+            .route("/archive", web::get().to(archive))
+            .route("/archive/{slug}", web::get().to(archive_issue))
+            .route("/feed.rss", web::get().to(rss_feed))
+            .route("/feed.atom", web::get().to(atom_feed))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/subscriptions/resend-confirmation", web::post().to(resend_confirmation))
+            .service(
+                web::scope("/api/v1")
+                    .wrap_fn(require_api_key)
+                    .route("/subscriptions", web::post().to(api_subscribe)),
+            )
             .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/preferences", web::get().to(preferences_form))
+            .route("/preferences", web::post().to(update_preferences))
+            .route("/preferences/confirm-email", web::get().to(confirm_email_change_form))
+            .route("/preferences/confirm-email", web::post().to(confirm_email_change))
+            .route("/preferences/unsubscribe", web::get().to(unsubscribe_form))
+            .route("/preferences/unsubscribe", web::post().to(unsubscribe))
+            .route("/admin/lists", web::get().to(get_lists))
+            .route("/admin/lists", web::post().to(create_list))
+            .route("/admin/subscribers/{subscriber_id}/tags", web::post().to(tag_subscriber))
+            .route(
+                "/admin/subscribers/{subscriber_id}/tags/{tag}",
+                web::delete().to(untag_subscriber),
+            )
+            .route(
+                "/admin/subscribers/{subscriber_id}/attributes",
+                web::patch().to(update_subscriber_attributes),
+            )
+            .route("/admin/email-domains", web::get().to(get_email_domain_rules))
+            .route("/admin/email-domains/{domain}", web::put().to(set_email_domain_rule))
+            .route("/admin/email-domains/{domain}", web::delete().to(delete_email_domain_rule))
+            .route("/admin/suppressions", web::get().to(get_suppressions))
+            .route("/admin/suppressions", web::post().to(create_suppression))
+            .route("/admin/suppressions/import", web::post().to(import_suppressions))
+            .route("/admin/suppressions/{email}", web::get().to(get_suppression))
+            .route("/admin/suppressions/{email}", web::put().to(update_suppression))
+            .route("/admin/suppressions/{email}", web::delete().to(delete_suppression))
+            .route("/admin/newsletters/{issue_id}/stats", web::get().to(get_issue_stats))
+            .route("/admin/newsletters/{issue_id}/progress", web::get().to(get_issue_progress))
+            .route("/admin/newsletters/{issue_id}/pause", web::post().to(pause_issue))
+            .route("/admin/newsletters/{issue_id}/resume", web::post().to(resume_issue))
+            .route("/admin/newsletters/{issue_id}/cancel", web::post().to(cancel_issue))
+            .route(
+                "/admin/newsletters/{issue_id}/visibility",
+                web::put().to(update_issue_visibility),
+            )
+            .route("/t/o/{token}", web::get().to(track_open))
+            .route("/t/c/{token}", web::get().to(track_click))
+            .route("/webhooks/postmark", web::post().to(postmark_webhook))
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(hmac_secret.clone())
+            .app_data(webhook_settings.clone())
+            .app_data(admin_settings.clone())
+            .app_data(api_settings.clone())
+            .app_data(bot_protection.clone())
+            .app_data(email_policy.clone())
+            .app_data(default_locale.clone())
     })
     .listen(listener)?
     .run();
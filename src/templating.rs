@@ -0,0 +1,75 @@
+/// Substitute every `{{ key }}` placeholder of `template` with its value.
+///
+/// Substitution happens in a single pass, so a value containing a placeholder
+/// is never expanded again. Values are inserted as is, escape them beforehand
+/// if they come from user input.
+pub fn render_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{ ") {
+        rendered.push_str(&rest[..start]);
+        let after_open = &rest[start + 3..];
+        let value = after_open.find(" }}").and_then(|end| {
+            let key = &after_open[..end];
+            values
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| (*v, end))
+        });
+        match value {
+            Some((value, end)) => {
+                rendered.push_str(value);
+                rest = &after_open[end + 3..];
+            }
+            None => {
+                rendered.push_str("{{ ");
+                rest = after_open;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_html, render_template};
+
+    #[test]
+    fn html_special_characters_are_escaped() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#x27;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn placeholders_are_substituted_in_a_single_pass() {
+        let rendered = render_template(
+            "<p>{{ a }} and {{ b }}</p>",
+            &[("a", "{{ b }}"), ("b", "two")],
+        );
+        assert_eq!(rendered, "<p>{{ b }} and two</p>");
+    }
+
+    #[test]
+    fn unknown_placeholders_are_left_untouched() {
+        let rendered = render_template("{{ unknown }} {{ a }}", &[("a", "one")]);
+        assert_eq!(rendered, "{{ unknown }} one");
+    }
+}
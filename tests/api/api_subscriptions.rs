@@ -0,0 +1,145 @@
+use crate::helpers::spawn_app;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn subscribing_through_the_api_returns_the_new_subscriber() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+    assert_eq!(body["lists"], serde_json::json!(["newsletter"]));
+    let saved = sqlx::query!("SELECT id, email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(body["subscriber_id"], saved.id.to_string());
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn every_invalid_field_is_reported_as_problem_details() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({
+            "name": "",
+            "email": "definitely-not-an-email",
+            "lists": ["newsletter", "Not A Slug"]
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 400);
+    let names: Vec<&str> = problem["invalid-params"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["name", "email", "lists[1]"]);
+}
+
+#[tokio::test]
+async fn unknown_lists_are_reported_as_problem_details() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "lists": ["no-such-list"]
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["invalid-params"][0]["name"], "lists");
+}
+
+#[tokio::test]
+async fn malformed_json_is_reported_as_problem_details() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("{\"name\": \"le guin\"", "truncated JSON"),
+        ("{\"name\": \"le guin\"}", "a missing field"),
+        ("[]", "an array"),
+    ];
+
+    for (body, description) in test_cases {
+        let response = reqwest::Client::new()
+            .post(format!("{}/api/v1/subscriptions", &app.address))
+            .bearer_auth(&app.api_key)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject {}.",
+            description
+        );
+        assert_eq!(
+            response.headers()["Content-Type"],
+            "application/problem+json"
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["status"], 400);
+    }
+}
+
+#[tokio::test]
+async fn the_api_requires_a_valid_api_key() {
+    let app = spawn_app().await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com"
+    });
+    let test_cases = vec![(None, "no API key"), (Some("not-a-key"), "an unknown API key")];
+
+    for (api_key, description) in test_cases {
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/api/v1/subscriptions", &app.address))
+            .json(&body);
+        if let Some(api_key) = api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await.expect("Failed to execute request.");
+
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "The API did not reject a request with {}.",
+            description
+        );
+        assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    }
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
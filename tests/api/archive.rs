@@ -0,0 +1,207 @@
+use crate::helpers::{assert_requires_admin, create_confirmed_subscriber, spawn_app, TestApp};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+fn newsletter_request_body(title: &str, visibility: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "content": {
+            "text": "Hi {{ name }}, here is the news.",
+            "html": "<p>Hi {{ name }}, here is the news.</p>",
+        },
+        "visibility": visibility
+    })
+}
+
+// publish an issue, returning its slug
+async fn publish(app: &TestApp, body: serde_json::Value) -> String {
+    let _mock_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app.post_newsletters(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let published: serde_json::Value = response.json().await.unwrap();
+    published["slug"].as_str().unwrap().to_string()
+}
+
+async fn get_archive(app: &TestApp, path: &str) -> reqwest::Response {
+    reqwest::get(format!("{}/archive{}", &app.address, path))
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn the_archive_lists_every_issue_that_is_not_hidden() {
+    let app = spawn_app().await;
+    publish(&app, newsletter_request_body("Public issue", "public")).await;
+    publish(&app, newsletter_request_body("Members issue", "subscribers-only")).await;
+    publish(&app, newsletter_request_body("Hidden issue", "hidden")).await;
+
+    let response = get_archive(&app, "").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<a href=\"/archive/public-issue\">Public issue</a>"));
+    assert!(html.contains("<a href=\"/archive/members-issue\">Members issue</a>"));
+    assert!(html.contains("subscribers only"));
+    assert!(!html.contains("Hidden issue"));
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    let app = spawn_app().await;
+    for i in 0..21 {
+        publish(&app, newsletter_request_body(&format!("Issue {}", i), "public")).await;
+    }
+
+    let first_page = get_archive(&app, "").await.text().await.unwrap();
+    let second_page = get_archive(&app, "?page=2").await.text().await.unwrap();
+
+    assert_eq!(first_page.matches("<li>").count(), 20);
+    assert!(first_page.contains("/archive/issue-20\""));
+    assert!(first_page.contains("<a href=\"/archive?page=2\">Older issues</a>"));
+    assert!(!first_page.contains("Newer issues"));
+    assert_eq!(second_page.matches("<li>").count(), 1);
+    assert!(second_page.contains("/archive/issue-0\""));
+    assert!(second_page.contains("<a href=\"/archive?page=1\">Newer issues</a>"));
+    assert!(!second_page.contains("Older issues"));
+    assert_eq!(get_archive(&app, "?page=3").await.status().as_u16(), 404);
+    assert_eq!(get_archive(&app, "?page=0").await.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_public_issue_is_rendered_in_the_site_layout() {
+    let app = spawn_app().await;
+    let slug = publish(&app, newsletter_request_body("Public issue", "public")).await;
+
+    let response = get_archive(&app, &format!("/{}", slug)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<title>Public issue</title>"));
+    assert!(html.contains("<p>Hi reader, here is the news.</p>"));
+}
+
+#[tokio::test]
+async fn subscribers_only_issues_require_the_token_of_a_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.preferences_token().await;
+    let slug = publish(&app, newsletter_request_body("Members issue", "subscribers-only")).await;
+
+    let anonymous = get_archive(&app, &format!("/{}", slug)).await;
+    let forged = get_archive(&app, &format!("/{}?token=not-a-token", slug)).await;
+    let subscriber = get_archive(&app, &format!("/{}?token={}", slug, token)).await;
+
+    assert_eq!(anonymous.status().as_u16(), 403);
+    assert_eq!(forged.status().as_u16(), 403);
+    assert_eq!(subscriber.status().as_u16(), 200);
+    let html = subscriber.text().await.unwrap();
+    assert!(html.contains("<p>Hi le guin, here is the news.</p>"));
+}
+
+#[tokio::test]
+async fn hidden_and_unknown_issues_are_not_found() {
+    let app = spawn_app().await;
+    let slug = publish(&app, newsletter_request_body("Hidden issue", "hidden")).await;
+
+    assert_eq!(get_archive(&app, &format!("/{}", slug)).await.status().as_u16(), 404);
+    assert_eq!(get_archive(&app, "/no-such-issue").await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn slugs_are_derived_from_the_title_and_kept_unique() {
+    let app = spawn_app().await;
+
+    let first = publish(&app, newsletter_request_body("Weekly news!", "public")).await;
+    let second = publish(&app, newsletter_request_body("Weekly news?", "public")).await;
+    let mut body = newsletter_request_body("Anything", "public");
+    body["slug"] = "custom-slug".into();
+    let custom = publish(&app, body.clone()).await;
+    let duplicate = app.post_newsletters(body).await;
+
+    assert_eq!(first, "weekly-news");
+    assert_eq!(second, "weekly-news-2");
+    assert_eq!(custom, "custom-slug");
+    assert_eq!(duplicate.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn concurrent_issues_with_the_same_title_get_different_slugs() {
+    let app = spawn_app().await;
+
+    let slugs = tokio::join!(
+        publish(&app, newsletter_request_body("Weekly news", "public")),
+        publish(&app, newsletter_request_body("Weekly news", "public")),
+        publish(&app, newsletter_request_body("Weekly news", "public")),
+    );
+
+    let mut slugs = vec![slugs.0, slugs.1, slugs.2];
+    slugs.sort();
+    assert_eq!(slugs, vec!["weekly-news", "weekly-news-2", "weekly-news-3"]);
+}
+
+#[tokio::test]
+async fn invalid_slugs_and_visibilities_are_rejected() {
+    let app = spawn_app().await;
+    let mut invalid_slug = newsletter_request_body("Title", "public");
+    invalid_slug["slug"] = "Not a slug".into();
+    let test_cases = vec![
+        (invalid_slug, "invalid slug"),
+        (newsletter_request_body("Title", "private"), "unknown visibility"),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_newsletters(body).await;
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had an {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn the_visibility_of_an_issue_can_be_changed() {
+    let app = spawn_app().await;
+    let response = app
+        .post_newsletters(newsletter_request_body("Public issue", "public"))
+        .await;
+    let published: serde_json::Value = response.json().await.unwrap();
+
+    let response = app
+        .admin_client()
+        .put(format!(
+            "{}/admin/newsletters/{}/visibility",
+            &app.address,
+            published["issue_id"].as_str().unwrap()
+        ))
+        .json(&serde_json::json!({ "visibility": "hidden" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_archive(&app, "/public-issue").await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn changing_the_visibility_requires_admin_credentials() {
+    let app = spawn_app().await;
+    let response = app
+        .post_newsletters(newsletter_request_body("Public issue", "public"))
+        .await;
+    let published: serde_json::Value = response.json().await.unwrap();
+
+    assert_requires_admin(vec![reqwest::Client::new()
+        .put(format!(
+            "{}/admin/newsletters/{}/visibility",
+            &app.address,
+            published["issue_id"].as_str().unwrap()
+        ))
+        .json(&serde_json::json!({ "visibility": "hidden" }))])
+    .await;
+    assert_eq!(get_archive(&app, "/public-issue").await.status().as_u16(), 200);
+}
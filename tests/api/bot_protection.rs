@@ -0,0 +1,95 @@
+use crate::helpers::{spawn_app, spawn_app_with_challenge_verifier, TestApp};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::challenge_verifier::FakeChallengeVerifier;
+
+const FORM: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn suspicious_submissions_are_silently_dropped() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let human_token = app.form_token(Utc::now() - Duration::minutes(1));
+    let test_cases = vec![
+        (
+            format!("{}&form_token={}&website=spam.example", FORM, human_token),
+            "a filled in honeypot",
+        ),
+        (FORM.to_string(), "a missing form token"),
+        (format!("{}&form_token=1700000000.forged", FORM), "a forged form token"),
+        (
+            format!("{}&form_token={}", FORM, app.form_token(Utc::now())),
+            "a form filled in too fast",
+        ),
+        (
+            format!("{}&form_token={}", FORM, app.form_token(Utc::now() - Duration::days(2))),
+            "an expired form token",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_subscription_form(body).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            200,
+            "The submission with {} was not answered like any other.",
+            description
+        );
+        assert_eq!(
+            subscriber_count(&app).await,
+            0,
+            "The submission with {} was not dropped.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn the_home_page_serves_a_form_token() {
+    let app = spawn_app().await;
+
+    let page = reqwest::get(&app.address).await.unwrap().text().await.unwrap();
+
+    assert!(page.contains(r#"name="form_token" value=""#));
+    assert!(page.contains(r#"name="website""#));
+}
+
+#[tokio::test]
+async fn the_challenge_is_verified_when_a_verifier_is_configured() {
+    let app =
+        spawn_app_with_challenge_verifier(Some(Arc::new(FakeChallengeVerifier::new("human"))))
+            .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(FORM.to_string()).await.error_for_status().unwrap();
+    app.post_subscriptions(format!("{}&challenge_response=robot", FORM))
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(subscriber_count(&app).await, 0);
+
+    app.post_subscriptions(format!("{}&challenge_response=human", FORM))
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(subscriber_count(&app).await, 1);
+}
@@ -0,0 +1,298 @@
+use crate::helpers::{spawn_app, TestApp};
+use chrono::{Duration, Utc};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "throttle": {"max_per_hour": 2}
+    })
+}
+
+async fn create_confirmed_subscriber(app: &TestApp, email: &str) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = format!("name=le%20guin&email={}", email.replace('@', "%40"));
+    app.post_subscriptions(body).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn sent_emails(app: &TestApp) -> usize {
+    app.email_server.received_requests().await.unwrap().len()
+}
+
+async fn change_delivery(
+    app: &TestApp,
+    issue_id: &str,
+    action: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!(
+            "{}/admin/newsletters/{}/{}",
+            &app.address, issue_id, action
+        ))
+        .basic_auth(&app.admin_username, Some(&app.admin_password))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+// publish a throttled issue to `subscribers` confirmed subscribers, returning the id of the issue
+async fn publish_throttled_issue(app: &TestApp, subscribers: usize) -> String {
+    for i in 0..subscribers {
+        create_confirmed_subscriber(app, &format!("reader{}@example.com", i)).await;
+    }
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_newsletters(newsletter_request_body()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let published: serde_json::Value = response.json().await.unwrap();
+    published["issue_id"].as_str().unwrap().to_string()
+}
+
+// publish an unthrottled issue in the background,
+// returning the request and the id of the issue once it is stored
+async fn start_publishing(app: &TestApp) -> (tokio::task::JoinHandle<reqwest::Response>, String) {
+    let mut body = newsletter_request_body();
+    body.as_object_mut().unwrap().remove("throttle");
+    let publish = {
+        let address = app.address.clone();
+        tokio::spawn(async move {
+            reqwest::Client::new()
+                .post(format!("{}/newsletters", address))
+                .json(&body)
+                .send()
+                .await
+                .unwrap()
+        })
+    };
+    // the issue is stored before its first delivery
+    let issue_id = loop {
+        let issue = sqlx::query!("SELECT id FROM newsletter_issues")
+            .fetch_optional(&app.db_pool)
+            .await
+            .unwrap();
+        if let Some(issue) = issue {
+            break issue.id.to_string();
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    };
+    (publish, issue_id)
+}
+
+#[tokio::test]
+async fn a_paused_issue_holds_its_deliveries_until_it_is_resumed() {
+    let app = spawn_app().await;
+    let issue_id = publish_throttled_issue(&app, 5).await;
+    let before_dispatch = sent_emails(&app).await;
+    let now = Utc::now();
+    app.dispatch_pending_deliveries(now).await;
+    assert_eq!(sent_emails(&app).await, before_dispatch + 2);
+
+    let response = change_delivery(
+        &app,
+        &issue_id,
+        "pause",
+        serde_json::json!({"reason": "Typo in the title"}),
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let change: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(change["delivery_state"], "paused");
+    assert_eq!(change["pending"], 3);
+    app.dispatch_pending_deliveries(now + Duration::minutes(61)).await;
+    assert_eq!(sent_emails(&app).await, before_dispatch + 2);
+
+    let response = change_delivery(&app, &issue_id, "resume", serde_json::json!({}))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_pending_deliveries(now + Duration::minutes(61)).await;
+    assert_eq!(sent_emails(&app).await, before_dispatch + 4);
+    let audit_log = sqlx::query!("SELECT action, actor, reason FROM issue_audit_log ORDER BY occurred_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(audit_log.len(), 2);
+    assert_eq!(audit_log[0].action, "paused");
+    assert_eq!(audit_log[0].actor, "editor@example.com");
+    assert_eq!(audit_log[0].reason.as_deref(), Some("Typo in the title"));
+    assert_eq!(audit_log[1].action, "resumed");
+    assert_eq!(audit_log[1].reason, None);
+}
+
+#[tokio::test]
+async fn a_cancelled_issue_discards_its_queued_deliveries() {
+    let app = spawn_app().await;
+    let issue_id = publish_throttled_issue(&app, 3).await;
+    let before_cancel = sent_emails(&app).await;
+
+    let response = change_delivery(&app, &issue_id, "cancel", serde_json::json!({}))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let change: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(change["delivery_state"], "cancelled");
+    assert_eq!(change["cancelled"], 3);
+    assert_eq!(change["pending"], 0);
+    app.dispatch_pending_deliveries(Utc::now()).await;
+    assert_eq!(sent_emails(&app).await, before_cancel);
+    let progress: serde_json::Value = app
+        .admin_client()
+        .get(format!("{}/admin/newsletters/{}/progress", &app.address, issue_id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["delivery_state"], "cancelled");
+    assert_eq!(progress["cancelled"], 3);
+    assert_eq!(progress["estimated_completion"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn a_cancelled_issue_cannot_be_resumed() {
+    let app = spawn_app().await;
+    let issue_id = publish_throttled_issue(&app, 1).await;
+    change_delivery(&app, &issue_id, "cancel", serde_json::json!({}))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    for action in ["resume", "pause", "cancel"] {
+        let response = change_delivery(&app, &issue_id, action, serde_json::json!({})).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            409,
+            "A cancelled issue did not refuse to {}.",
+            action
+        );
+    }
+}
+
+#[tokio::test]
+async fn an_issue_being_published_stops_when_paused() {
+    let app = spawn_app().await;
+    for i in 0..3 {
+        create_confirmed_subscriber(&app, &format!("reader{}@example.com", i)).await;
+    }
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(500)))
+        .mount(&app.email_server)
+        .await;
+    let before_publish = sent_emails(&app).await;
+    let (publish, issue_id) = start_publishing(&app).await;
+
+    change_delivery(&app, &issue_id, "pause", serde_json::json!({}))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let published: serde_json::Value = publish.await.unwrap().json().await.unwrap();
+    let sent = published["sent"].as_u64().unwrap();
+    assert!(sent <= 1);
+    assert_eq!(published["pending"].as_u64().unwrap(), 3 - sent);
+    change_delivery(&app, &issue_id, "resume", serde_json::json!({}))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_pending_deliveries(Utc::now()).await;
+    assert_eq!(sent_emails(&app).await, before_publish + 3);
+}
+
+#[tokio::test]
+async fn an_issue_being_published_leaves_nothing_pending_when_cancelled() {
+    let app = spawn_app().await;
+    for i in 0..3 {
+        create_confirmed_subscriber(&app, &format!("reader{}@example.com", i)).await;
+    }
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(500)))
+        .mount(&app.email_server)
+        .await;
+    let (publish, issue_id) = start_publishing(&app).await;
+
+    change_delivery(&app, &issue_id, "cancel", serde_json::json!({}))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let published: serde_json::Value = publish.await.unwrap().json().await.unwrap();
+    let sent = published["sent"].as_u64().unwrap();
+    assert!(sent <= 1);
+    assert_eq!(published["cancelled"].as_u64().unwrap(), 3 - sent);
+    let pending = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM newsletter_deliveries WHERE status = 'pending'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(pending.count, 0);
+}
+
+#[tokio::test]
+async fn changing_the_delivery_state_requires_admin_credentials() {
+    let app = spawn_app().await;
+    let issue_id = publish_throttled_issue(&app, 1).await;
+    let url = format!("{}/admin/newsletters/{}/pause", &app.address, issue_id);
+
+    let anonymous = reqwest::Client::new()
+        .post(&url)
+        .json(&serde_json::json!({}))
+        .send()
+        .await
+        .unwrap();
+    let wrong_password = reqwest::Client::new()
+        .post(&url)
+        .basic_auth(&app.admin_username, Some("not-the-password"))
+        .json(&serde_json::json!({}))
+        .send()
+        .await
+        .unwrap();
+
+    for response in [anonymous, wrong_password] {
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(response.headers()["WWW-Authenticate"], r#"Basic realm="admin""#);
+    }
+    let state = sqlx::query!("SELECT delivery_state FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(state.delivery_state, "active");
+}
+
+#[tokio::test]
+async fn changing_the_delivery_state_of_an_unknown_issue_is_a_404() {
+    let app = spawn_app().await;
+
+    let response = change_delivery(
+        &app,
+        &uuid::Uuid::new_v4().to_string(),
+        "pause",
+        serde_json::json!({}),
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
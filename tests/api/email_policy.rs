@@ -0,0 +1,178 @@
+use crate::helpers::{assert_requires_admin, spawn_app, TestApp};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+async fn put_domain_rule(app: &TestApp, domain: &str, rule: &str) -> reqwest::Response {
+    app.admin_client()
+        .put(format!("{}/admin/email-domains/{}", &app.address, domain))
+        .json(&serde_json::json!({ "rule": rule }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn subscribe(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post_api_subscriptions(&serde_json::json!({"name": "le guin", "email": email}))
+        .await
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn disposable_addresses_are_rejected_with_a_specific_message() {
+    let app = spawn_app().await;
+
+    let response = subscribe(&app, "ursula@mailinator.com").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["invalid-params"][0]["name"], "email");
+    assert_eq!(
+        problem["invalid-params"][0]["reason"],
+        "Disposable email addresses are not accepted, please use a permanent address."
+    );
+}
+
+#[tokio::test]
+async fn role_accounts_are_rejected() {
+    let app = spawn_app().await;
+
+    for email in ["postmaster%40example.com", "noreply%40example.com", "abuse%40example.com"] {
+        let response = app
+            .post_subscriptions(format!("name=le%20guin&email={}", email))
+            .await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The form accepted the role account {}.",
+            email
+        );
+    }
+}
+
+#[tokio::test]
+async fn allowed_domains_are_accepted_even_if_disposable() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+
+    put_domain_rule(&app, "mailinator.com", "allow")
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = subscribe(&app, "ursula@mailinator.com").await;
+
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+#[tokio::test]
+async fn denied_domains_and_their_subdomains_are_rejected() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+
+    put_domain_rule(&app, "Example.com", "deny")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let response = subscribe(&app, "ursula@mail.example.com").await;
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        problem["invalid-params"][0]["reason"],
+        "Email addresses at mail.example.com are not accepted."
+    );
+    // a more specific rule wins
+    put_domain_rule(&app, "mail.example.com", "allow")
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = subscribe(&app, "ursula@mail.example.com").await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+#[tokio::test]
+async fn domain_rules_can_be_listed_replaced_and_deleted() {
+    let app = spawn_app().await;
+    put_domain_rule(&app, "spam.example", "deny")
+        .await
+        .error_for_status()
+        .unwrap();
+    put_domain_rule(&app, "spam.example", "allow")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let rules: serde_json::Value = app
+        .admin_client()
+        .get(format!("{}/admin/email-domains", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(rules.as_array().unwrap().len(), 1);
+    assert_eq!(rules[0]["domain"], "spam.example");
+    assert_eq!(rules[0]["rule"], "allow");
+
+    let url = format!("{}/admin/email-domains/spam.example", &app.address);
+    let deleted = app.admin_client().delete(&url).send().await.unwrap();
+    assert_eq!(deleted.status().as_u16(), 204);
+    let deleted_again = app.admin_client().delete(&url).send().await.unwrap();
+    assert_eq!(deleted_again.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn domain_rules_require_admin_credentials() {
+    let app = spawn_app().await;
+    put_domain_rule(&app, "spam.example", "deny")
+        .await
+        .error_for_status()
+        .unwrap();
+    let client = reqwest::Client::new();
+    let url = format!("{}/admin/email-domains", &app.address);
+
+    assert_requires_admin(vec![
+        client.get(&url),
+        client
+            .put(format!("{}/spam.example", &url))
+            .json(&serde_json::json!({ "rule": "allow" })),
+        client.delete(format!("{}/spam.example", &url)),
+    ])
+    .await;
+    let rules: serde_json::Value = app
+        .admin_client()
+        .get(&url)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(rules[0]["rule"], "deny");
+}
+
+#[tokio::test]
+async fn invalid_domain_rules_are_rejected() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("spam.example", "block", "an unknown rule"),
+        ("spam_box.example", "deny", "an invalid domain"),
+    ];
+    for (domain, rule, description) in test_cases {
+        let response = put_domain_rule(&app, domain, rule).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject {}.",
+            description
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use crate::helpers::{spawn_app, TestApp};
+
+fn newsletter_request_body(title: &str, visibility: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "content": {
+            "text": "Tom & Jerry are back.",
+            "html": "<p>Tom &amp; Jerry are <b>back</b>.</p>",
+        },
+        "visibility": visibility
+    })
+}
+
+async fn publish(app: &TestApp, body: serde_json::Value) {
+    let response = app.post_newsletters(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn get_feed(app: &TestApp, path: &str, headers: &[(&str, &str)]) -> reqwest::Response {
+    let mut request = reqwest::Client::new().get(format!("{}{}", &app.address, path));
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn the_rss_feed_contains_public_issues_with_escaped_content() {
+    let app = spawn_app().await;
+    publish(&app, newsletter_request_body("Public issue", "public")).await;
+    publish(&app, newsletter_request_body("Members issue", "subscribers-only")).await;
+
+    let response = get_feed(&app, "/feed.rss", &[]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/rss+xml; charset=utf-8"
+    );
+    let xml = response.text().await.unwrap();
+    assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?><rss version=\"2.0\""));
+    assert_eq!(xml.matches("<item>").count(), 1);
+    assert!(xml.contains("<title>Public issue</title>"));
+    assert!(xml.contains("<link>http://127.0.0.1/archive/public-issue</link>"));
+    assert!(xml.contains("<guid isPermaLink=\"false\">urn:uuid:"));
+    assert!(xml.contains("<pubDate>"));
+    assert!(xml.contains(
+        "<description>&lt;p&gt;Tom &amp;amp; Jerry are &lt;b&gt;back&lt;/b&gt;.&lt;/p&gt;</description>"
+    ));
+    assert!(!xml.contains("Members issue"));
+}
+
+#[tokio::test]
+async fn the_atom_feed_contains_public_issues_with_escaped_content() {
+    let app = spawn_app().await;
+    publish(&app, newsletter_request_body("Public issue", "public")).await;
+    publish(&app, newsletter_request_body("Hidden issue", "hidden")).await;
+
+    let response = get_feed(&app, "/feed.atom", &[]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/atom+xml; charset=utf-8"
+    );
+    let xml = response.text().await.unwrap();
+    assert_eq!(xml.matches("<entry>").count(), 1);
+    assert!(xml.contains("<id>urn:uuid:"));
+    assert!(xml.contains("<link rel=\"alternate\" href=\"http://127.0.0.1/archive/public-issue\"/>"));
+    assert!(xml.contains("<content type=\"html\">&lt;p&gt;Tom &amp;amp; Jerry"));
+    assert!(!xml.contains("Hidden issue"));
+}
+
+#[tokio::test]
+async fn an_unchanged_feed_is_not_sent_again() {
+    let app = spawn_app().await;
+    publish(&app, newsletter_request_body("Public issue", "public")).await;
+    let response = get_feed(&app, "/feed.rss", &[]).await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_string();
+    let last_modified = response.headers()["Last-Modified"].to_str().unwrap().to_string();
+
+    let by_etag = get_feed(&app, "/feed.rss", &[("If-None-Match", &etag)]).await;
+    let by_date = get_feed(&app, "/feed.atom", &[("If-Modified-Since", &last_modified)]).await;
+
+    assert_eq!(by_etag.status().as_u16(), 304);
+    assert_eq!(by_etag.headers()["ETag"], etag.as_str());
+    assert!(by_etag.text().await.unwrap().is_empty());
+    assert_eq!(by_date.status().as_u16(), 304);
+}
+
+#[tokio::test]
+async fn publishing_an_issue_invalidates_the_cached_feed() {
+    let app = spawn_app().await;
+    publish(&app, newsletter_request_body("First issue", "public")).await;
+    let response = get_feed(&app, "/feed.rss", &[]).await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_string();
+
+    publish(&app, newsletter_request_body("Second issue", "public")).await;
+    let response = get_feed(&app, "/feed.rss", &[("If-None-Match", &etag)]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_ne!(response.headers()["ETag"], etag.as_str());
+    assert!(response.text().await.unwrap().contains("Second issue"));
+}
+
+#[tokio::test]
+async fn an_empty_feed_is_valid() {
+    let app = spawn_app().await;
+
+    let response = get_feed(&app, "/feed.atom", &[]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get("Last-Modified").is_none());
+    let xml = response.text().await.unwrap();
+    assert!(xml.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\">"));
+    assert!(!xml.contains("<entry>"));
+}
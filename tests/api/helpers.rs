@@ -1,10 +1,19 @@
 use sqlx::{ PgPool, PgConnection, Executor, Connection };
 use uuid::Uuid;
-use zero2prod::configuration::{ get_configuration, DatabaseSettings };
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
+use secrecy::{ExposeSecret, Secret};
+use zero2prod::challenge_verifier::ChallengeVerifier;
+use zero2prod::configuration::{ get_configuration, DatabaseSettings, Settings };
+use zero2prod::domain::Locale;
+use zero2prod::email_client::EmailClient;
+use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::routes::sign_form_token;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 use once_cell::sync::Lazy;
 use zero2prod::startup::{get_connection_pool, Application};
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
@@ -32,12 +41,53 @@ pub struct TestApp {
     pub db_pool: PgPool,
     pub email_server: MockServer,
     pub port: u16,
+    pub webhook_username: String,
+    pub webhook_password: String,
+    pub admin_username: String,
+    pub admin_password: String,
+    pub api_key: String,
+    pub email_client: EmailClient,
+    pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    pub default_locale: Locale,
 }
 
 impl TestApp {
+    // submitted with the token of a form served a minute ago, as a human would
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
+        let form_token = self.form_token(Utc::now() - Duration::minutes(1));
+        let body = format!("{}&form_token={}", body, form_token);
+        self.post_subscription_form(body).await
+    }
+
+    // submitted as is, bot protection fields included
+    pub async fn post_subscription_form(&self, body: String) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub fn form_token(&self, issued_at: DateTime<Utc>) -> String {
+        sign_form_token(&self.hmac_secret, issued_at)
+    }
+
+    pub async fn post_api_subscriptions(&self, body: &serde_json::Value) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/api/v1/subscriptions", &self.address))
+            .bearer_auth(&self.api_key)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_resend_confirmation(&self, body: String) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions/resend-confirmation", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -45,18 +95,146 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    /// Wait for the emails sent after the response went out, e.g. resent confirmations.
+    pub async fn wait_for_emails(&self, count: usize) -> Vec<wiremock::Request> {
+        for _ in 0..100 {
+            let requests = self.email_server.received_requests().await.unwrap();
+            if requests.len() >= count {
+                return requests;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("Gave up waiting for {} emails.", count);
+    }
+
+    pub async fn get_preferences(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/preferences", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_preferences(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/preferences", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unsubscribe(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/preferences/unsubscribe", &self.address))
+            .form(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Run the background worker as if it was `now`, until it runs out of work.
+    pub async fn dispatch_pending_deliveries(&self, now: DateTime<Utc>) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.hmac_secret,
+                self.default_locale,
+                now,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn preferences_token(&self) -> String {
+        sqlx::query!("SELECT preferences_token FROM subscriptions")
+            .fetch_one(&self.db_pool)
+            .await
+            .expect("Failed to fetch the preferences token.")
+            .preferences_token
+    }
+
+    pub async fn post_lists(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/lists", &self.address))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // a client sending the admin credentials with every request
+    pub fn admin_client(&self) -> reqwest::Client {
+        let credentials = base64::encode(format!("{}:{}", self.admin_username, self.admin_password));
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            format!("Basic {}", credentials).parse().unwrap(),
+        );
+        reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap()
+    }
+
+    pub async fn post_suppressions(&self, body: serde_json::Value) -> reqwest::Response {
+        self.admin_client()
+            .post(format!("{}/admin/suppressions", &self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn import_suppressions(&self, csv: &str) -> reqwest::Response {
+        self.admin_client()
+            .post(format!("{}/admin/suppressions/import", &self.address))
+            .header("Content-Type", "text/csv")
+            .body(csv.to_string())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/newsletters", &self.address))
+            .post(format!("{}/newsletters", &self.address))
             .json(&body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_postmark_webhook(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .basic_auth(&self.webhook_username, Some(&self.webhook_password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub fn get_confirmation_links(
         &self,
         email_request: &wiremock::Request
+    ) -> ConfirmationLinks {
+        self.get_links(email_request, "/subscriptions/confirm")
+    }
+
+    // extract the links pointing to `link_path` from both bodies of an email
+    pub fn get_links(
+        &self,
+        email_request: &wiremock::Request,
+        link_path: &str,
     ) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(
             &email_request.body
@@ -66,6 +244,7 @@ impl TestApp {
             let links: Vec<_> = linkify::LinkFinder::new()
                 .links(s)
                 .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .filter(|l| reqwest::Url::parse(l.as_str()).unwrap().path() == link_path)
                 .collect();
             assert_eq!(links.len(), 1);
             let raw_link = links[0].as_str().to_owned();
@@ -75,8 +254,8 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { 
             html, 
             plain_text 
@@ -86,6 +265,21 @@ impl TestApp {
 
 // this function handles the logic of spawn a server to the background
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with_challenge_verifier(None).await
+}
+
+pub async fn spawn_app_with_challenge_verifier(
+    challenge_verifier: Option<Arc<dyn ChallengeVerifier>>,
+) -> TestApp {
+    spawn_app_with(|_| {}, challenge_verifier).await
+}
+
+// rate limits are lifted unless `configure` sets some,
+// tests create many subscribers from the same address
+pub async fn spawn_app_with(
+    configure: impl FnOnce(&mut Settings),
+    challenge_verifier: Option<Arc<dyn ChallengeVerifier>>,
+) -> TestApp {
     Lazy::force(&TRACING);
     let email_server = MockServer::start().await;
     
@@ -94,21 +288,32 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
+        c.rate_limits.rules.clear();
+        configure(&mut c);
         c
     };
 
     configure_database(&configuration.database).await;
-    let application = Application::build(configuration.clone())
+    let application = Application::build_with_challenge_verifier(configuration.clone(), challenge_verifier)
         .await
         .expect("Failed to build application.");
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    drop(tokio::spawn(application.run_until_stopped()));
 
     TestApp {
         address: format!("http://localhost:{}", application_port),
         port: application_port,
         db_pool: get_connection_pool(&configuration.database),
         email_server,
+        webhook_username: configuration.webhooks.username.clone(),
+        webhook_password: configuration.webhooks.password.expose_secret().clone(),
+        admin_username: configuration.admin.users[0].username.clone(),
+        admin_password: configuration.admin.users[0].password.expose_secret().clone(),
+        api_key: configuration.api.keys[0].expose_secret().clone(),
+        email_client: configuration.email_client.client(),
+        default_locale: configuration.localization.default_locale().unwrap(),
+        base_url: configuration.application.base_url,
+        hmac_secret: configuration.application.hmac_secret,
     }
 }
 
@@ -133,4 +338,47 @@ pub async fn configure_database(config: &DatabaseSettings) -> PgPool {
         .expect("Failed to migrate the database");
     
     connection_pool
-}
\ No newline at end of file
+}
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+// every request is sent without credentials and must be refused
+pub async fn assert_requires_admin(requests: Vec<reqwest::RequestBuilder>) {
+    for request in requests {
+        let response = request.send().await.expect("Failed to execute request.");
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(response.headers()["WWW-Authenticate"], r#"Basic realm="admin""#);
+    }
+}
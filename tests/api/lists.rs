@@ -0,0 +1,306 @@
+use crate::helpers::{assert_requires_admin, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn admins_can_create_and_list_mailing_lists() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_lists(serde_json::json!({"slug": "weekly", "name": "Weekly digest"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let lists: serde_json::Value = reqwest::Client::new()
+        .get(format!("{}/admin/lists", app.address))
+        .basic_auth(&app.admin_username, Some(&app.admin_password))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let slugs: Vec<&str> = lists
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| l["slug"].as_str().unwrap())
+        .collect();
+    assert_eq!(slugs, vec!["newsletter", "weekly"]);
+}
+
+#[tokio::test]
+async fn mailing_lists_require_admin_credentials() {
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/admin/lists", app.address);
+
+    assert_requires_admin(vec![
+        client.get(&url),
+        client
+            .post(&url)
+            .json(&serde_json::json!({"slug": "weekly", "name": "Weekly digest"})),
+    ])
+    .await;
+}
+
+#[tokio::test]
+async fn creating_a_list_with_an_existing_slug_returns_a_409() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_lists(serde_json::json!({"slug": "newsletter", "name": "Again"}))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn creating_a_list_with_invalid_data_returns_a_400() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (serde_json::json!({"slug": "Not a slug", "name": "Weekly"}), "invalid slug"),
+        (serde_json::json!({"slug": "weekly", "name": "  "}), "empty name"),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_lists(body).await;
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload had an {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_returns_a_400() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&lists=nope".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn memberships_are_confirmed_along_with_the_subscriber() {
+    let app = spawn_app().await;
+    create_weekly_list(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&lists=weekly".into(),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    assert_eq!(memberships(&app).await, vec![("weekly".into(), "pending_confirmation".into())]);
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(memberships(&app).await, vec![("weekly".into(), "confirmed".into())]);
+}
+
+#[tokio::test]
+async fn subscribing_again_joins_the_lists_that_are_missing() {
+    let app = spawn_app().await;
+    create_weekly_list(&app).await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "newsletter").await;
+
+    let response = app
+        .post_subscriptions(
+            "name=le%20guin&email=Ursula_Le_Guin%40gmail.com&lists=newsletter,weekly".into(),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        memberships(&app).await,
+        vec![
+            ("newsletter".into(), "confirmed".into()),
+            ("weekly".into(), "confirmed".into())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn pending_subscribers_joining_more_lists_confirm_them_with_their_address() {
+    let app = spawn_app().await;
+    create_weekly_list(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    for lists in ["newsletter", "weekly"] {
+        app.post_subscriptions(format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&lists={}",
+            lists
+        ))
+        .await
+        .error_for_status()
+        .unwrap();
+    }
+    assert_eq!(
+        memberships(&app).await,
+        vec![
+            ("newsletter".into(), "pending_confirmation".into()),
+            ("weekly".into(), "pending_confirmation".into())
+        ]
+    );
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        memberships(&app).await,
+        vec![
+            ("newsletter".into(), "confirmed".into()),
+            ("weekly".into(), "confirmed".into())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn newsletters_are_only_delivered_to_members_of_the_target_list() {
+    let app = spawn_app().await;
+    create_weekly_list(&app).await;
+    create_confirmed_subscriber(&app, "a%40example.com", "weekly").await;
+    create_confirmed_subscriber(&app, "b%40example.com", "newsletter").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "list": "weekly",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "a@example.com");
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_list_returns_a_400() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "list": "nope",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribers_can_switch_lists_from_the_preference_center() {
+    let app = spawn_app().await;
+    create_weekly_list(&app).await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "newsletter").await;
+    let token = app.preferences_token().await;
+
+    let page = app.get_preferences(&token).await.text().await.unwrap();
+    assert!(page.contains(r#"name="list_newsletter" value="on" checked"#));
+    assert!(page.contains(r#"name="list_weekly" value="on" >"#));
+
+    app.post_preferences(&serde_json::json!({
+        "token": token,
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "content_format": "html",
+        "update_lists": "true",
+        "list_weekly": "on",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    assert_eq!(
+        memberships(&app).await,
+        vec![
+            ("newsletter".into(), "unsubscribed".into()),
+            ("weekly".into(), "confirmed".into()),
+        ]
+    );
+}
+
+async fn create_weekly_list(app: &TestApp) {
+    app.post_lists(serde_json::json!({"slug": "weekly", "name": "Weekly digest"}))
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+async fn create_confirmed_subscriber(app: &TestApp, email: &str, lists: &str) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(format!("name=le%20guin&email={}&lists={}", email, lists))
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn memberships(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.slug, r.status))
+    .collect()
+}
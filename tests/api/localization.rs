@@ -0,0 +1,255 @@
+use chrono::{Duration, Utc};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+const BODY: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+async fn subscribe(app: &TestApp, body: &str, accept_language: Option<&str>) -> reqwest::Response {
+    let form_token = app.form_token(Utc::now() - Duration::minutes(1));
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(format!("{}&form_token={}", body, form_token));
+    if let Some(accept_language) = accept_language {
+        request = request.header("Accept-Language", accept_language);
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn stored_locale(app: &TestApp) -> Option<String> {
+    sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .locale
+}
+
+async fn last_email(app: &TestApp) -> (wiremock::Request, serde_json::Value) {
+    let request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body = serde_json::from_slice(&request.body).unwrap();
+    (request, body)
+}
+
+#[tokio::test]
+async fn the_locale_picked_in_the_form_is_stored_and_used_for_the_confirmation_email() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+
+    subscribe(&app, &format!("{}&locale=fr", BODY), Some("en"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(stored_locale(&app).await.as_deref(), Some("fr"));
+    let (request, email) = last_email(&app).await;
+    assert_eq!(email["Subject"], "Bienvenue");
+    assert!(email["TextBody"].as_str().unwrap().contains("pour confirmer votre abonnement"));
+    assert!(email["HtmlBody"].as_str().unwrap().contains("Gérer votre abonnement"));
+    // the links survive the translation
+    app.get_confirmation_links(&request);
+}
+
+#[tokio::test]
+async fn the_browser_language_is_used_when_the_form_doesnt_pick_one() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+
+    subscribe(&app, &format!("{}&locale=", BODY), Some("de-CH, fr;q=0.8, en;q=0.5"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(stored_locale(&app).await.as_deref(), Some("fr"));
+}
+
+#[tokio::test]
+async fn the_default_locale_of_the_settings_is_used_when_nothing_else_matches() {
+    let app = spawn_app_with(|c| c.localization.default_locale = "fr".into(), None).await;
+    mount_email_server(&app).await;
+
+    subscribe(&app, BODY, Some("de")).await.error_for_status().unwrap();
+
+    assert_eq!(stored_locale(&app).await.as_deref(), Some("fr"));
+    let (_, email) = last_email(&app).await;
+    assert_eq!(email["Subject"], "Bienvenue");
+}
+
+#[tokio::test]
+async fn english_is_the_default_locale() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+
+    subscribe(&app, BODY, None).await.error_for_status().unwrap();
+
+    assert_eq!(stored_locale(&app).await.as_deref(), Some("en"));
+    let (_, email) = last_email(&app).await;
+    assert_eq!(email["Subject"], "Welcome");
+}
+
+#[tokio::test]
+async fn the_api_rejects_unsupported_locales() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "locale": "tlh"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["invalid-params"][0]["name"], "locale");
+}
+
+#[tokio::test]
+async fn the_api_stores_the_requested_locale() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "locale": "fr-CA"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+    assert_eq!(stored_locale(&app).await.as_deref(), Some("fr"));
+}
+
+#[tokio::test]
+async fn the_confirmation_page_is_shown_in_the_locale_of_the_subscriber() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    subscribe(&app, &format!("{}&locale=fr", BODY), None)
+        .await
+        .error_for_status()
+        .unwrap();
+    let (request, _) = last_email(&app).await;
+    let confirmation_links = app.get_confirmation_links(&request);
+
+    // the subscriber's choice wins over their browser
+    let page = reqwest::Client::new()
+        .get(confirmation_links.html)
+        .header("Accept-Language", "en")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(page.contains("<html lang=\"fr\">"));
+    assert!(page.contains("Abonnement confirmé"));
+}
+
+#[tokio::test]
+async fn error_pages_follow_the_browser_language() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/subscriptions/confirm", app.address))
+        .header("Accept-Language", "fr")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    let page = response.text().await.unwrap();
+    assert!(page.contains("<html lang=\"fr\">"));
+    assert!(page.contains("Lien de confirmation incomplet"));
+}
+
+#[tokio::test]
+async fn unsubscribe_pages_are_shown_in_the_locale_of_the_subscriber() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    subscribe(&app, &format!("{}&locale=fr", BODY), None)
+        .await
+        .error_for_status()
+        .unwrap();
+    let token = app.preferences_token().await;
+
+    let form = reqwest::get(format!(
+        "{}/preferences/unsubscribe?token={}",
+        app.address, token
+    ))
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+    assert!(form.contains("Voulez-vous vraiment ne plus recevoir notre newsletter ?"));
+
+    let page = app.post_unsubscribe(&token).await.text().await.unwrap();
+    assert!(page.contains("<html lang=\"fr\">"));
+    assert!(page.contains("Vous êtes désabonné"));
+}
+
+#[tokio::test]
+async fn newsletter_footers_are_in_the_locale_of_each_subscriber() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    subscribe(&app, &format!("{}&locale=fr", BODY), None)
+        .await
+        .error_for_status()
+        .unwrap();
+    let (request, _) = last_email(&app).await;
+    reqwest::get(app.get_confirmation_links(&request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let (_, email) = last_email(&app).await;
+    assert!(email["TextBody"].as_str().unwrap().contains("Gérer votre abonnement : http"));
+    assert!(email["HtmlBody"].as_str().unwrap().contains(">Gérer votre abonnement</a>"));
+}
+
+#[tokio::test]
+async fn subscribers_without_a_stored_locale_get_the_default_one() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    subscribe(&app, &format!("{}&locale=fr", BODY), None)
+        .await
+        .error_for_status()
+        .unwrap();
+    // as if they joined before locales were captured
+    sqlx::query!("UPDATE subscriptions SET locale = NULL")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    app.post_resend_confirmation("email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    app.wait_for_emails(2).await;
+    let (_, email) = last_email(&app).await;
+    assert_eq!(email["Subject"], "Welcome");
+}
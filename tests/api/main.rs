@@ -1,5 +1,27 @@
+// the upstream tests predate these lints, keep their borrows as written
+#![allow(clippy::needless_borrow, clippy::needless_borrows_for_generic_args)]
+
 mod helpers;
 mod health_check;
 mod subscriptions;
 mod subscriptions_confirm;
-mod newsletter;
\ No newline at end of file
+mod subscriptions_resend_confirmation;
+mod newsletter;
+mod preferences;
+mod lists;
+mod segments;
+mod webhooks;
+mod suppressions;
+mod tracking;
+mod newsletter_stats;
+mod archive;
+mod feeds;
+mod subject_tests;
+mod throttling;
+mod delivery_control;
+mod api_subscriptions;
+mod problem_details;
+mod bot_protection;
+mod rate_limiting;
+mod email_policy;
+mod localization;
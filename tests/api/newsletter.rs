@@ -1,4 +1,4 @@
-use crate::helpers::{spawn_app, TestApp, ConfirmationLinks};
+use crate::helpers::{spawn_app, create_confirmed_subscriber, create_unconfirmed_subscriber};
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -50,6 +50,67 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     assert_eq!(response.status().as_u16(), 200);
 }
 
+#[tokio::test]
+async fn publishing_responds_with_a_summary_of_the_deliveries() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let summary: serde_json::Value = response.json().await.unwrap();
+    let issue_id = summary["issue_id"].as_str().unwrap();
+    assert!(uuid::Uuid::parse_str(issue_id).is_ok());
+    assert_eq!(summary["slug"], "newsletter-title");
+    assert_eq!(summary["sent"], 1);
+    assert_eq!(summary["failed"], 0);
+    assert_eq!(summary["pending"], 0);
+    assert_eq!(summary["cancelled"], 0);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_reported_in_the_summary_without_failing_the_request() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let summary: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(summary["sent"], 0);
+    assert_eq!(summary["failed"], 1);
+}
+
 #[tokio::test]
 async fn newsletters_returns_400_for_invalid_data() {
     let app = spawn_app().await;
@@ -67,6 +128,47 @@ async fn newsletters_returns_400_for_invalid_data() {
             serde_json::json!({"title": "Newsletter!"}),
             "missing content",
         ),
+        (
+            serde_json::json!({
+                "title": "Newsletter!",
+                "content": {"html": "<p>Newsletter body as HTML</p>"}
+            }),
+            "html without text or markdown",
+        ),
+        (
+            serde_json::json!({
+                "title": "  ",
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+            "empty title",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter!",
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<script>alert(1)</script>",
+                }
+            }),
+            "html body empty once sanitized",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter!",
+                "content": {"markdown": "Read [more](/archive)"}
+            }),
+            "relative link",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter!",
+                "content": {"markdown": "a".repeat(256 * 1024 + 1)}
+            }),
+            "body too large",
+        ),
     ];
 
     for (invalid_body, error_message) in test_cases {
@@ -81,37 +183,149 @@ async fn newsletters_returns_400_for_invalid_data() {
     }
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+#[tokio::test]
+async fn merge_fields_are_substituted_for_each_recipient() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.preferences_token().await;
 
-    let _mock_guard = Mock::given(path("/email"))
+    Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
         .expect(1)
-        .mount_as_scoped(&app.email_server)
+        .mount(&app.email_server)
         .await;
-    
-    app.post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
-
-    let email_request = &app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-    app.get_confirmation_links(&email_request)
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "News for {{ name }}",
+        "content": {
+            "text": "Hi {{ name }}! Unsubscribe: {{ unsubscribe_url }}",
+            "html": "<p>Hi {{name}}!</p><a href=\"{{ preferences_url }}\">Preferences</a>",
+        }
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "News for le guin");
+    assert!(body["TextBody"].as_str().unwrap().starts_with(&format!(
+        "Hi le guin! Unsubscribe: http://127.0.0.1/preferences/unsubscribe?token={}",
+        token
+    )));
+    assert!(body["HtmlBody"].as_str().unwrap().starts_with(&format!(
+        "<p>Hi le guin!</p><a href=\"http://127.0.0.1/preferences?token={}\" rel=\"noopener noreferrer\">Preferences</a>",
+        token
+    )));
+}
+
+#[tokio::test]
+async fn unknown_merge_fields_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Hi {{ first_name }}",
+            "html": "<p>Hi {{ name }}</p>",
+        }
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
-    reqwest::get(confirmation_link.html)
-        .await
+#[tokio::test]
+async fn markdown_content_is_rendered_to_html_and_plain_text() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "markdown": "Hi **{{ name }}**, [see you]({{preferences_url}})!",
+        }
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = body["HtmlBody"].as_str().unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<title>Newsletter title</title>"));
+    assert!(html.contains("<p>Hi <strong>le guin</strong>, <a href=\"http://127.0.0.1/preferences?token="));
+    assert!(body["TextBody"]
+        .as_str()
         .unwrap()
-        .error_for_status()
-        .unwrap();
-}
\ No newline at end of file
+        .starts_with("Hi le guin, see you (http://127.0.0.1/preferences?token="));
+}
+
+#[tokio::test]
+async fn explicit_text_overrides_the_markdown_rendering() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "markdown": "Hi **{{ name }}**!",
+            "text": "A hand-written alternative",
+        }
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"].as_str().unwrap().contains("<p>Hi <strong>le guin</strong>!</p>"));
+    assert!(body["TextBody"].as_str().unwrap().starts_with("A hand-written alternative"));
+}
+
+#[tokio::test]
+async fn scripts_and_event_handlers_are_stripped_from_the_html_body() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p onmouseover=\"alert(1)\">Hello</p><script>alert(1)</script>",
+        }
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"].as_str().unwrap().starts_with("<p>Hello</p><hr />"));
+}
@@ -0,0 +1,124 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+// publish an issue while the email server answers with `status`, returning the id of the issue
+async fn publish(app: &TestApp, status: u16) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(status))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let published: serde_json::Value = response.json().await.unwrap();
+    published["issue_id"].as_str().unwrap().to_string()
+}
+
+async fn get_stats(app: &TestApp, issue_id: &str) -> serde_json::Value {
+    let response = app
+        .admin_client()
+        .get(format!("{}/admin/newsletters/{}/stats", &app.address, issue_id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_counted_without_aborting_the_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let issue_id = publish(&app, 500).await;
+
+    let stats = get_stats(&app, &issue_id).await;
+    assert_eq!(stats["sent"], 0);
+    assert_eq!(stats["failed"], 1);
+}
+
+#[tokio::test]
+async fn hard_bounces_reported_for_an_issue_are_counted() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let issue_id = publish(&app, 200).await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "HardBounce",
+            "Email": "ursula_le_guin@gmail.com",
+            "BouncedAt": "2023-02-26T12:00:00Z",
+            "Metadata": { "issue_id": issue_id }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let stats = get_stats(&app, &issue_id).await;
+    assert_eq!(stats["sent"], 1);
+    assert_eq!(stats["bounced"], 1);
+}
+
+#[tokio::test]
+async fn soft_bounces_are_not_counted_as_bounced() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let issue_id = publish(&app, 200).await;
+
+    app.post_postmark_webhook(&serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "SoftBounce",
+        "Email": "ursula_le_guin@gmail.com",
+        "BouncedAt": "2023-02-26T12:00:00Z",
+        "Metadata": { "issue_id": issue_id }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let stats = get_stats(&app, &issue_id).await;
+    assert_eq!(stats["bounced"], 0);
+}
+
+#[tokio::test]
+async fn unsubscribes_are_attributed_to_the_last_issue_received() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.preferences_token().await;
+    let first_issue_id = publish(&app, 200).await;
+    let second_issue_id = publish(&app, 200).await;
+
+    app.post_unsubscribe(&token).await.error_for_status().unwrap();
+
+    assert_eq!(get_stats(&app, &first_issue_id).await["unsubscribes"], 0);
+    assert_eq!(get_stats(&app, &second_issue_id).await["unsubscribes"], 1);
+}
+
+#[tokio::test]
+async fn list_growth_is_reported_per_day_around_the_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let issue_id = publish(&app, 200).await;
+
+    let stats = get_stats(&app, &issue_id).await;
+
+    let growth = stats["growth"].as_array().unwrap();
+    // the 30 days before the issue and the day it was published
+    assert_eq!(growth.len(), 31);
+    assert!(growth[..30].iter().all(|d| d["new_subscribers"] == 0));
+    assert_eq!(growth[30]["new_subscribers"], 1);
+    assert_eq!(
+        growth[30]["day"],
+        chrono::Utc::now().date_naive().to_string()
+    );
+}
@@ -0,0 +1,269 @@
+use crate::helpers::{spawn_app, create_confirmed_subscriber};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn the_preference_center_rejects_unknown_tokens() {
+    let app = spawn_app().await;
+
+    let response = app.get_preferences("not-a-real-token").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_preference_center_shows_the_current_preferences() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.preferences_token().await;
+
+    let response = app.get_preferences(&token).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"value="le guin""#));
+    assert!(page.contains(r#"value="ursula_le_guin@gmail.com""#));
+    assert!(page.contains("You are subscribed to our newsletter."));
+}
+
+#[tokio::test]
+async fn updating_the_name_is_persisted_and_audited() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.preferences_token().await;
+
+    let response = app
+        .post_preferences(&serde_json::json!({
+            "token": token,
+            "name": "Ursula Le Guin",
+            "email": "ursula_le_guin@gmail.com",
+            "content_format": "html",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula Le Guin");
+    let audit = sqlx::query!("SELECT action, old_value, new_value FROM subscriber_audit_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(audit.action, "name_changed");
+    assert_eq!(audit.old_value.as_deref(), Some("le guin"));
+    assert_eq!(audit.new_value.as_deref(), Some("Ursula Le Guin"));
+}
+
+#[tokio::test]
+async fn invalid_preferences_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.preferences_token().await;
+    let test_cases = vec![
+        ("", "ursula_le_guin@gmail.com", "html", "empty name"),
+        ("Ursula", "definitely-not-an-email", "html", "invalid email"),
+        ("Ursula", "ursula_le_guin@gmail.com", "pdf", "unknown content format"),
+    ];
+
+    for (name, email, content_format, description) in test_cases {
+        let response = app
+            .post_preferences(&serde_json::json!({
+                "token": token,
+                "name": name,
+                "email": email,
+                "content_format": content_format,
+            }))
+            .await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload had an {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn changing_the_email_requires_confirming_the_new_address() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.preferences_token().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_preferences(&serde_json::json!({
+        "token": token,
+        "name": "le guin",
+        "email": "ursula@example.com",
+        "content_format": "html",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // the address doesn't change until the new one is confirmed
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula@example.com");
+    let links = app.get_links(&email_request, "/preferences/confirm-email");
+    // following the link only shows a confirmation form
+    let form = reqwest::get(links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(form.contains("ursula@example.com"));
+    assert!(form.contains(r#"<form action="/preferences/confirm-email" method="post">"#));
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+
+    reqwest::Client::new()
+        .post(format!("{}/preferences/confirm-email", app.address))
+        .form(&links.html.query_pairs().collect::<Vec<_>>())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula@example.com");
+    let actions: Vec<String> =
+        sqlx::query!("SELECT action FROM subscriber_audit_log ORDER BY occurred_at")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.action)
+            .collect();
+    assert_eq!(actions, vec!["email_change_requested", "email_changed"]);
+}
+
+#[tokio::test]
+async fn subscribers_preferring_plain_text_receive_no_html() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.preferences_token().await;
+    app.post_preferences(&serde_json::json!({
+        "token": token,
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "content_format": "text",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body.get("HtmlBody").is_none());
+    assert!(body["TextBody"].as_str().unwrap().starts_with("Newsletter body as plain text"));
+}
+
+#[tokio::test]
+async fn newsletters_link_to_the_preference_center() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.preferences_token().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let links = app.get_links(&email_request, "/preferences");
+    assert_eq!(links.html, links.plain_text);
+    assert_eq!(links.html.query(), Some(format!("token={}", token).as_str()));
+}
+
+#[tokio::test]
+async fn unsubscribed_readers_no_longer_receive_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.preferences_token().await;
+
+    let response = app.post_unsubscribe(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+    let audit = sqlx::query!("SELECT action FROM subscriber_audit_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(audit.action, "unsubscribed");
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+}
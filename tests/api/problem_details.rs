@@ -0,0 +1,116 @@
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn validation_errors_are_problem_details() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "",
+            "content": {"text": "Newsletter body as plain text"}
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(response.headers()["Content-Type"], "application/problem+json");
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["title"], "Bad Request");
+    assert_eq!(problem["status"], 400);
+    assert_eq!(problem["detail"], "The title of the issue is empty.");
+    assert!(uuid::Uuid::parse_str(problem["request_id"].as_str().unwrap()).is_ok());
+}
+
+#[tokio::test]
+async fn extractor_failures_are_problem_details() {
+    let app = spawn_app().await;
+
+    let response = app.post_subscriptions("name=le%20guin".into()).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(response.headers()["Content-Type"], "application/problem+json");
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert!(problem["detail"].as_str().unwrap().contains("email"));
+}
+
+#[tokio::test]
+async fn server_errors_do_not_leak_their_cause() {
+    let app = spawn_app().await;
+    sqlx::query!("ALTER TABLE subscriptions DROP COLUMN email;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 500);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 500);
+    assert!(!problem["detail"].as_str().unwrap().contains("database"));
+}
+
+#[tokio::test]
+async fn browsers_get_an_error_page() {
+    let app = spawn_app().await;
+
+    let body = format!(
+        "name=le%20guin&email=not-an-email&form_token={}",
+        app.form_token(chrono::Utc::now() - chrono::Duration::minutes(1))
+    );
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "text/html,application/xhtml+xml,*/*;q=0.8")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    let page = response.text().await.unwrap();
+    assert!(page.contains("not-an-email is not a valid subscriber email."));
+    assert!(page.contains("Request id: "));
+}
+
+#[tokio::test]
+async fn api_clients_get_problem_details_from_browser_routes() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/subscriptions/confirm", &app.address))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(response.headers()["Content-Type"], "application/problem+json");
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        problem["detail"],
+        "The confirmation link is missing its subscription token."
+    );
+}
+
+#[tokio::test]
+async fn browser_routes_keep_their_own_error_pages() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/subscriptions/confirm", &app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    assert!(response.text().await.unwrap().contains("<h1>"));
+}
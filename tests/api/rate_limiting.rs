@@ -0,0 +1,211 @@
+use crate::helpers::{spawn_app_with, TestApp};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::{
+    RateLimitAlgorithm, RateLimitBackend, RateLimitRuleSettings, Settings,
+};
+
+fn rule(path: &str, limit: u32, form_field: Option<&str>) -> RateLimitRuleSettings {
+    RateLimitRuleSettings {
+        path: path.into(),
+        method: None,
+        algorithm: RateLimitAlgorithm::FixedWindow,
+        limit,
+        period_seconds: 3600,
+        form_field: form_field.map(String::from),
+    }
+}
+
+async fn spawn_app_with_rules(configure: impl FnOnce(&mut Settings)) -> TestApp {
+    let app = spawn_app_with(configure, None).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app
+}
+
+async fn get_confirm(app: &TestApp, forwarded_for: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .get(format!("{}/subscriptions/confirm", &app.address))
+        .query(&[("subscription_token", "mytoken")]);
+    if let Some(forwarded_for) = forwarded_for {
+        request = request.header("X-Forwarded-For", forwarded_for);
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn clients_over_the_limit_are_told_when_to_retry() {
+    let app = spawn_app_with_rules(|c| {
+        c.rate_limits.rules = vec![rule("/subscriptions/confirm", 2, None)];
+    })
+    .await;
+
+    for _ in 0..2 {
+        let response = get_confirm(&app, None).await;
+        assert_ne!(response.status().as_u16(), 429);
+    }
+    let response = get_confirm(&app, None).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: i64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 3600);
+    assert_eq!(response.headers()["Content-Type"], "application/problem+json");
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 429);
+}
+
+#[tokio::test]
+async fn subscriptions_are_limited_for_each_email_address() {
+    let app = spawn_app_with_rules(|c| {
+        c.rate_limits.rules = vec![rule("/subscriptions", 2, Some("email"))];
+    })
+    .await;
+    let body = |email: &str| format!("name=le%20guin&email={}", email);
+
+    for _ in 0..2 {
+        let response = app.post_subscriptions(body("ursula_le_guin%40gmail.com")).await;
+        assert_ne!(response.status().as_u16(), 429);
+    }
+    // the same address, however it is written
+    let response = app.post_subscriptions(body("Ursula_Le_Guin%40gmail.com")).await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    // the route still reads the form of other addresses
+    let response = app.post_subscriptions(body("jk_rowling%40gmail.com")).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions WHERE email = 'jk_rowling@gmail.com'")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_some());
+}
+
+#[tokio::test]
+async fn api_subscriptions_are_limited_for_each_email_address() {
+    let app = spawn_app_with_rules(|c| {
+        c.rate_limits.rules = vec![rule("/api/v1/subscriptions", 1, Some("email"))];
+    })
+    .await;
+    let body = |email: &str| serde_json::json!({"name": "le guin", "email": email});
+
+    let response = app.post_api_subscriptions(&body("ursula_le_guin@gmail.com")).await;
+    assert_ne!(response.status().as_u16(), 429);
+    let response = app.post_api_subscriptions(&body(" Ursula_Le_Guin@gmail.com")).await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    // the route still reads the json of other addresses
+    let response = app.post_api_subscriptions(&body("jk_rowling@gmail.com")).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let saved = sqlx::query!("SELECT email FROM subscriptions WHERE email = 'jk_rowling@gmail.com'")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_some());
+}
+
+#[tokio::test]
+async fn the_forwarded_client_address_is_used_behind_a_trusted_proxy() {
+    let app = spawn_app_with_rules(|c| {
+        c.rate_limits.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+        c.rate_limits.rules = vec![rule("/subscriptions/confirm", 1, None)];
+    })
+    .await;
+
+    let first = get_confirm(&app, Some("198.51.100.1")).await;
+    let other_client = get_confirm(&app, Some("198.51.100.2")).await;
+    // a forged address on the left is ignored
+    let same_client = get_confirm(&app, Some("192.0.2.99, 198.51.100.1")).await;
+
+    assert_ne!(first.status().as_u16(), 429);
+    assert_ne!(other_client.status().as_u16(), 429);
+    assert_eq!(same_client.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn the_forwarded_client_address_is_ignored_without_a_trusted_proxy() {
+    let app = spawn_app_with_rules(|c| {
+        c.rate_limits.rules = vec![rule("/subscriptions/confirm", 1, None)];
+    })
+    .await;
+
+    let first = get_confirm(&app, Some("198.51.100.1")).await;
+    let second = get_confirm(&app, Some("198.51.100.2")).await;
+
+    assert_ne!(first.status().as_u16(), 429);
+    assert_eq!(second.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn the_postgres_backend_keeps_its_counters_in_the_database() {
+    let app = spawn_app_with_rules(|c| {
+        c.rate_limits.backend = RateLimitBackend::Postgres;
+        c.rate_limits.rules = vec![RateLimitRuleSettings {
+            algorithm: RateLimitAlgorithm::Gcra,
+            ..rule("/subscriptions/confirm", 1, None)
+        }];
+    })
+    .await;
+
+    let first = get_confirm(&app, None).await;
+    let second = get_confirm(&app, None).await;
+
+    assert_ne!(first.status().as_u16(), 429);
+    assert_eq!(second.status().as_u16(), 429);
+    let counters = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM rate_limit_counters"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(counters.count, 1);
+}
+
+#[tokio::test]
+async fn a_limited_client_does_not_get_counters_for_other_field_values() {
+    let app = spawn_app_with_rules(|c| {
+        c.rate_limits.backend = RateLimitBackend::Postgres;
+        c.rate_limits.rules = vec![
+            rule("/subscriptions", 10, Some("email")),
+            rule("/subscriptions", 1, None),
+        ];
+    })
+    .await;
+    let body = |email: &str| format!("name=le%20guin&email={}", email);
+
+    let first = app.post_subscriptions(body("ursula_le_guin%40gmail.com")).await;
+    for email in ["jk_rowling%40gmail.com", "tolkien%40gmail.com"] {
+        let response = app.post_subscriptions(body(email)).await;
+        assert_eq!(response.status().as_u16(), 429);
+    }
+
+    assert_ne!(first.status().as_u16(), 429);
+    let keys: Vec<String> = sqlx::query!("SELECT key FROM rate_limit_counters")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.key)
+        .collect();
+    // one for the client ip, one for the first address which isn't stored as it is
+    assert_eq!(keys.len(), 2);
+    assert!(keys.iter().all(|key| !key.contains("gmail")));
+}
+
+#[tokio::test]
+async fn routes_without_a_rule_are_not_limited() {
+    let app = spawn_app_with_rules(|c| {
+        c.rate_limits.rules = vec![rule("/subscriptions/confirm", 1, None)];
+    })
+    .await;
+
+    for _ in 0..3 {
+        let response = reqwest::get(format!("{}/health_check", &app.address))
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::helpers::{assert_requires_admin, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn admins_can_tag_and_untag_subscribers() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "a@example.com").await;
+
+    let response = tag(&app, subscriber_id, "beta").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(tags(&app, subscriber_id).await, vec!["beta"]);
+
+    let response = reqwest::Client::new()
+        .delete(format!("{}/admin/subscribers/{}/tags/beta", app.address, subscriber_id))
+        .basic_auth(&app.admin_username, Some(&app.admin_password))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(tags(&app, subscriber_id).await.is_empty());
+
+    let actions: Vec<String> =
+        sqlx::query!("SELECT action FROM subscriber_audit_log ORDER BY occurred_at")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.action)
+            .collect();
+    assert_eq!(actions, vec!["tag_added", "tag_removed"]);
+}
+
+#[tokio::test]
+async fn changing_tags_and_attributes_requires_admin_credentials() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "a@example.com").await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/admin/subscribers/{}", app.address, subscriber_id);
+
+    assert_requires_admin(vec![
+        client
+            .post(format!("{}/tags", url))
+            .json(&serde_json::json!({ "tag": "beta" })),
+        client.delete(format!("{}/tags/beta", url)),
+        client
+            .patch(format!("{}/attributes", url))
+            .json(&serde_json::json!({ "plan": "pro" })),
+    ])
+    .await;
+    assert!(tags(&app, subscriber_id).await.is_empty());
+}
+
+#[tokio::test]
+async fn tagging_rejects_invalid_tags_and_unknown_subscribers() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "a@example.com").await;
+
+    let response = tag(&app, subscriber_id, "Not a tag").await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = tag(&app, Uuid::new_v4(), "beta").await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn attributes_are_merged_and_null_values_removed() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "a@example.com").await;
+
+    set_attributes(&app, subscriber_id, serde_json::json!({"plan": "pro", "country": "fr"}))
+        .await;
+    let response =
+        set_attributes(&app, subscriber_id, serde_json::json!({"plan": null, "seats": 3})).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let attributes: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(attributes, serde_json::json!({"country": "fr", "seats": 3}));
+}
+
+#[tokio::test]
+async fn invalid_attributes_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "a@example.com").await;
+    let test_cases = vec![
+        (serde_json::json!(["plan"]), "not an object"),
+        (serde_json::json!({"Plan": "pro"}), "invalid key"),
+        (serde_json::json!({"plan": {"name": "pro"}}), "nested value"),
+    ];
+
+    for (body, description) in test_cases {
+        let response = set_attributes(&app, subscriber_id, body).await;
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload was {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn newsletters_are_only_delivered_to_the_matching_segment() {
+    let app = spawn_app().await;
+    let beta = create_confirmed_subscriber(&app, "beta@example.com").await;
+    let pro = create_confirmed_subscriber(&app, "pro@example.com").await;
+    let old = create_confirmed_subscriber(&app, "old@example.com").await;
+    tag(&app, beta, "beta").await.error_for_status().unwrap();
+    set_attributes(&app, pro, serde_json::json!({"plan": "pro"}))
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!(
+        "UPDATE subscriptions SET subscribed_at = '2020-06-01T12:00:00Z' WHERE id = $1",
+        old
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let test_cases = vec![
+        ("tag:beta", vec!["beta@example.com"]),
+        ("attr.plan:pro", vec!["pro@example.com"]),
+        ("tag:beta OR attr.plan:pro", vec!["beta@example.com", "pro@example.com"]),
+        ("NOT attr.plan:pro", vec!["beta@example.com", "old@example.com"]),
+        ("joined_before:2021-01-01", vec!["old@example.com"]),
+        ("joined_after:2020-06-01 AND NOT tag:beta", vec!["pro@example.com"]),
+    ];
+    for (segment, expected_recipients) in test_cases {
+        let recipients = publish_to_segment(&app, segment).await;
+        assert_eq!(recipients, expected_recipients, "Unexpected recipients for `{}`", segment);
+    }
+}
+
+#[tokio::test]
+async fn publishing_with_an_invalid_segment_returns_a_400() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "segment": "tag:beta AND",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+async fn publish_to_segment(app: &TestApp, segment: &str) -> Vec<String> {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let already_received = app.email_server.received_requests().await.unwrap().len();
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "segment": segment,
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    let mut recipients: Vec<String> = app.email_server.received_requests().await.unwrap()
+        [already_received..]
+        .iter()
+        .map(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            body["To"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    recipients.sort();
+    recipients
+}
+
+async fn create_confirmed_subscriber(app: &TestApp, email: &str) -> Uuid {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = format!("name=le%20guin&email={}", email.replace('@', "%40"));
+    app.post_subscriptions(body).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn tag(app: &TestApp, subscriber_id: Uuid, tag: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/{}/tags", app.address, subscriber_id))
+        .basic_auth(&app.admin_username, Some(&app.admin_password))
+        .json(&serde_json::json!({ "tag": tag }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn set_attributes(
+    app: &TestApp,
+    subscriber_id: Uuid,
+    attributes: serde_json::Value,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .patch(format!("{}/admin/subscribers/{}/attributes", app.address, subscriber_id))
+        .basic_auth(&app.admin_username, Some(&app.admin_password))
+        .json(&attributes)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn tags(app: &TestApp, subscriber_id: Uuid) -> Vec<String> {
+    sqlx::query!(
+        "SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag",
+        subscriber_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.tag)
+    .collect()
+}
@@ -0,0 +1,184 @@
+use crate::helpers::{spawn_app, TestApp};
+use chrono::{Duration, Utc};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn newsletter_request_body(sample_percent: u32) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "tracking": true,
+        "subject_test": {
+            "subjects": ["Subject A", "Subject B"],
+            "sample_percent": sample_percent,
+            "wait_minutes": 60,
+            "metric": "open"
+        }
+    })
+}
+
+async fn create_confirmed_subscriber(app: &TestApp, email: &str) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = format!("name=le%20guin&email={}", email.replace('@', "%40"));
+    app.post_subscriptions(body).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+// the emails received by the email server since `skip` requests, as JSON bodies
+async fn sent_emails(app: &TestApp, skip: usize) -> Vec<serde_json::Value> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .skip(skip)
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .collect()
+}
+
+async fn deliveries(app: &TestApp) -> Vec<(String, Option<i32>)> {
+    sqlx::query!("SELECT status, variant FROM newsletter_deliveries ORDER BY status, variant")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.status, r.variant))
+        .collect()
+}
+
+#[tokio::test]
+async fn the_winning_subject_is_sent_to_the_rest_of_the_list() {
+    let app = spawn_app().await;
+    for i in 0..10 {
+        create_confirmed_subscriber(&app, &format!("reader{}@example.com", i)).await;
+    }
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let before_publish = app.email_server.received_requests().await.unwrap().len();
+
+    let response = app.post_newsletters(newsletter_request_body(20)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let published: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(published["sent"], 2);
+    assert_eq!(published["pending"], 8);
+    let sample = sent_emails(&app, before_publish).await;
+    let mut subjects: Vec<&str> = sample.iter().map(|e| e["Subject"].as_str().unwrap()).collect();
+    subjects.sort();
+    assert_eq!(subjects, vec!["Subject A", "Subject B"]);
+
+    // the recipient of the second subject opens the issue
+    let email_b = sample.iter().find(|e| e["Subject"] == "Subject B").unwrap();
+    let pixel = linkify::LinkFinder::new()
+        .links(email_b["HtmlBody"].as_str().unwrap())
+        .find(|l| l.as_str().contains("/t/o/"))
+        .unwrap();
+    let mut pixel = reqwest::Url::parse(pixel.as_str()).unwrap();
+    pixel.set_port(Some(app.port)).unwrap();
+    reqwest::get(pixel).await.unwrap().error_for_status().unwrap();
+
+    // nothing happens until the end of the test
+    app.dispatch_pending_deliveries(Utc::now()).await;
+    assert_eq!(sent_emails(&app, before_publish).await.len(), 2);
+
+    app.dispatch_pending_deliveries(Utc::now() + Duration::minutes(61)).await;
+
+    let remainder = sent_emails(&app, before_publish + 2).await;
+    assert_eq!(remainder.len(), 8);
+    assert!(remainder.iter().all(|e| e["Subject"] == "Subject B"));
+    let winner = sqlx::query!("SELECT winner FROM subject_tests")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .winner;
+    assert_eq!(winner, Some(1));
+    let deliveries = deliveries(&app).await;
+    assert_eq!(deliveries.len(), 10);
+    assert_eq!(
+        deliveries.iter().filter(|d| *d == &("sent".to_string(), Some(1))).count(),
+        9
+    );
+}
+
+#[tokio::test]
+async fn subscribers_who_left_before_the_decision_are_skipped() {
+    let app = spawn_app().await;
+    for i in 0..3 {
+        create_confirmed_subscriber(&app, &format!("reader{}@example.com", i)).await;
+    }
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_newsletters(newsletter_request_body(1)).await;
+    let published: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(published["pending"], 1);
+
+    let pending = sqlx::query!(
+        "SELECT s.preferences_token FROM newsletter_deliveries d \
+        JOIN subscriptions s ON s.id = d.subscriber_id WHERE d.status = 'pending'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    app.post_unsubscribe(&pending.preferences_token)
+        .await
+        .error_for_status()
+        .unwrap();
+    let before_decision = app.email_server.received_requests().await.unwrap().len();
+
+    app.dispatch_pending_deliveries(Utc::now() + Duration::minutes(61)).await;
+
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), before_decision);
+    let statuses: Vec<String> = deliveries(&app).await.into_iter().map(|d| d.0).collect();
+    assert_eq!(statuses, vec!["sent", "sent", "skipped"]);
+}
+
+#[tokio::test]
+async fn invalid_subject_tests_are_rejected() {
+    let app = spawn_app().await;
+    let mut without_tracking = newsletter_request_body(20);
+    without_tracking["tracking"] = false.into();
+    let mut single_subject = newsletter_request_body(20);
+    single_subject["subject_test"]["subjects"] = serde_json::json!(["Subject A"]);
+    let mut unknown_metric = newsletter_request_body(20);
+    unknown_metric["subject_test"]["metric"] = "reply".into();
+    let mut no_wait = newsletter_request_body(20);
+    no_wait["subject_test"]["wait_minutes"] = 0.into();
+    let mut invalid_subject = newsletter_request_body(20);
+    invalid_subject["subject_test"]["subjects"] = serde_json::json!(["A", "{{ unknown }}"]);
+    let test_cases = vec![
+        (without_tracking, "tracking disabled"),
+        (single_subject, "single subject"),
+        (newsletter_request_body(0), "empty sample"),
+        (newsletter_request_body(100), "sample of the whole list"),
+        (unknown_metric, "unknown metric"),
+        (no_wait, "no wait"),
+        (invalid_subject, "subject with an unknown merge field"),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_newsletters(body).await;
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had a {}.",
+            description
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{create_confirmed_subscriber, spawn_app, spawn_app_with};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -131,4 +131,130 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     let response = app.post_subscriptions(body.into()).await;
 
     assert_eq!(response.status().as_u16(), 500)
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn subscribe_normalizes_the_email_and_keeps_what_was_typed() {
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=%20Ursula_Le_Guin%40Gmail.COM%20";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, email_display FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "Ursula_Le_Guin@gmail.com");
+    assert_eq!(saved.email_display, "Ursula_Le_Guin@Gmail.COM");
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["To"], "Ursula_Le_Guin@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribing_again_whatever_the_case_of_the_email_resends_the_confirmation() {
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40GMAIL.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.count, 1);
+}
+
+#[tokio::test]
+async fn subscribing_again_once_confirmed_looks_like_a_new_signup() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+// every byte percent encoded, for the raw form bodies
+fn url_encode(s: &str) -> String {
+    s.bytes().map(|b| format!("%{:02X}", b)).collect()
+}
+
+#[tokio::test]
+async fn subscribe_stores_both_forms_of_an_internationalized_address() {
+    let test_app = spawn_app_with(|c| c.email_client.smtputf8 = true, None).await;
+    let body = format!("name=le%20guin&email={}", url_encode("用户@例え.JP"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app.post_subscriptions(body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, email_unicode, email_display FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "用户@xn--r8jz45g.jp");
+    // decoded from the ASCII form, unlike the address as it was typed
+    assert_eq!(saved.email_unicode, "用户@例え.jp");
+    assert_eq!(saved.email_display, "用户@例え.JP");
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["To"], "用户@xn--r8jz45g.jp");
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_utf8_local_parts_without_smtputf8_support() {
+    let test_app = spawn_app().await;
+    let body = format!("name=le%20guin&email={}", url_encode("ursula.lé.guin@gmail.com"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app.post_subscriptions(body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,238 @@
+use crate::helpers::{assert_requires_admin, create_confirmed_subscriber, spawn_app};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn suppressions_can_be_created_read_updated_and_deleted() {
+    let app = spawn_app().await;
+    let client = app.admin_client();
+    let url = format!("{}/admin/suppressions/someone@example.com", &app.address);
+
+    let response = app
+        .post_suppressions(serde_json::json!({
+            "email": "Someone@Example.com",
+            "reason": "Asked never to be contacted"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["email"], "someone@example.com");
+    assert_eq!(created["source"], "admin");
+
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let fetched: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(fetched["reason"], "Asked never to be contacted");
+
+    let response = client
+        .put(&url)
+        .json(&serde_json::json!({"reason": "Legal request"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let updated: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(updated["reason"], "Legal request");
+
+    let listed: Vec<serde_json::Value> = client
+        .get(format!("{}/admin/suppressions", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed.len(), 1);
+
+    let response = client.delete(&url).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+    let response = client.delete(&url).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn suppressions_require_admin_credentials() {
+    let app = spawn_app().await;
+    app.post_suppressions(serde_json::json!({
+        "email": "someone@example.com",
+        "reason": "Complained"
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    let client = reqwest::Client::new();
+    let url = format!("{}/admin/suppressions", &app.address);
+    let address_url = format!("{}/someone@example.com", url);
+
+    assert_requires_admin(vec![
+        client.get(&url),
+        client
+            .post(&url)
+            .json(&serde_json::json!({"email": "other@example.com", "reason": "Complained"})),
+        client
+            .post(format!("{}/import", url))
+            .header("Content-Type", "text/csv")
+            .body("email,reason\nother@example.com,Complained\n"),
+        client.get(&address_url),
+        client
+            .put(&address_url)
+            .json(&serde_json::json!({"reason": "Legal request"})),
+        client.delete(&address_url),
+    ])
+    .await;
+    let suppressed = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM suppressions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(suppressed.count, 1);
+}
+
+#[tokio::test]
+async fn create_suppression_returns_a_400_for_invalid_data() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"email": "not-an-email", "reason": "Complained"}),
+            "invalid email",
+        ),
+        (
+            serde_json::json!({"email": "someone@example.com", "reason": " "}),
+            "empty reason",
+        ),
+        (serde_json::json!({"email": "someone@example.com"}), "missing reason"),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = app.post_suppressions(invalid_body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn suppressing_an_address_twice_returns_a_409() {
+    let app = spawn_app().await;
+    let body = serde_json::json!({"email": "someone@example.com", "reason": "Complained"});
+
+    app.post_suppressions(body.clone()).await.error_for_status().unwrap();
+    let response = app.post_suppressions(body).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn a_csv_file_can_be_imported() {
+    let app = spawn_app().await;
+    app.post_suppressions(serde_json::json!({
+        "email": "known@example.com",
+        "reason": "Complained"
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let response = app
+        .import_suppressions(
+            "email,reason\n\
+            first@example.com,Complained by phone\n\
+            second@example.com,\n\
+            KNOWN@example.com,Duplicate\n",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let outcome: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(outcome["imported"], 2);
+    assert_eq!(outcome["skipped"], 1);
+    let saved = sqlx::query!("SELECT email, reason, source FROM suppressions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 3);
+    assert_eq!(saved[0].email, "first@example.com");
+    assert_eq!(saved[0].source, "import");
+    assert_eq!(saved[2].email, "second@example.com");
+    assert_eq!(saved[2].reason, "imported");
+}
+
+#[tokio::test]
+async fn an_invalid_csv_file_is_rejected_as_a_whole() {
+    let app = spawn_app().await;
+
+    let response = app
+        .import_suppressions(
+            "email,reason\n\
+            first@example.com,Complained\n\
+            not-an-email,Complained\n",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let saved = sqlx::query!("SELECT email FROM suppressions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn suppressed_addresses_do_not_receive_confirmation_emails() {
+    let app = spawn_app().await;
+    app.post_suppressions(serde_json::json!({
+        "email": "ursula_le_guin@gmail.com",
+        "reason": "Asked never to be contacted"
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn suppressed_subscribers_do_not_receive_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_suppressions(serde_json::json!({
+        "email": "Ursula_Le_Guin@gmail.com",
+        "reason": "Asked never to be contacted"
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,196 @@
+use crate::helpers::{assert_requires_admin, spawn_app, TestApp};
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn newsletter_request_body(throttle: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "throttle": throttle
+    })
+}
+
+async fn create_confirmed_subscriber(app: &TestApp, email: &str) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = format!("name=le%20guin&email={}", email.replace('@', "%40"));
+    app.post_subscriptions(body).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn sent_emails(app: &TestApp) -> usize {
+    app.email_server.received_requests().await.unwrap().len()
+}
+
+async fn get_progress(app: &TestApp, issue_id: &str) -> serde_json::Value {
+    let response = app
+        .admin_client()
+        .get(format!("{}/admin/newsletters/{}/progress", &app.address, issue_id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+fn utc(s: &str) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap())
+}
+
+#[tokio::test]
+async fn throttled_issues_are_sent_by_the_worker_within_the_hourly_cap() {
+    let app = spawn_app().await;
+    for i in 0..5 {
+        create_confirmed_subscriber(&app, &format!("reader{}@example.com", i)).await;
+    }
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let before_publish = sent_emails(&app).await;
+
+    let response = app
+        .post_newsletters(newsletter_request_body(serde_json::json!({"max_per_hour": 2})))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let published: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(published["sent"], 0);
+    assert_eq!(published["pending"], 5);
+    assert_eq!(sent_emails(&app).await, before_publish);
+
+    let now = Utc::now();
+    app.dispatch_pending_deliveries(now).await;
+    assert_eq!(sent_emails(&app).await, before_publish + 2);
+    // the cap is reached until the first deliveries are an hour old
+    app.dispatch_pending_deliveries(now + Duration::minutes(30)).await;
+    assert_eq!(sent_emails(&app).await, before_publish + 2);
+    app.dispatch_pending_deliveries(now + Duration::minutes(61)).await;
+    assert_eq!(sent_emails(&app).await, before_publish + 4);
+    app.dispatch_pending_deliveries(now + Duration::minutes(122)).await;
+    assert_eq!(sent_emails(&app).await, before_publish + 5);
+}
+
+#[tokio::test]
+async fn throttled_issues_are_only_sent_inside_their_window() {
+    let app = spawn_app().await;
+    for i in 0..3 {
+        create_confirmed_subscriber(&app, &format!("reader{}@example.com", i)).await;
+    }
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let before_publish = sent_emails(&app).await;
+
+    let response = app
+        .post_newsletters(newsletter_request_body(serde_json::json!({
+            "window": {"start": "09:00", "end": "17:00", "timezone": "Europe/Paris"}
+        })))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // 19:30 in Paris
+    app.dispatch_pending_deliveries(utc("2030-01-07 18:30")).await;
+    assert_eq!(sent_emails(&app).await, before_publish);
+    // 08:30 in Paris, the window isn't open yet
+    app.dispatch_pending_deliveries(utc("2030-01-08 07:30")).await;
+    assert_eq!(sent_emails(&app).await, before_publish);
+    // 09:30 in Paris
+    app.dispatch_pending_deliveries(utc("2030-01-08 08:30")).await;
+    assert_eq!(sent_emails(&app).await, before_publish + 3);
+}
+
+#[tokio::test]
+async fn the_progress_of_a_throttled_issue_is_reported() {
+    let app = spawn_app().await;
+    for i in 0..5 {
+        create_confirmed_subscriber(&app, &format!("reader{}@example.com", i)).await;
+    }
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(newsletter_request_body(serde_json::json!({"max_per_hour": 2})))
+        .await;
+    let published: serde_json::Value = response.json().await.unwrap();
+    let issue_id = published["issue_id"].as_str().unwrap();
+
+    let before_dispatch = Utc::now();
+    app.dispatch_pending_deliveries(before_dispatch).await;
+
+    let progress = get_progress(&app, issue_id).await;
+    assert_eq!(progress["total"], 5);
+    assert_eq!(progress["sent"], 2);
+    assert_eq!(progress["pending"], 3);
+    assert_eq!(progress["throttle"]["max_per_hour"], 2);
+    // three more deliveries at two per hour
+    let estimated_completion: DateTime<Utc> =
+        serde_json::from_value(progress["estimated_completion"].clone()).unwrap();
+    assert!(estimated_completion >= before_dispatch + Duration::minutes(90));
+    assert!(estimated_completion <= Utc::now() + Duration::minutes(90));
+}
+
+#[tokio::test]
+async fn the_progress_of_an_unknown_issue_is_a_404() {
+    let app = spawn_app().await;
+
+    let url = format!("{}/admin/newsletters/{}/progress", &app.address, uuid::Uuid::new_v4());
+
+    let response = app.admin_client().get(&url).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_progress_requires_admin_credentials() {
+    let app = spawn_app().await;
+    let url = format!("{}/admin/newsletters/{}/progress", &app.address, uuid::Uuid::new_v4());
+
+    assert_requires_admin(vec![reqwest::Client::new().get(&url)]).await;
+}
+
+#[tokio::test]
+async fn invalid_throttles_are_rejected() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (serde_json::json!({}), "neither a cap nor a window"),
+        (serde_json::json!({"max_per_hour": 0}), "a zero cap"),
+        (
+            serde_json::json!({"window": {"start": "9am", "end": "17:00", "timezone": "UTC"}}),
+            "a malformed time",
+        ),
+        (
+            serde_json::json!({"window": {"start": "09:00", "end": "09:00", "timezone": "UTC"}}),
+            "an empty window",
+        ),
+        (
+            serde_json::json!({"window": {"start": "09:00", "end": "17:00", "timezone": "Mars/Olympus"}}),
+            "an unknown timezone",
+        ),
+    ];
+    for (throttle, description) in test_cases {
+        let response = app.post_newsletters(newsletter_request_body(throttle)).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject a throttle with {}.",
+            description
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::helpers::{assert_requires_admin, create_confirmed_subscriber, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn newsletter_request_body(tracking: bool) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Read https://example.com/article",
+            "html": "<p><a href=\"https://example.com/article\">Read</a></p>",
+        },
+        "tracking": tracking
+    })
+}
+
+// publish an issue to a single confirmed subscriber, returning its id and HTML body
+async fn publish(app: &TestApp, body: serde_json::Value) -> (String, String) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app.post_newsletters(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let published: serde_json::Value = response.json().await.unwrap();
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    (
+        published["issue_id"].as_str().unwrap().to_string(),
+        email["HtmlBody"].as_str().unwrap().to_string(),
+    )
+}
+
+// the tracking link to `link_path`, pointing to the test server
+fn get_tracking_link(app: &TestApp, html: &str, link_path: &str) -> reqwest::Url {
+    let links: Vec<_> = linkify::LinkFinder::new()
+        .links(html)
+        .filter(|l| l.as_str().contains(link_path))
+        .collect();
+    assert_eq!(links.len(), 1);
+    let mut link = reqwest::Url::parse(links[0].as_str()).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+fn no_redirect_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn opens_and_clicks_are_tracked_and_aggregated() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (issue_id, html) = publish(&app, newsletter_request_body(true)).await;
+    assert!(!html.contains("href=\"https://example.com/article\""));
+
+    let click_link = get_tracking_link(&app, &html, "/t/c/");
+    for _ in 0..2 {
+        let response = no_redirect_client().get(click_link.clone()).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 302);
+        assert_eq!(response.headers()["Location"], "https://example.com/article");
+    }
+    let open_link = get_tracking_link(&app, &html, "/t/o/");
+    let response = reqwest::get(open_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+
+    let stats: serde_json::Value = app
+        .admin_client()
+        .get(format!("{}/admin/newsletters/{}/stats", &app.address, issue_id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["opens"], 1);
+    assert_eq!(stats["unique_opens"], 1);
+    assert_eq!(stats["clicks"], 2);
+    assert_eq!(stats["unique_clicks"], 1);
+    assert_eq!(stats["links"][0]["url"], "https://example.com/article");
+    assert_eq!(stats["links"][0]["clicks"], 2);
+}
+
+#[tokio::test]
+async fn tampered_tracking_links_do_not_redirect() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (_, html) = publish(&app, newsletter_request_body(true)).await;
+    let mut click_link = get_tracking_link(&app, &html, "/t/c/");
+    let token = click_link.path().trim_start_matches("/t/c/").to_string();
+    let (_, tag) = token.split_once('.').unwrap();
+    let forged_payload = base64::encode_config(
+        serde_json::json!({
+            "kind": "click",
+            "issue_id": uuid::Uuid::new_v4(),
+            "subscriber_id": uuid::Uuid::new_v4(),
+            "url": "https://evil.example.com"
+        })
+        .to_string(),
+        base64::URL_SAFE_NO_PAD,
+    );
+    click_link.set_path(&format!("/t/c/{}.{}", forged_payload, tag));
+
+    let response = no_redirect_client().get(click_link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+    assert!(response.headers().get("Location").is_none());
+}
+
+#[tokio::test]
+async fn issues_are_not_tracked_unless_requested() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let (_, html) = publish(&app, newsletter_request_body(false)).await;
+
+    assert!(html.contains("href=\"https://example.com/article\""));
+    assert!(!html.contains("/t/o/"));
+}
+
+#[tokio::test]
+async fn subscribers_who_opted_out_are_not_tracked() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.preferences_token().await;
+    app.post_preferences(&serde_json::json!({
+        "token": token,
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "content_format": "html",
+        "update_tracking": "true"
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let (_, html) = publish(&app, newsletter_request_body(true)).await;
+
+    assert!(html.contains("href=\"https://example.com/article\""));
+    assert!(!html.contains("/t/o/"));
+}
+
+#[tokio::test]
+async fn stats_of_an_unknown_issue_return_a_404() {
+    let app = spawn_app().await;
+
+    let url = format!("{}/admin/newsletters/{}/stats", &app.address, uuid::Uuid::new_v4());
+
+    let response = app.admin_client().get(&url).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn stats_require_admin_credentials() {
+    let app = spawn_app().await;
+    let url = format!("{}/admin/newsletters/{}/stats", &app.address, uuid::Uuid::new_v4());
+
+    assert_requires_admin(vec![reqwest::Client::new().get(&url)]).await;
+}
@@ -0,0 +1,229 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+fn hard_bounce() -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "ID": 4323372036854775807_u64,
+        "Type": "HardBounce",
+        "TypeCode": 1,
+        "Email": "ursula_le_guin@gmail.com",
+        "BouncedAt": "2023-02-05T12:00:00Z",
+        "Description": "The server was unable to deliver your message (ex: unknown user, mailbox not found)."
+    })
+}
+
+#[tokio::test]
+async fn requests_without_valid_credentials_are_rejected() {
+    let app = spawn_app().await;
+
+    let test_cases = vec![
+        (None, "missing credentials"),
+        (Some(("postmark", "wrong-password")), "wrong password"),
+        (Some(("someone", "my-webhook-secret")), "wrong username"),
+    ];
+    for (credentials, description) in test_cases {
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/webhooks/postmark", &app.address))
+            .json(&hard_bounce());
+        if let Some((username, password)) = credentials {
+            request = request.basic_auth(username, Some(password));
+        }
+        let response = request.send().await.expect("Failed to execute request.");
+
+        assert_eq!(
+            401,
+            response.status().as_u16(),
+            "The API did not fail with 401 Unauthorized with {}.",
+            description
+        );
+        assert_eq!(
+            r#"Basic realm="webhooks""#,
+            response.headers()["WWW-Authenticate"]
+        );
+    }
+    let events = sqlx::query!("SELECT id FROM email_events")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(events.is_empty());
+}
+
+#[tokio::test]
+async fn a_hard_bounce_moves_the_subscriber_to_bounced() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_postmark_webhook(&hard_bounce()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "bounced");
+    let event = sqlx::query!("SELECT record_type, event_type, subscriber_id FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.record_type, "Bounce");
+    assert_eq!(event.event_type.as_deref(), Some("HardBounce"));
+    assert!(event.subscriber_id.is_some());
+    let audit = sqlx::query!("SELECT action, old_value, new_value FROM subscriber_audit_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(audit.action, "bounced");
+    assert_eq!(audit.old_value.as_deref(), Some("confirmed"));
+    assert_eq!(audit.new_value.as_deref(), Some("bounced"));
+}
+
+#[tokio::test]
+async fn a_soft_bounce_is_recorded_without_changing_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let mut soft_bounce = hard_bounce();
+    soft_bounce["Type"] = "SoftBounce".into();
+    soft_bounce["TypeCode"] = 4096.into();
+
+    let response = app.post_postmark_webhook(&soft_bounce).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    let event = sqlx::query!("SELECT event_type FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.event_type.as_deref(), Some("SoftBounce"));
+}
+
+#[tokio::test]
+async fn a_spam_complaint_moves_the_subscriber_to_complained() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "Type": "SpamComplaint",
+            "Email": "ursula_le_guin@gmail.com",
+            "BouncedAt": "2023-02-05T12:00:00Z"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "complained");
+    let suppression = sqlx::query!("SELECT email, reason, source FROM suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(suppression.email, "ursula_le_guin@gmail.com");
+    assert_eq!(suppression.reason, "complained");
+    assert_eq!(suppression.source, "postmark");
+}
+
+#[tokio::test]
+async fn a_suppressing_subscription_change_updates_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "SubscriptionChange",
+            "Recipient": "ursula_le_guin@gmail.com",
+            "SuppressSending": true,
+            "SuppressionReason": "ManualSuppression",
+            "ChangedAt": "2023-02-05T12:00:00Z"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn events_for_unknown_addresses_are_recorded() {
+    let app = spawn_app().await;
+    let mut bounce = hard_bounce();
+    bounce["Email"] = "nobody@example.com".into();
+
+    let response = app.post_postmark_webhook(&bounce).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let event = sqlx::query!("SELECT email, subscriber_id FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.email, "nobody@example.com");
+    assert!(event.subscriber_id.is_none());
+}
+
+#[tokio::test]
+async fn other_record_types_are_accepted_and_ignored() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Delivery",
+            "Recipient": "ursula_le_guin@gmail.com",
+            "DeliveredAt": "2023-02-05T12:00:00Z"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn malformed_records_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "HardBounce"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn bounced_subscribers_do_not_receive_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_postmark_webhook(&hard_bounce())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}